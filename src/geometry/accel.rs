@@ -0,0 +1,271 @@
+use std::fmt;
+use std::mem;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::config::Float;
+use super::mat4::Mat4;
+use super::ray::{Ray, Hit};
+use super::primitive_traits::{Primitive, BBox, BoundedPrimitive};
+use super::bvh::BvhNode;
+use super::instance::Instance;
+
+/// Statistics of a built BVH.
+#[derive(Clone, Debug)]
+pub struct BvhStats
+{
+    pub node_count: usize,
+    pub leaf_count: usize,
+    pub depth: usize,
+    /// Approximate memory used by the nodes of the tree, not counting
+    /// the primitives in the leaves.
+    pub bytes: usize,
+    pub build_time: Duration,
+}
+
+impl BvhStats
+{
+    fn new(tree: &BvhNode, build_time: Duration) -> Self
+    {
+        let (node_count, leaf_count, depth) = tree.shape();
+        // Every node lives in its own `Arc`, which also holds two
+        // reference counts. Every leaf holds one more `Arc` to the
+        // primitive.
+        let node_size = mem::size_of::<BvhNode>() + 2 * mem::size_of::<usize>();
+        Self {
+            node_count,
+            leaf_count,
+            depth,
+            bytes: node_count * node_size,
+            build_time,
+        }
+    }
+}
+
+impl fmt::Display for BvhStats
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result
+    {
+        write!(f, "{} nodes, {} leaves, depth {}, {:.1} KiB, built in {:.3} ms",
+               self.node_count, self.leaf_count, self.depth,
+               self.bytes as f64 / 1024.0,
+               self.build_time.as_secs_f64() * 1000.0)
+    }
+}
+
+/// Build a BVH over `prims` and time it.
+fn buildTimed(prims: &mut [Arc<dyn BoundedPrimitive + Send + Sync>]) ->
+    (BvhNode, BvhStats)
+{
+    let start = Instant::now();
+    let tree = BvhNode::new(prims);
+    let stats = BvhStats::new(&tree, start.elapsed());
+    (tree, stats)
+}
+
+/// A bottom-level acceleration structure: a BVH over the primitives
+/// of one object (e.g. the triangles of a mesh), in the object’s own
+/// space. It is built once, and placed into the world any number of
+/// times by the `TwoLevelBvh`.
+pub struct Blas
+{
+    tree: BvhNode,
+    stats: BvhStats,
+}
+
+impl Blas
+{
+    /// Build from the primitives of an object. Panics if `prims` is
+    /// empty.
+    pub fn new(mut prims: Vec<Arc<dyn BoundedPrimitive + Send + Sync>>) -> Self
+    {
+        if prims.is_empty()
+        {
+            panic!("Cannot build BLAS without primitives");
+        }
+        let (tree, stats) = buildTimed(&mut prims[..]);
+        Self { tree, stats }
+    }
+
+    pub fn stats(&self) -> &BvhStats
+    {
+        &self.stats
+    }
+}
+
+impl Primitive for Blas
+{
+    fn intersect(&self, r: &Ray, t_min: Float, t_max: Float) -> Option<Hit>
+    {
+        self.tree.intersect(r, t_min, t_max)
+    }
+}
+
+impl BoundedPrimitive for Blas
+{
+    fn bbox(&self) -> BBox
+    {
+        self.tree.bbox()
+    }
+}
+
+/// A two-level acceleration structure. The bottom level is a
+/// `Blas` per unique object. The top level is a BVH over
+/// `Instance`s of those objects. Moving an instance only rebuilds
+/// the top level, which is cheap because there are usually far
+/// fewer instances than primitives.
+pub struct TwoLevelBvh
+{
+    blases: Vec<Arc<Blas>>,
+    /// The instances, and the index of the BLAS each of them uses.
+    instances: Vec<(Arc<Instance>, usize)>,
+    tlas: Option<BvhNode>,
+    tlas_stats: Option<BvhStats>,
+}
+
+impl TwoLevelBvh
+{
+    pub fn new() -> Self
+    {
+        Self {
+            blases: Vec::new(),
+            instances: Vec::new(),
+            tlas: None,
+            tlas_stats: None,
+        }
+    }
+
+    /// Build a BLAS from the primitives of an object, and return its
+    /// index.
+    pub fn addObject(&mut self, prims: Vec<Arc<dyn BoundedPrimitive + Send + Sync>>)
+                     -> usize
+    {
+        self.blases.push(Arc::new(Blas::new(prims)));
+        self.blases.len() - 1
+    }
+
+    /// Place object `obj` (an index returned by `addObject`) into the
+    /// world with `transform`, and return the index of the instance.
+    /// The top level needs to be rebuilt by `build` afterwards.
+    pub fn addInstance(&mut self, obj: usize, transform: Mat4) -> usize
+    {
+        let blas = self.blases[obj].clone();
+        self.instances.push((Arc::new(Instance::new(blas, transform)), obj));
+        self.instances.len() - 1
+    }
+
+    /// Return instance `i`.
+    pub fn instance(&self, i: usize) -> &Instance
+    {
+        &self.instances[i].0
+    }
+
+    /// Move instance `i` to `transform`. The top level needs to be
+    /// rebuilt by `build` afterwards. The BLAS is untouched.
+    pub fn moveInstance(&mut self, i: usize, transform: Mat4)
+    {
+        let obj = self.instances[i].1;
+        self.instances[i].0 = Arc::new(
+            Instance::new(self.blases[obj].clone(), transform));
+    }
+
+    /// (Re)build the top level over the current instances.
+    pub fn build(&mut self)
+    {
+        if self.instances.is_empty()
+        {
+            self.tlas = None;
+            self.tlas_stats = None;
+            return;
+        }
+        let mut prims: Vec<Arc<dyn BoundedPrimitive + Send + Sync>> =
+            self.instances.iter().map(
+                |(inst, _)| inst.clone() as Arc<dyn BoundedPrimitive + Send + Sync>)
+            .collect();
+        let (tree, stats) = buildTimed(&mut prims[..]);
+        self.tlas = Some(tree);
+        self.tlas_stats = Some(stats);
+    }
+}
+
+impl Default for TwoLevelBvh
+{
+    fn default() -> Self
+    {
+        Self::new()
+    }
+}
+
+/// Build time and memory usage of both levels, one line per BVH.
+impl fmt::Display for TwoLevelBvh
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result
+    {
+        match &self.tlas_stats
+        {
+            Some(stats) => writeln!(f, "TLAS ({} instances): {}",
+                                    self.instances.len(), stats)?,
+            None => writeln!(f, "TLAS: not built")?,
+        }
+        let mut total_bytes = 0;
+        let mut total_time = Duration::new(0, 0);
+        for (i, blas) in self.blases.iter().enumerate()
+        {
+            writeln!(f, "BLAS {}: {}", i, blas.stats())?;
+            total_bytes += blas.stats().bytes;
+            total_time += blas.stats().build_time;
+        }
+        write!(f, "BLAS total ({} objects): {:.1} KiB, built in {:.3} ms",
+               self.blases.len(), total_bytes as f64 / 1024.0,
+               total_time.as_secs_f64() * 1000.0)
+    }
+}
+
+impl Primitive for TwoLevelBvh
+{
+    fn intersect(&self, r: &Ray, t_min: Float, t_max: Float) -> Option<Hit>
+    {
+        self.tlas.as_ref()?.intersect(r, t_min, t_max)
+    }
+}
+
+/// Panics if the top level is not built.
+impl BoundedPrimitive for TwoLevelBvh
+{
+    fn bbox(&self) -> BBox
+    {
+        self.tlas.as_ref().expect("TLAS is not built").bbox()
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use crate::geometry::{Vec3, Sphere};
+
+    #[test]
+    fn movedInstanceIsHitAfterRebuild()
+    {
+        let mut accel = TwoLevelBvh::new();
+        let sphere = accel.addObject(vec![
+            Arc::new(Sphere { center: Vec3::origin(), radius: 1.0, material: 0 })]);
+        accel.addInstance(sphere, Mat4::translation(&Vec3::new(0.0, 0.0, -5.0)));
+        let moved = accel.addInstance(sphere, Mat4::translation(&Vec3::new(0.0, 0.0, -10.0)));
+        accel.build();
+
+        let r = Ray::new(Vec3::origin(), Vec3::new(0.0, 0.0, -1.0), 0.0);
+        let side = Ray::new(Vec3::origin(), Vec3::new(1.0, 0.0, 0.0), 0.0);
+        assert!((accel.intersect(&r, 0.0, Float::INFINITY).unwrap().t - 4.0).abs() < 1e-4);
+        assert!(accel.intersect(&side, 0.0, Float::INFINITY).is_none());
+
+        accel.moveInstance(moved, Mat4::translation(&Vec3::new(3.0, 0.0, 0.0)));
+        accel.build();
+        assert!((accel.intersect(&side, 0.0, Float::INFINITY).unwrap().t - 2.0).abs() < 1e-4);
+        assert!((accel.bbox().higher[0] - 4.0).abs() < 1e-4);
+
+        let report = accel.to_string();
+        assert!(report.starts_with("TLAS (2 instances)"));
+        assert!(report.contains("BLAS total (1 objects)"));
+    }
+}
@@ -0,0 +1,145 @@
+use std::sync::Arc;
+
+use crate::rand;
+use crate::config::Float;
+use crate::texture::{self, AnyTexture};
+use super::ray::{Ray, Hit};
+use super::primitive_traits::{Primitive, BBox, BoundedPrimitive};
+
+/// How many cut out hits to skip along a ray before giving up.
+const MAX_SKIPS: u32 = 32;
+
+/// A bounded primitive with an opacity mask, e.g. leaves or a fence.
+/// Rays pass through the wrapped primitive where the alpha (the
+/// luminance of a texture) is below a threshold. With stochastic
+/// alpha, rays pass through with a probability of 1 − alpha instead,
+/// which gives soft edges and semi-transparent surfaces.
+pub struct AlphaMasked
+{
+    obj: Arc<dyn BoundedPrimitive + Send + Sync>,
+    alpha: AnyTexture,
+    threshold: Float,
+    stochastic: bool,
+}
+
+impl AlphaMasked
+{
+    /// Cut out `obj` where `alpha` is below 0.5.
+    pub fn new(obj: Arc<dyn BoundedPrimitive + Send + Sync>, alpha: AnyTexture) -> Self
+    {
+        Self { obj, alpha, threshold: 0.5, stochastic: false }
+    }
+
+    /// Set the alpha below which the primitive is cut out.
+    pub fn withThreshold(mut self, threshold: Float) -> Self
+    {
+        self.threshold = threshold;
+        self
+    }
+
+    /// Let rays through with probability 1 − alpha, instead of by
+    /// the threshold.
+    pub fn stochastic(mut self) -> Self
+    {
+        self.stochastic = true;
+        self
+    }
+
+    fn isOpaque(&self, hit: &Hit) -> bool
+    {
+        let alpha = texture::luminance(&self.alpha.filtered(hit));
+        if self.stochastic
+        {
+            alpha >= 1.0 || rand::random::<Float>() < alpha
+        }
+        else
+        {
+            alpha >= self.threshold
+        }
+    }
+}
+
+impl Primitive for AlphaMasked
+{
+    fn intersect(&self, r: &Ray, t_min: Float, t_max: Float) -> Option<Hit>
+    {
+        let mut t_min = t_min;
+        for _ in 0..MAX_SKIPS
+        {
+            let hit = self.obj.intersect(r, t_min, t_max)?;
+            if self.isOpaque(&hit)
+            {
+                return Some(hit);
+            }
+            t_min = hit.t + 1e-5 * (1.0 + hit.t);
+        }
+        None
+    }
+}
+
+impl BoundedPrimitive for AlphaMasked
+{
+    fn bbox(&self) -> BBox
+    {
+        self.obj.bbox()
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use crate::geometry::{Vec3, Color, Sphere};
+    use crate::texture::{Texture, Constant};
+
+    /// Opaque below the plane z = 0, cut out above.
+    struct Below;
+
+    impl Texture for Below
+    {
+        fn value(&self, _: Float, _: Float, p: &Vec3) -> Color
+        {
+            if p[2] < 0.0 { Color::new(1.0, 1.0, 1.0) } else { Color::origin() }
+        }
+    }
+
+    fn sphere() -> Arc<dyn BoundedPrimitive + Send + Sync>
+    {
+        Arc::new(Sphere { center: Vec3::origin(), radius: 1.0, material: 0 })
+    }
+
+    fn down() -> Ray
+    {
+        Ray::new(Vec3::new(0.0, 0.0, 5.0), Vec3::new(0.0, 0.0, -1.0), 0.0)
+    }
+
+    #[test]
+    fn raysPassThroughCutOutParts()
+    {
+        let masked = AlphaMasked::new(sphere(), Arc::new(Below));
+        // The top is cut out, so the ray goes on to the inside of the
+        // bottom.
+        let hit = masked.intersect(&down(), 0.0, Float::INFINITY).unwrap();
+        assert!((hit.t - 6.0).abs() < 1e-4);
+        assert!(masked.intersect(&down(), 0.0, 5.5).is_none());
+
+        let faint = || Arc::new(Constant::scalar(0.3));
+        assert!(AlphaMasked::new(sphere(), faint())
+                .intersect(&down(), 0.0, Float::INFINITY).is_none());
+        assert!(AlphaMasked::new(sphere(), faint()).withThreshold(0.2)
+                .intersect(&down(), 0.0, Float::INFINITY).is_some());
+    }
+
+    #[test]
+    fn stochasticAlphaLetsRaysThroughByProbability()
+    {
+        let masked = AlphaMasked::new(sphere(), Arc::new(Constant::scalar(0.25))).stochastic();
+        // A ray is stopped by either side, with probability
+        // 1 − 0.75².
+        let n = 20000;
+        let hits = (0..n)
+            .filter(|_| masked.intersect(&down(), 0.0, Float::INFINITY).is_some())
+            .count();
+        assert!((hits as Float / n as Float - 0.4375).abs() < 0.015);
+    }
+}
@@ -1,3 +1,5 @@
+#![allow(clippy::redundant_field_names)]
+
 use std::sync::Arc;
 
 use rand;
@@ -63,6 +65,22 @@ impl BvhNode
         }
     }
 
+    /// Return the shape of the tree as (number of nodes, number of
+    /// leaves, depth).
+    pub fn shape(&self) -> (usize, usize, usize)
+    {
+        match &self.data
+        {
+            BvhData::Leaf(_) => (1, 1, 1),
+            BvhData::Branch(branch) =>
+            {
+                let (left_nodes, left_leaves, left_depth) = branch.left.shape();
+                let (right_nodes, right_leaves, right_depth) = branch.right.shape();
+                (left_nodes + right_nodes + 1, left_leaves + right_leaves,
+                 std::cmp::max(left_depth, right_depth) + 1)
+            },
+        }
+    }
 }
 
 impl Primitive for BvhNode
@@ -96,30 +114,21 @@ impl Primitive for BvhNode
                 let left_hit_maybe = branch.left.intersect(r, t_min, t_max);
                 let right_hit_maybe = branch.right.intersect(r, t_min, t_max);
 
-                if left_hit_maybe.is_some() && right_hit_maybe.is_some()
+                match (left_hit_maybe, right_hit_maybe)
                 {
-                    let left_hit = left_hit_maybe.unwrap();
-                    let right_hit = right_hit_maybe.unwrap();
-                    if left_hit.t < right_hit.t
-                    {
-                        Some(left_hit)
-                    }
-                    else
+                    (Some(left_hit), Some(right_hit)) =>
                     {
-                        Some(right_hit)
-                    }
-                }
-                else if left_hit_maybe.is_some()
-                {
-                    left_hit_maybe
-                }
-                else if right_hit_maybe.is_some()
-                {
-                    right_hit_maybe
-                }
-                else
-                {
-                    None
+                        if left_hit.t < right_hit.t
+                        {
+                            Some(left_hit)
+                        }
+                        else
+                        {
+                            Some(right_hit)
+                        }
+                    },
+                    (Some(left_hit), None) => Some(left_hit),
+                    (None, right_hit_maybe) => right_hit_maybe,
                 }
             },
         }
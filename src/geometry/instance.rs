@@ -0,0 +1,137 @@
+use std::sync::Arc;
+
+use super::vec3::Vec3;
+use super::mat4::Mat4;
+use crate::config::Float;
+use super::ray::{Ray, Hit, RayDifferential};
+use super::primitive_traits::{Primitive, BBox, BoundedPrimitive};
+
+/// A bounded primitive placed into the world with an affine
+/// transform. The wrapped primitive is shared, so a complex object
+/// can be instanced many times at the cost of one `Instance` each.
+///
+/// Intersection happens in the object space of the wrapped
+/// primitive: the ray is transformed by the inverse transform, and
+/// the hit is transformed back. The direction of the ray is not
+/// normalized during this, so the ray time of the hit is the same in
+/// both spaces.
+pub struct Instance
+{
+    obj: Arc<dyn BoundedPrimitive + Send + Sync>,
+    transform: Mat4,
+    inverse: Mat4,
+    bounding_box: BBox,
+}
+
+impl Instance
+{
+    /// Place `obj` into the world with `transform`. Panics if
+    /// `transform` is not invertible.
+    pub fn new(obj: Arc<dyn BoundedPrimitive + Send + Sync>, transform: Mat4) ->
+        Self
+    {
+        let inverse = transform.inverse().expect("Transform is not invertible");
+        Self::withInverse(obj, transform, inverse)
+    }
+
+    /// Same as `new`, but with a known inverse of `transform`, which
+    /// avoids the inversion.
+    pub fn withInverse(obj: Arc<dyn BoundedPrimitive + Send + Sync>,
+                       transform: Mat4, inverse: Mat4) -> Self
+    {
+        let bounding_box = transformBBox(&obj.bbox(), &transform);
+        Self {
+            obj,
+            transform,
+            inverse,
+            bounding_box,
+        }
+    }
+
+    /// The transform from object space to world space.
+    pub fn transform(&self) -> &Mat4
+    {
+        &self.transform
+    }
+}
+
+/// Return the world space bbox of the 8 corners of `b` after
+/// `transform`.
+pub fn transformBBox(b: &BBox, transform: &Mat4) -> BBox
+{
+    let mut result: Option<BBox> = None;
+    for i in 0..8
+    {
+        let corner = Vec3::new(
+            if i & 1 == 0 { b.lower[0] } else { b.higher[0] },
+            if i & 2 == 0 { b.lower[1] } else { b.higher[1] },
+            if i & 4 == 0 { b.lower[2] } else { b.higher[2] });
+        let p = transform.point(&corner);
+        let point_box = BBox { lower: p, higher: p };
+        result = Some(match result
+        {
+            Some(r) => r.union(&point_box),
+            None => point_box,
+        });
+    }
+    result.unwrap()
+}
+
+impl Primitive for Instance
+{
+    fn intersect(&self, r: &Ray, t_min: Float, t_max: Float) -> Option<Hit>
+    {
+        let mut local = Ray::new(self.inverse.point(&r.origin),
+                                 self.inverse.vector(&r.dir), r.time);
+        local.differentials = r.differentials.map(|diff| RayDifferential {
+            rx_origin: self.inverse.point(&diff.rx_origin),
+            rx_dir: self.inverse.vector(&diff.rx_dir),
+            ry_origin: self.inverse.point(&diff.ry_origin),
+            ry_dir: self.inverse.vector(&diff.ry_dir),
+        });
+        // The change of surface coordinates across pixels does not
+        // depend on the space, so only the vectors need transforming.
+        let mut hit = self.obj.intersect(&local, t_min, t_max)?;
+        hit.p = r.at(hit.t);
+        hit.normal = Vec3::unit(&self.inverse.normal(&hit.normal));
+        hit.dpdu = self.transform.vector(&hit.dpdu);
+        hit.dpdv = self.transform.vector(&hit.dpdv);
+        let tangent = self.transform.vector(&hit.tangent);
+        hit.setShadingFrame(Vec3::unit(&self.inverse.normal(&hit.shading_normal)),
+                            &tangent);
+        Some(hit)
+    }
+}
+
+impl BoundedPrimitive for Instance
+{
+    fn bbox(&self) -> BBox
+    {
+        self.bounding_box.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use crate::geometry::Sphere;
+
+    #[test]
+    fn hitIsTransformedBackToWorldSpace()
+    {
+        let sphere = Arc::new(Sphere { center: Vec3::origin(), radius: 1.0, material: 0 });
+        // An ellipsoid 2 wide along x, moved to x = 5.
+        let inst = Instance::new(sphere, Mat4::translation(&Vec3::new(5.0, 0.0, 0.0)) *
+                                 Mat4::scaling(&Vec3::new(2.0, 1.0, 1.0)));
+        let r = Ray::new(Vec3::origin(), Vec3::new(1.0, 0.0, 0.0), 0.0);
+        let hit = inst.intersect(&r, 0.0, Float::INFINITY).unwrap();
+        assert!((hit.t - 3.0).abs() < 1e-4);
+        assert!((hit.p - Vec3::new(3.0, 0.0, 0.0)).norm() < 1e-4);
+        assert!((hit.normal - Vec3::new(-1.0, 0.0, 0.0)).norm() < 1e-4);
+
+        let b = inst.bbox();
+        assert!((b.lower[0] - 3.0).abs() < 1e-4 && (b.higher[0] - 7.0).abs() < 1e-4);
+        assert!((b.higher[1] - 1.0).abs() < 1e-4);
+    }
+}
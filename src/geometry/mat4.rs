@@ -0,0 +1,300 @@
+use std::ops;
+
+use crate::config::Float;
+use super::vec3::{self, Vec3};
+
+/// A 4x4 matrix, used as an affine transform in homogeneous
+/// coordinates.
+///
+/// Points are treated as column vectors, so a transform `a` followed
+/// by a transform `b` is `b * a`. Only `*` with another `Mat4` is
+/// provided as an operator. Points, vectors and normals transform
+/// differently, so they have their own methods.
+#[derive(Clone, Copy, Debug)]
+pub struct Mat4
+{
+    /// The internal storage of the matrix, row-major.
+    pub data: [[Float; 4]; 4],
+}
+
+impl ops::Mul for Mat4
+{
+    type Output = Self;
+    fn mul(self, rhs: Self) -> Self
+    {
+        let mut result = Self::zero();
+        for i in 0..4
+        {
+            for j in 0..4
+            {
+                result.data[i][j] = (0..4).map(|k| self.data[i][k] * rhs.data[k][j])
+                    .sum();
+            }
+        }
+        result
+    }
+}
+
+impl ops::MulAssign for Mat4
+{
+    fn mul_assign(&mut self, rhs: Self)
+    {
+        *self = *self * rhs;
+    }
+}
+
+impl Mat4
+{
+    /// Construct a matrix from its rows.
+    pub const fn new(data: [[Float; 4]; 4]) -> Self
+    {
+        Self { data }
+    }
+
+    /// Return the zero matrix.
+    pub const fn zero() -> Self
+    {
+        Self::new([[0.0; 4]; 4])
+    }
+
+    /// Return the identity matrix.
+    pub const fn identity() -> Self
+    {
+        Self::new([[1.0, 0.0, 0.0, 0.0],
+                   [0.0, 1.0, 0.0, 0.0],
+                   [0.0, 0.0, 1.0, 0.0],
+                   [0.0, 0.0, 0.0, 1.0]])
+    }
+
+    /// A translation by `d`.
+    pub fn translation(d: &Vec3) -> Self
+    {
+        Self::new([[1.0, 0.0, 0.0, d[0]],
+                   [0.0, 1.0, 0.0, d[1]],
+                   [0.0, 0.0, 1.0, d[2]],
+                   [0.0, 0.0, 0.0, 1.0]])
+    }
+
+    /// A scaling by `s[i]` along axis i.
+    pub fn scaling(s: &Vec3) -> Self
+    {
+        Self::new([[s[0], 0.0, 0.0, 0.0],
+                   [0.0, s[1], 0.0, 0.0],
+                   [0.0, 0.0, s[2], 0.0],
+                   [0.0, 0.0, 0.0, 1.0]])
+    }
+
+    /// A counter-clockwise rotation by `angle` (in radians) around
+    /// `axis`, which does not need to be normalized.
+    pub fn rotation(axis: &Vec3, angle: Float) -> Self
+    {
+        let a = Vec3::unit(axis);
+        let (sin, cos) = angle.sin_cos();
+        let c1 = 1.0 - cos;
+        Self::new([[a[0] * a[0] * c1 + cos,
+                    a[0] * a[1] * c1 - a[2] * sin,
+                    a[0] * a[2] * c1 + a[1] * sin, 0.0],
+                   [a[1] * a[0] * c1 + a[2] * sin,
+                    a[1] * a[1] * c1 + cos,
+                    a[1] * a[2] * c1 - a[0] * sin, 0.0],
+                   [a[2] * a[0] * c1 - a[1] * sin,
+                    a[2] * a[1] * c1 + a[0] * sin,
+                    a[2] * a[2] * c1 + cos, 0.0],
+                   [0.0, 0.0, 0.0, 1.0]])
+    }
+
+    /// Return the inverse of the matrix, or `None` if it is singular.
+    /// This is Gauss–Jordan elimination with partial pivoting.
+    pub fn inverse(&self) -> Option<Self>
+    {
+        let mut m = self.data;
+        let mut inv = Self::identity().data;
+        for col in 0..4
+        {
+            let mut pivot = col;
+            for row in col+1..4
+            {
+                if m[row][col].abs() > m[pivot][col].abs()
+                {
+                    pivot = row;
+                }
+            }
+            if m[pivot][col] == 0.0
+            {
+                return None;
+            }
+            m.swap(col, pivot);
+            inv.swap(col, pivot);
+
+            let scale = 1.0 / m[col][col];
+            for j in 0..4
+            {
+                m[col][j] *= scale;
+                inv[col][j] *= scale;
+            }
+            for row in 0..4
+            {
+                if row == col
+                {
+                    continue;
+                }
+                let factor = m[row][col];
+                for j in 0..4
+                {
+                    m[row][j] -= factor * m[col][j];
+                    inv[row][j] -= factor * inv[col][j];
+                }
+            }
+        }
+        Some(Self::new(inv))
+    }
+
+    /// Transform a position. The translation part applies.
+    pub fn point(&self, p: &Vec3) -> Vec3
+    {
+        let d = &self.data;
+        let x = d[0][0] * p[0] + d[0][1] * p[1] + d[0][2] * p[2] + d[0][3];
+        let y = d[1][0] * p[0] + d[1][1] * p[1] + d[1][2] * p[2] + d[1][3];
+        let z = d[2][0] * p[0] + d[2][1] * p[1] + d[2][2] * p[2] + d[2][3];
+        let w = d[3][0] * p[0] + d[3][1] * p[1] + d[3][2] * p[2] + d[3][3];
+        if w == 1.0
+        {
+            Vec3::new(x, y, z)
+        }
+        else
+        {
+            Vec3::new(x, y, z) / w
+        }
+    }
+
+    /// Transform a direction. The translation part does not apply.
+    pub fn vector(&self, v: &Vec3) -> Vec3
+    {
+        let d = &self.data;
+        Vec3::new(d[0][0] * v[0] + d[0][1] * v[1] + d[0][2] * v[2],
+                  d[1][0] * v[0] + d[1][1] * v[1] + d[1][2] * v[2],
+                  d[2][0] * v[0] + d[2][1] * v[1] + d[2][2] * v[2])
+    }
+
+    /// Transform a normal, assuming `self` is the *inverse* of the
+    /// transform that is applied to the geometry. Normals transform
+    /// with the inverse transpose, so this saves an inversion when
+    /// the inverse is already at hand. The result is not normalized.
+    pub fn normal(&self, n: &Vec3) -> Vec3
+    {
+        let d = &self.data;
+        Vec3::new(d[0][0] * n[0] + d[1][0] * n[1] + d[2][0] * n[2],
+                  d[0][1] * n[0] + d[1][1] * n[1] + d[2][1] * n[2],
+                  d[0][2] * n[0] + d[1][2] * n[1] + d[2][2] * n[2])
+    }
+}
+
+impl ops::Index<(usize, usize)> for Mat4
+{
+    type Output = Float;
+
+    fn index(&self, i: (usize, usize)) -> &Self::Output
+    {
+        &self.data[i.0][i.1]
+    }
+}
+
+impl ops::IndexMut<(usize, usize)> for Mat4
+{
+    fn index_mut(&mut self, i: (usize, usize)) -> &mut Self::Output
+    {
+        &mut self.data[i.0][i.1]
+    }
+}
+
+/// Construct a transform that maps the standard frame to one where
+/// the z axis points to `dir`. Useful for orienting objects.
+pub fn alignZ(dir: &Vec3) -> Mat4
+{
+    let w = Vec3::unit(dir);
+    let (u, v) = vec3::basis(&w);
+    Mat4::new([[u[0], v[0], w[0], 0.0],
+               [u[1], v[1], w[1], 0.0],
+               [u[2], v[2], w[2], 0.0],
+               [0.0, 0.0, 0.0, 1.0]])
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use crate::config::PI;
+
+    fn assertNear(a: &Vec3, b: &Vec3)
+    {
+        assert!((*a - *b).norm() < 1e-4, "{:?} != {:?}", a, b);
+    }
+
+    fn transpose(m: &Mat4) -> Mat4
+    {
+        let mut result = Mat4::zero();
+        for i in 0..4
+        {
+            for j in 0..4
+            {
+                result[(i, j)] = m[(j, i)];
+            }
+        }
+        result
+    }
+
+    #[test]
+    fn inverseUndoesTransform()
+    {
+        let m = Mat4::translation(&Vec3::new(1.0, -2.0, 3.0)) *
+            Mat4::rotation(&Vec3::new(1.0, 1.0, 0.0), 0.7) *
+            Mat4::scaling(&Vec3::new(2.0, 0.5, 3.0));
+        let inv = m.inverse().unwrap();
+        let product = m * inv;
+        for i in 0..4
+        {
+            for j in 0..4
+            {
+                let expected = if i == j { 1.0 } else { 0.0 };
+                assert!((product[(i, j)] - expected).abs() < 1e-5);
+            }
+        }
+        let p = Vec3::new(0.3, 0.4, -5.0);
+        assertNear(&inv.point(&m.point(&p)), &p);
+        assert!(Mat4::scaling(&Vec3::new(1.0, 0.0, 1.0)).inverse().is_none());
+    }
+
+    #[test]
+    fn rotationInverseIsTranspose()
+    {
+        let r = Mat4::rotation(&Vec3::new(0.0, 0.0, 1.0), PI * 0.5);
+        assertNear(&r.point(&Vec3::new(1.0, 0.0, 0.0)), &Vec3::new(0.0, 1.0, 0.0));
+        let product = r * transpose(&r);
+        for i in 0..4
+        {
+            assert!((product[(i, i)] - 1.0).abs() < 1e-5);
+        }
+    }
+
+    #[test]
+    fn normalsStayPerpendicular()
+    {
+        let m = Mat4::scaling(&Vec3::new(1.0, 4.0, 1.0)) *
+            Mat4::rotation(&Vec3::new(0.0, 0.0, 1.0), 0.5);
+        let inv = m.inverse().unwrap();
+        let tangent = Vec3::new(1.0, 1.0, 0.0);
+        let normal = Vec3::new(1.0, -1.0, 0.0);
+        let n = inv.normal(&normal);
+        assert!(vec3::dot(&m.vector(&tangent), &n).abs() < 1e-5);
+        // The translation does not move vectors.
+        let t = Mat4::translation(&Vec3::new(5.0, 5.0, 5.0));
+        assertNear(&t.vector(&tangent), &tangent);
+    }
+
+    #[test]
+    fn alignZPointsZAlongDirection()
+    {
+        let dir = Vec3::new(1.0, 2.0, -0.5);
+        assertNear(&alignZ(&dir).vector(&Vec3::new(0.0, 0.0, 1.0)), &Vec3::unit(&dir));
+    }
+}
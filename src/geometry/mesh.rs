@@ -0,0 +1,262 @@
+use std::sync::Arc;
+
+use super::vec3::{self, Vec3};
+use crate::config::Float;
+use super::ray::{Ray, Hit};
+use crate::rand;
+use super::primitive_traits::{self, Primitive, BBox, BoundedPrimitive, SampledPrimitive};
+
+/// A triangle mesh with shared vertices. The mesh itself is not a
+/// primitive; it is broken into `Triangle`s that refer back to it,
+/// so that they can be put into a BVH.
+pub struct TriangleMesh
+{
+    pub positions: Vec<Vec3>,
+    /// Each triangle is 3 indices into `positions`, counter-clockwise
+    /// when looking at the front face.
+    pub indices: Vec<[usize; 3]>,
+    /// Optional vertex normals, parallel to `positions`. If present,
+    /// they are interpolated into the shading normal.
+    pub normals: Option<Vec<Vec3>>,
+    /// Optional vertex texture coordinates, parallel to `positions`.
+    pub uvs: Option<Vec<(Float, Float)>>,
+    pub material: usize,
+}
+
+impl TriangleMesh
+{
+    pub fn new(positions: Vec<Vec3>, indices: Vec<[usize; 3]>, material: usize) ->
+        Self
+    {
+        Self { positions, indices, normals: None, uvs: None, material }
+    }
+
+    /// Return the mesh with vertex normals.
+    pub fn withNormals(mut self, normals: Vec<Vec3>) -> Self
+    {
+        self.normals = Some(normals);
+        self
+    }
+
+    /// Return the mesh with vertex texture coordinates.
+    pub fn withUVs(mut self, uvs: Vec<(Float, Float)>) -> Self
+    {
+        self.uvs = Some(uvs);
+        self
+    }
+
+    /// Return all the triangles of the mesh as primitives.
+    pub fn triangles(self: &Arc<Self>) ->
+        Vec<Arc<dyn BoundedPrimitive + Send + Sync>>
+    {
+        (0..self.indices.len()).map(
+            |i| Arc::new(Triangle { mesh: self.clone(), index: i })
+                as Arc<dyn BoundedPrimitive + Send + Sync>).collect()
+    }
+
+    /// Return all the triangles of the mesh as primitives that can
+    /// be lights.
+    pub fn sampledTriangles(self: &Arc<Self>) ->
+        Vec<Arc<dyn SampledPrimitive + Send + Sync>>
+    {
+        (0..self.indices.len()).map(
+            |i| Arc::new(Triangle { mesh: self.clone(), index: i })
+                as Arc<dyn SampledPrimitive + Send + Sync>).collect()
+    }
+}
+
+/// One triangle in a `TriangleMesh`.
+pub struct Triangle
+{
+    mesh: Arc<TriangleMesh>,
+    index: usize,
+}
+
+impl Triangle
+{
+    /// Return the 3 vertices of the triangle.
+    pub fn vertices(&self) -> (Vec3, Vec3, Vec3)
+    {
+        let idx = &self.mesh.indices[self.index];
+        (self.mesh.positions[idx[0]], self.mesh.positions[idx[1]],
+         self.mesh.positions[idx[2]])
+    }
+}
+
+impl Primitive for Triangle
+{
+    /// This is the Möller–Trumbore algorithm.
+    fn intersect(&self, r: &Ray, t_min: Float, t_max: Float) -> Option<Hit>
+    {
+        let (p0, p1, p2) = self.vertices();
+        let e1 = p1 - p0;
+        let e2 = p2 - p0;
+        let pvec = vec3::cross(&r.dir, &e2);
+        let det = vec3::dot(&e1, &pvec);
+        if det == 0.0
+        {
+            // Ray is parallel to the triangle.
+            return None;
+        }
+        let det_inverse = 1.0 / det;
+        let tvec = r.origin - p0;
+        let u = vec3::dot(&tvec, &pvec) * det_inverse;
+        if !(0.0..=1.0).contains(&u)
+        {
+            return None;
+        }
+        let qvec = vec3::cross(&tvec, &e1);
+        let v = vec3::dot(&r.dir, &qvec) * det_inverse;
+        if v < 0.0 || u + v > 1.0
+        {
+            return None;
+        }
+        let t = vec3::dot(&e2, &qvec) * det_inverse;
+        if !(t < t_max && t > t_min)
+        {
+            return None;
+        }
+        let idx = &self.mesh.indices[self.index];
+        let w = 1.0 - u - v;
+        let normal = Vec3::unit(&vec3::cross(&e1, &e2));
+
+        // Without texture coordinates on the mesh, the barycentric
+        // coordinates are used as surface coordinates.
+        let (tex_u, tex_v, dpdu, dpdv) = match &self.mesh.uvs
+        {
+            Some(uvs) =>
+            {
+                let (uv0, uv1, uv2) = (uvs[idx[0]], uvs[idx[1]], uvs[idx[2]]);
+                let du1 = uv1.0 - uv0.0;
+                let dv1 = uv1.1 - uv0.1;
+                let du2 = uv2.0 - uv0.0;
+                let dv2 = uv2.1 - uv0.1;
+                let uv_det = du1 * dv2 - dv1 * du2;
+                let (dpdu, dpdv) = if uv_det == 0.0 { (e1, e2) }
+                                   else { ((e1 * dv2 - e2 * dv1) / uv_det,
+                                           (e2 * du1 - e1 * du2) / uv_det) };
+                (w * uv0.0 + u * uv1.0 + v * uv2.0,
+                 w * uv0.1 + u * uv1.1 + v * uv2.1, dpdu, dpdv)
+            },
+            None => (u, v, e1, e2),
+        };
+
+        let mut hit = Hit::new(r, t, normal, tex_u, tex_v, dpdu, dpdv,
+                               self.mesh.material);
+        if let Some(normals) = &self.mesh.normals
+        {
+            let mut shading = normals[idx[0]] * w + normals[idx[1]] * u +
+                normals[idx[2]] * v;
+            // Keep the shading normal on the same side as the geometry.
+            if vec3::dot(&shading, &normal) < 0.0
+            {
+                shading = -shading;
+            }
+            hit.setShadingFrame(Vec3::unit(&shading), &dpdu);
+        }
+        Some(hit)
+    }
+}
+
+impl BoundedPrimitive for Triangle
+{
+    fn bbox(&self) -> BBox
+    {
+        let (p0, p1, p2) = self.vertices();
+        BBox { lower: p0, higher: p0 }
+            .union(&BBox { lower: p1, higher: p1 })
+            .union(&BBox { lower: p2, higher: p2 })
+            .padded(1e-4)
+    }
+}
+
+impl SampledPrimitive for Triangle
+{
+    /// Sample the surface uniformly.
+    fn sampleToward(&self, reference: &Vec3) -> Option<(Vec3, Float)>
+    {
+        let (p0, p1, p2) = self.vertices();
+        let s = rand::random::<Float>().sqrt();
+        let t = rand::random::<Float>();
+        let q = p0 * (1.0 - s) + p1 * (s * (1.0 - t)) + p2 * (s * t);
+        let d = q - *reference;
+        let distance2 = d.normSquared();
+        if distance2 == 0.0
+        {
+            return None;
+        }
+        let dir = d / distance2.sqrt();
+        let normal = Vec3::unit(&vec3::cross(&(p1 - p0), &(p2 - p0)));
+        let cos = vec3::dot(&dir, &normal).abs();
+        if cos == 0.0
+        {
+            return None;
+        }
+        Some((dir, distance2 / (cos * self.area())))
+    }
+
+    fn pdfToward(&self, reference: &Vec3, dir: &Vec3) -> Float
+    {
+        primitive_traits::areaToSolidAngle(self, self.area(), reference, dir)
+    }
+
+    fn material(&self) -> usize
+    {
+        self.mesh.material
+    }
+
+    fn area(&self) -> Float
+    {
+        let (p0, p1, p2) = self.vertices();
+        0.5 * vec3::cross(&(p1 - p0), &(p2 - p0)).norm()
+    }
+
+    fn normalBounds(&self) -> (Vec3, Float)
+    {
+        let (p0, p1, p2) = self.vertices();
+        (Vec3::unit(&vec3::cross(&(p1 - p0), &(p2 - p0))), 1.0)
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    fn triangle() -> Triangle
+    {
+        let mesh = TriangleMesh::new(
+            vec![Vec3::new(0.0, 0.0, -2.0), Vec3::new(2.0, 0.0, -2.0),
+                 Vec3::new(0.0, 2.0, -2.0)], vec![[0, 1, 2]], 0)
+            .withUVs(vec![(0.0, 0.0), (1.0, 0.0), (0.0, 1.0)]);
+        Triangle { mesh: Arc::new(mesh), index: 0 }
+    }
+
+    #[test]
+    fn hitHasBarycentricCoordinates()
+    {
+        let tri = triangle();
+        let r = Ray::new(Vec3::new(0.5, 1.0, 0.0), Vec3::new(0.0, 0.0, -1.0), 0.0);
+        let hit = tri.intersect(&r, 0.0, Float::INFINITY).unwrap();
+        assert!((hit.t - 2.0).abs() < 1e-5);
+        assert!((hit.u - 0.25).abs() < 1e-5 && (hit.v - 0.5).abs() < 1e-5);
+        assert!((hit.normal - Vec3::new(0.0, 0.0, 1.0)).norm() < 1e-5);
+        assert!((hit.dpdu - Vec3::new(2.0, 0.0, 0.0)).norm() < 1e-5);
+    }
+
+    #[test]
+    fn missesOutsideEdgesAndParallelRays()
+    {
+        let tri = triangle();
+        let down = Vec3::new(0.0, 0.0, -1.0);
+        assert!(tri.intersect(&Ray::new(Vec3::new(1.5, 1.5, 0.0), down, 0.0),
+                              0.0, Float::INFINITY).is_none());
+        assert!(tri.intersect(&Ray::new(Vec3::new(-0.1, 0.5, 0.0), down, 0.0),
+                              0.0, Float::INFINITY).is_none());
+        assert!(tri.intersect(&Ray::new(Vec3::new(0.5, 0.5, 0.0), down, 0.0),
+                              0.0, 1.5).is_none());
+        assert!(tri.intersect(&Ray::new(Vec3::new(-1.0, 0.5, -2.0),
+                                        Vec3::new(1.0, 0.0, 0.0), 0.0),
+                              0.0, Float::INFINITY).is_none());
+    }
+}
@@ -9,8 +9,29 @@ pub use primitive_traits::*;
 mod primitive;
 pub use primitive::*;
 
+mod accel;
+pub use accel::*;
+
+mod shapes;
+pub use shapes::*;
+
+mod mesh;
+pub use mesh::*;
+
+mod instance;
+pub use instance::*;
+
+mod motion;
+pub use motion::*;
+
+mod alpha_mask;
+pub use alpha_mask::*;
+
 mod ray;
 pub use ray::*;
 
 pub mod vec3;
 pub use vec3::*;
+
+pub mod mat4;
+pub use mat4::*;
@@ -0,0 +1,96 @@
+use super::vec3::Vec3;
+use crate::config::Float;
+
+/// A position that changes over time, given as a list of keyframes.
+/// Between two keyframes the position is linearly interpolated.
+/// Before the first keyframe and after the last one, the position
+/// stays at that keyframe.
+#[derive(Clone, Debug)]
+pub struct Keyframes
+{
+    /// List of (time, position), sorted by time.
+    frames: Vec<(Float, Vec3)>,
+}
+
+impl Keyframes
+{
+    /// Construct from a list of (time, position) pairs. The pairs do
+    /// not need to be sorted. Panics if `frames` is empty.
+    pub fn new(mut frames: Vec<(Float, Vec3)>) -> Self
+    {
+        if frames.is_empty()
+        {
+            panic!("Keyframes need at least one frame");
+        }
+        frames.sort_by(|a, b| a.0.total_cmp(&b.0));
+        Self { frames }
+    }
+
+    /// A position that moves linearly from `p0` at `time0` to `p1`
+    /// at `time1`.
+    pub fn linear(time0: Float, p0: Vec3, time1: Float, p1: Vec3) -> Self
+    {
+        Self::new(vec![(time0, p0), (time1, p1)])
+    }
+
+    /// Return the position at `time`.
+    pub fn at(&self, time: Float) -> Vec3
+    {
+        let first = &self.frames[0];
+        if time <= first.0
+        {
+            return first.1;
+        }
+        for pair in self.frames.windows(2)
+        {
+            let (t0, p0) = pair[0];
+            let (t1, p1) = pair[1];
+            if time <= t1
+            {
+                let s = (time - t0) / (t1 - t0);
+                return p0 + (p1 - p0) * s;
+            }
+        }
+        self.frames[self.frames.len() - 1].1
+    }
+
+    /// Return all the keyed positions. Because positions are linearly
+    /// interpolated, any position along the motion is in the convex
+    /// hull of these.
+    pub fn positions(&self) -> impl Iterator<Item = &Vec3>
+    {
+        self.frames.iter().map(|f| &f.1)
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    fn assertNear(a: &Vec3, b: &Vec3)
+    {
+        assert!((*a - *b).norm() < 1e-5, "{:?} != {:?}", a, b);
+    }
+
+    #[test]
+    fn interpolatesBetweenUnsortedFrames()
+    {
+        let k = Keyframes::new(vec![(1.0, Vec3::new(2.0, 0.0, 0.0)),
+                                    (0.0, Vec3::new(0.0, 0.0, 0.0)),
+                                    (2.0, Vec3::new(2.0, 4.0, 0.0))]);
+        assertNear(&k.at(0.25), &Vec3::new(0.5, 0.0, 0.0));
+        assertNear(&k.at(1.5), &Vec3::new(2.0, 2.0, 0.0));
+        // Outside the keyframes, the position stays.
+        assertNear(&k.at(-1.0), &Vec3::new(0.0, 0.0, 0.0));
+        assertNear(&k.at(3.0), &Vec3::new(2.0, 4.0, 0.0));
+    }
+
+    #[test]
+    fn nanTimeDoesNotPanic()
+    {
+        let k = Keyframes::new(vec![(Float::NAN, Vec3::new(1.0, 0.0, 0.0)),
+                                    (0.0, Vec3::new(0.0, 0.0, 0.0))]);
+        assertNear(&k.at(-1.0), &Vec3::new(0.0, 0.0, 0.0));
+    }
+}
@@ -1,12 +1,82 @@
+#![allow(clippy::redundant_field_names, clippy::needless_return)]
+
 use std::vec::Vec;
 use std::sync::Arc;
 
+use crate::rand;
 use super::vec3;
 use vec3::Vec3;
-use crate::config::Float;
+use crate::config::{Float, PI};
 use super::ray::{Ray,Hit};
-use super::primitive_traits::{Primitive, BBox, BoundedPrimitive};
-use super::bvh;
+use super::primitive_traits::{self, Primitive, BBox, BoundedPrimitive, SampledPrimitive};
+use super::BvhNode;
+use super::Keyframes;
+
+/// Intersect ray `r` with a sphere at `center` with `radius`.
+fn intersectSphere(center: &Vec3, radius: Float, material: usize,
+                   r: &Ray, t_min: Float, t_max: Float) -> Option<Hit>
+{
+    let oc = r.origin - *center;
+    let a = r.dir.normSquared();
+    let b = vec3::dot(&oc, &r.dir);
+    let c = oc.normSquared() - radius * radius;
+
+    let discriminant = b * b - a * c;
+
+    if discriminant > 0.0
+    {
+        let mut temp = (-b - discriminant.sqrt()) / a;
+        if !(temp < t_max && temp > t_min)
+        {
+            temp = (-b + discriminant.sqrt()) / a;
+            if !(temp < t_max && temp > t_min)
+            {
+                return None;
+            }
+        }
+
+        let normal = (r.at(temp) - *center) / radius;
+        let (u, v) = sphereUV(&normal);
+        let (dpdu, dpdv) = sphereDerivatives(&normal, radius);
+        return Some(Hit::new(r, temp, normal, u, v, dpdu, dpdv, material));
+    }
+    return None;
+}
+
+/// Return the surface coordinates on a unit sphere at point `p`. u
+/// goes around the y axis, v goes from -y to +y.
+pub fn sphereUV(p: &Vec3) -> (Float, Float)
+{
+    let theta = (-p[1]).clamp(-1.0, 1.0).acos();
+    let phi = (-p[2]).atan2(p[0]) + PI;
+    (phi / (2.0 * PI), theta / PI)
+}
+
+/// Return dp/du and dp/dv at point `n` on a unit sphere, scaled to
+/// a sphere with `radius`, where u and v are from `sphereUV`.
+fn sphereDerivatives(n: &Vec3, radius: Float) -> (Vec3, Vec3)
+{
+    let dpdu = Vec3::new(n[2], 0.0, -n[0]) * (2.0 * PI * radius);
+    // Distance to the y axis.
+    let rho = (n[0] * n[0] + n[2] * n[2]).sqrt();
+    let dpdv = if rho > 0.0
+    {
+        Vec3::new(-n[1] * n[0] / rho, rho, -n[1] * n[2] / rho) * (PI * radius)
+    }
+    else
+    {
+        Vec3::origin()
+    };
+    (dpdu, dpdv)
+}
+
+fn sphereBBox(center: &Vec3, radius: Float) -> BBox
+{
+    BBox {
+        lower: *center - Vec3::new(radius, radius, radius),
+        higher: *center + Vec3::new(radius, radius, radius),
+    }
+}
 
 /// A sphere with a center and a radius.
 #[derive(Clone, Copy, Debug)]
@@ -23,50 +93,147 @@ impl Primitive for Sphere
 {
     fn intersect(&self, r: &Ray, t_min: Float, t_max: Float) -> Option<Hit>
     {
-        let oc = r.origin - self.center;
-        let a = r.dir.normSquared();
-        let b = vec3::dot(&oc, &r.dir);
-        let c = oc.normSquared() - self.radius * self.radius;
+        intersectSphere(&self.center, self.radius, self.material, r, t_min, t_max)
+    }
+}
 
-        let discriminant = b * b - a * c;
+impl BoundedPrimitive for Sphere
+{
+    fn bbox(&self) -> BBox
+    {
+        sphereBBox(&self.center, self.radius)
+    }
+}
 
-        if discriminant > 0.0
+impl Sphere
+{
+    /// Return the cosine of the half angle of the cone the sphere
+    /// covers seen from `p`, or None if `p` is inside.
+    fn coneCos(&self, p: &Vec3) -> Option<Float>
+    {
+        let distance2 = (self.center - *p).normSquared();
+        let sin2 = self.radius * self.radius / distance2;
+        if sin2 >= 1.0
         {
-            let mut temp = (-b - discriminant.sqrt()) / a;
-            if !(temp < t_max && temp > t_min)
+            return None;
+        }
+        Some((1.0 - sin2).sqrt())
+    }
+}
+
+impl SampledPrimitive for Sphere
+{
+    /// From outside, sample the cone of directions the sphere covers
+    /// uniformly. From inside, sample the surface uniformly.
+    fn sampleToward(&self, reference: &Vec3) -> Option<(Vec3, Float)>
+    {
+        match self.coneCos(reference)
+        {
+            Some(cos_max) =>
+            {
+                let axis = Vec3::unit(&(self.center - *reference));
+                let cos = 1.0 - rand::random::<Float>() * (1.0 - cos_max);
+                let sin = (1.0 - cos * cos).max(0.0).sqrt();
+                let phi = 2.0 * PI * rand::random::<Float>();
+                let (t, b) = vec3::basis(&axis);
+                let dir = axis * cos + (t * phi.cos() + b * phi.sin()) * sin;
+                Some((dir, 1.0 / (2.0 * PI * (1.0 - cos_max))))
+            },
+            None =>
             {
-                temp = (-b + discriminant.sqrt()) / a;
-                if !(temp < t_max && temp > t_min)
+                let q = self.center + Vec3::unit(&Vec3::randInUnitSphere()) * self.radius;
+                let d = q - *reference;
+                let distance2 = d.normSquared();
+                if distance2 == 0.0
                 {
                     return None;
                 }
-            }
+                let dir = d / distance2.sqrt();
+                let cos = vec3::dot(&dir, &((q - self.center) / self.radius)).abs();
+                if cos == 0.0
+                {
+                    return None;
+                }
+                Some((dir, distance2 / (cos * self.area())))
+            },
+        }
+    }
 
-            let p = r.at(temp);
-            return Some(Hit { t: temp, p: p,
-                              normal: (p - self.center) / self.radius,
-                              material: self.material,
-            });
+    fn pdfToward(&self, reference: &Vec3, dir: &Vec3) -> Float
+    {
+        match self.coneCos(reference)
+        {
+            Some(cos_max) =>
+            {
+                let r = Ray::new(*reference, *dir, 0.0);
+                if self.intersect(&r, 0.0, Float::INFINITY).is_some()
+                {
+                    1.0 / (2.0 * PI * (1.0 - cos_max))
+                }
+                else
+                {
+                    0.0
+                }
+            },
+            None => primitive_traits::areaToSolidAngle(self, self.area(), reference, dir),
         }
-        return None;
+    }
+
+    fn material(&self) -> usize
+    {
+        self.material
+    }
+
+    fn area(&self) -> Float
+    {
+        4.0 * PI * self.radius * self.radius
+    }
+
+    fn normalBounds(&self) -> (Vec3, Float)
+    {
+        (Vec3::new(0.0, 1.0, 0.0), -1.0)
     }
 }
 
-impl BoundedPrimitive for Sphere
+/// A sphere whose center moves over time. Its position at the time
+/// of a ray is used to intersect with that ray.
+#[derive(Clone, Debug)]
+pub struct MovingSphere
 {
+    pub center: Keyframes,
+    pub radius: Float,
+    pub material: usize,
+}
+
+impl Primitive for MovingSphere
+{
+    fn intersect(&self, r: &Ray, t_min: Float, t_max: Float) -> Option<Hit>
+    {
+        intersectSphere(&self.center.at(r.time), self.radius, self.material,
+                        r, t_min, t_max)
+    }
+}
+
+impl BoundedPrimitive for MovingSphere
+{
+    /// Return the bbox that contains the sphere throughout its whole
+    /// motion.
     fn bbox(&self) -> BBox
     {
-        BBox {
-            lower: self.center -
-                Vec3::new(self.radius, self.radius, self.radius),
-            higher: self.center +
-                Vec3::new(self.radius, self.radius, self.radius),
+        let mut positions = self.center.positions();
+        let mut b = sphereBBox(positions.next().unwrap(), self.radius);
+        for p in positions
+        {
+            b = b.union(&sphereBBox(p, self.radius));
         }
+        b
     }
 }
 
 /// An infinite plane denoted by a point on the plane, and a normal
-/// vector. Does not have a bounding box.
+/// vector. Does not have a bounding box. The surface coordinates are
+/// the distances from `origin` along two arbitrary perpendicular
+/// directions on the plane.
 #[derive(Clone, Copy, Debug)]
 pub struct InfinitePlane
 {
@@ -88,8 +255,11 @@ impl Primitive for InfinitePlane
         let t = vec3::dot(&(self.origin - r.origin), &self.normal) / denomi;
         if t < t_max && t > t_min
         {
-            return Some(Hit { t: t, p: r.at(t), normal: self.normal,
-                              material: self.material });
+            let (tangent, bitangent) = vec3::basis(&self.normal);
+            let d = r.at(t) - self.origin;
+            return Some(Hit::new(r, t, self.normal, vec3::dot(&d, &tangent),
+                                 vec3::dot(&d, &bitangent), tangent, bitangent,
+                                 self.material));
         }
         else
         {
@@ -103,7 +273,7 @@ pub struct PrimitiveList
 {
     bounded: Vec<Arc<dyn BoundedPrimitive + Send + Sync>>,
     unbounded: Vec<Arc<dyn Primitive + Send + Sync>>,
-    bvh_tree: Arc<bvh::BvhNode>,
+    bvh_tree: Arc<BvhNode>,
     use_bvh: bool,
 }
 
@@ -114,7 +284,7 @@ impl PrimitiveList
     pub fn new(mut bounded: Vec<Arc<dyn BoundedPrimitive + Send + Sync>>,
                unbounded: Vec<Arc<dyn Primitive + Send + Sync>>) -> Self
     {
-        let tree = Arc::new(bvh::BvhNode::new(&mut bounded[..]));
+        let tree = Arc::new(BvhNode::new(&mut bounded[..]));
 
         Self {
             bounded: bounded, unbounded: unbounded, bvh_tree: tree,
@@ -185,3 +355,41 @@ impl BoundedPrimitive for PrimitiveList
         b
     }
 }
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn movingSphereIsHitWhereItIsAtTheTimeOfTheRay()
+    {
+        let s = MovingSphere {
+            center: Keyframes::linear(0.0, Vec3::new(0.0, 0.0, -2.0),
+                                      1.0, Vec3::new(2.0, 0.0, -2.0)),
+            radius: 0.5,
+            material: 0,
+        };
+        let at = |time| Ray::new(Vec3::origin(), Vec3::new(0.0, 0.0, -1.0), time);
+        let hit = s.intersect(&at(0.0), 0.0, Float::INFINITY).unwrap();
+        assert!((hit.t - 1.5).abs() < 1e-5);
+        assert!(s.intersect(&at(1.0), 0.0, Float::INFINITY).is_none());
+
+        let b = s.bbox();
+        assert!(b.lower[0] <= -0.5 && b.higher[0] >= 2.5);
+    }
+
+    #[test]
+    fn sphereDerivativesMatchSurfaceCoordinates()
+    {
+        let n = Vec3::unit(&Vec3::new(0.3, 0.4, -0.5));
+        let (u, v) = sphereUV(&n);
+        let (dpdu, dpdv) = sphereDerivatives(&n, 1.0);
+        let step = 1e-3;
+        let (u1, _) = sphereUV(&Vec3::unit(&(n + dpdu * step)));
+        let (_, v1) = sphereUV(&Vec3::unit(&(n + dpdv * step)));
+        assert!(((u1 - u) / step - 1.0).abs() < 1e-2);
+        assert!(((v1 - v) / step - 1.0).abs() < 1e-2);
+        assert!((sphereUV(&Vec3::new(0.0, 1.0, 0.0)).1 - 1.0).abs() < 1e-6);
+    }
+}
@@ -39,6 +39,30 @@ impl BBox
         true
     }
 
+    /// Return the part of ray `r` between ray time `tmin` and `tmax`
+    /// that is inside the bounding box, as (entry, exit) ray times.
+    pub fn clip(&self, r: &Ray, tmin: Float, tmax: Float) -> Option<(Float, Float)>
+    {
+        let mut t_enter = tmin;
+        let mut t_exit = tmax;
+        for i in 0..3
+        {
+            let dir_inverse: Float = 1.0 / r.dir[i];
+            let mut t0 = (self.lower[i] - r.origin[i]) * dir_inverse;
+            let mut t1 = (self.higher[i] - r.origin[i]) * dir_inverse;
+            if t0 > t1
+            {
+                mem::swap(&mut t0, &mut t1);
+            }
+            // NaN (a ray in the plane of a face) leaves the range as
+            // is.
+            if t0 > t_enter { t_enter = t0; }
+            if t1 < t_exit { t_exit = t1; }
+            if t_exit <= t_enter { return None; }
+        }
+        Some((t_enter, t_exit))
+    }
+
     /// Return the minimal bbox that can contain both this bbox and `rhs`.
     pub fn union(&self, rhs: &BBox) -> BBox
     {
@@ -54,6 +78,23 @@ impl BBox
         }
     }
 
+    /// Return a bbox that is at least `delta` thick along every
+    /// axis. A flat bbox (e.g. of an axis-aligned triangle) can never
+    /// be hit, because the entry and exit ray time would be equal.
+    pub fn padded(&self, delta: Float) -> BBox
+    {
+        let mut result = self.clone();
+        for i in 0..3
+        {
+            if result.higher[i] - result.lower[i] < delta
+            {
+                result.lower[i] -= delta * 0.5;
+                result.higher[i] += delta * 0.5;
+            }
+        }
+        result
+    }
+
 }
 
 /// All primitives should implement this trait.
@@ -74,3 +115,145 @@ pub trait BoundedPrimitive: Primitive
     /// Return the minimal bounding box of the primitive.
     fn bbox(&self) -> BBox;
 }
+
+/// Bounded primitives that directions toward them can be sampled for,
+/// so that they can be lights.
+pub trait SampledPrimitive: BoundedPrimitive
+{
+    /// Sample a unit direction from `reference` toward the primitive.
+    /// Return it and its density per solid angle.
+    fn sampleToward(&self, reference: &Vec3) -> Option<(Vec3, Float)>;
+
+    /// Return the density per solid angle of `sampleToward` from
+    /// `reference` choosing unit direction `dir`.
+    fn pdfToward(&self, reference: &Vec3, dir: &Vec3) -> Float;
+
+    /// Return the index of the material of the primitive.
+    fn material(&self) -> usize;
+
+    /// Return the area of the surface.
+    fn area(&self) -> Float;
+
+    /// Return a cone that bounds the normals of the surface, as its
+    /// unit axis and the cosine of its half angle.
+    fn normalBounds(&self) -> (Vec3, Float);
+}
+
+/// Convert the density of a point uniformly sampled on the surface of
+/// `obj`, which has `area`, to a density per solid angle at
+/// `reference`, for unit direction `dir`. It is 0 if `dir` misses.
+pub fn areaToSolidAngle<P: Primitive + ?Sized>(obj: &P, area: Float, reference: &Vec3,
+                                               dir: &Vec3) -> Float
+{
+    let r = Ray::new(*reference, *dir, 0.0);
+    match obj.intersect(&r, 0.0, Float::INFINITY)
+    {
+        Some(hit) =>
+        {
+            let cos = vec3::dot(dir, &hit.normal).abs();
+            if cos == 0.0 { 0.0 } else { hit.t * hit.t / (cos * area) }
+        },
+        None => 0.0,
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::config::PI;
+    use crate::geometry::{Sphere, Quad, Disk, TriangleMesh};
+
+    /// Integrate the density of directions from `reference` toward
+    /// `obj` over the unit sphere, by the midpoint rule.
+    fn integratePdf(obj: &dyn SampledPrimitive, reference: &Vec3) -> Float
+    {
+        let (n_theta, n_phi) = (400, 800);
+        let (d_theta, d_phi) = (PI / n_theta as Float, 2.0 * PI / n_phi as Float);
+        let mut total = 0.0;
+        for i in 0..n_theta
+        {
+            let theta = (i as Float + 0.5) * d_theta;
+            for j in 0..n_phi
+            {
+                let phi = (j as Float + 0.5) * d_phi;
+                let dir = Vec3::new(theta.sin() * phi.cos(), theta.cos(),
+                                    theta.sin() * phi.sin());
+                total += obj.pdfToward(reference, &dir) * theta.sin() * d_theta * d_phi;
+            }
+        }
+        total
+    }
+
+    /// Check that directions sampled from `reference` hit `obj`, with
+    /// the density that `pdfToward` gives.
+    fn checkSamples(obj: &dyn SampledPrimitive, reference: &Vec3)
+    {
+        for _ in 0..200
+        {
+            let (dir, pdf) = obj.sampleToward(reference).unwrap();
+            assert!((dir.norm() - 1.0).abs() < 1e-4);
+            assert!(obj.intersect(&Ray::new(*reference, dir, 0.0), 0.0, Float::INFINITY)
+                    .is_some());
+            let expected = obj.pdfToward(reference, &dir);
+            assert!((pdf - expected).abs() < 1e-2 * expected, "{} vs {}", pdf, expected);
+        }
+    }
+
+    fn shapes() -> Vec<Arc<dyn SampledPrimitive + Send + Sync>>
+    {
+        let mesh = Arc::new(TriangleMesh::new(
+            vec![Vec3::new(-1.0, 0.0, -1.0), Vec3::new(1.0, 0.0, -1.0),
+                 Vec3::new(0.0, 0.5, 1.0)], vec![[0, 1, 2]], 0));
+        let mut shapes: Vec<Arc<dyn SampledPrimitive + Send + Sync>> = vec![
+            Arc::new(Sphere { center: Vec3::new(0.0, 0.5, 0.0), radius: 1.0, material: 0 }),
+            Arc::new(Quad { origin: Vec3::new(-1.0, 0.0, -0.5), edge_u: Vec3::new(2.0, 0.0, 0.0),
+                            edge_v: Vec3::new(0.0, 0.5, 1.0), material: 0 }),
+            Arc::new(Disk::new(Vec3::origin(), Vec3::new(0.2, 1.0, 0.0), 1.2, 0)),
+        ];
+        shapes.extend(mesh.sampledTriangles());
+        shapes
+    }
+
+    #[test]
+    fn pdfTowardIntegratesToOne()
+    {
+        let reference = Vec3::new(0.5, 3.0, 1.0);
+        for obj in shapes()
+        {
+            let total = integratePdf(obj.as_ref(), &reference);
+            assert!((total - 1.0).abs() < 2e-2, "{}", total);
+        }
+        // From inside a sphere, the surface is sampled.
+        let sphere = Sphere { center: Vec3::origin(), radius: 1.0, material: 0 };
+        let total = integratePdf(&sphere, &Vec3::new(0.3, -0.2, 0.4));
+        assert!((total - 1.0).abs() < 2e-2, "{}", total);
+    }
+
+    #[test]
+    fn sampledDirectionsHitWithTheirDensity()
+    {
+        let reference = Vec3::new(0.5, 3.0, 1.0);
+        for obj in shapes()
+        {
+            checkSamples(obj.as_ref(), &reference);
+        }
+        let sphere = Sphere { center: Vec3::origin(), radius: 1.0, material: 0 };
+        checkSamples(&sphere, &Vec3::new(0.3, -0.2, 0.4));
+    }
+
+    #[test]
+    fn areaToSolidAngleIsZeroOnMisses()
+    {
+        let quad = Quad { origin: Vec3::origin(), edge_u: Vec3::new(1.0, 0.0, 0.0),
+                          edge_v: Vec3::new(0.0, 0.0, 1.0), material: 0 };
+        let up = Vec3::new(0.5, 2.0, 0.5);
+        assert_eq!(areaToSolidAngle(&quad, 1.0, &up, &Vec3::new(0.0, 1.0, 0.0)), 0.0);
+        // Straight down to the center, the density is the squared
+        // distance over the area.
+        let pdf = areaToSolidAngle(&quad, 1.0, &up, &Vec3::new(0.0, -1.0, 0.0));
+        assert!((pdf - 4.0).abs() < 1e-4);
+    }
+}
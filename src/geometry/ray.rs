@@ -1,16 +1,50 @@
-use super::vec3::Vec3;
+#![allow(clippy::redundant_field_names)]
+
+use super::vec3::{self, Vec3};
 use crate::config::Float;
+use crate::spectrum::Wavelengths;
 
 /// A linear ray in the form of `origin` + `dir` * t, where t is the
-/// “ray time”.
+/// “ray time”. Not to be confused with `time`, which is the moment
+/// during the exposure at which the ray is shot.
+#[derive(Clone)]
 pub struct Ray
 {
     pub origin: Vec3,
     pub dir: Vec3,
+    /// The moment within the shutter interval of the camera when the
+    /// ray exists. Moving primitives are positioned at this time.
+    pub time: Float,
+    /// Rays offset by one pixel in x and y on the film, if known.
+    /// This is used to estimate the footprint of the ray on surfaces.
+    pub differentials: Option<RayDifferential>,
+    /// The wavelengths carried by the path in spectral mode. Set by
+    /// the tracer; a material sets it on a scattered ray only to
+    /// change it.
+    pub wavelengths: Option<Wavelengths>,
+}
+
+/// The two rays next to a camera ray, shot from one pixel to the
+/// right and one pixel up on the film.
+#[derive(Clone, Copy, Debug)]
+pub struct RayDifferential
+{
+    pub rx_origin: Vec3,
+    pub rx_dir: Vec3,
+    pub ry_origin: Vec3,
+    pub ry_dir: Vec3,
 }
 
 impl Ray
 {
+    /// Construct a ray at a given moment `time`, without
+    /// differentials.
+    pub fn new(origin: Vec3, dir: Vec3, time: Float) -> Self
+    {
+        Self { origin: origin, dir: dir, time: time, differentials: None,
+               wavelengths: None }
+    }
+
     /// Calculate `origin` + `dir` * t at a given `t`.
     pub fn at(&self, t: Float) -> Vec3
     {
@@ -20,14 +54,236 @@ impl Ray
 
 /// An abstraction of the point where a ray intersects with some
 /// primitive.
+#[derive(Clone)]
 pub struct Hit
 {
     /// The ray time of the hit.
     pub t: Float,
     /// The coordinates of the intersection.
     pub p: Vec3,
-    /// The normal vector of the geometry at the intersection.
+    /// The normal vector of the geometry at the intersection. This
+    /// always points to the outside of the primitive, and is used to
+    /// offset rays and tell inside from outside.
     pub normal: Vec3,
+    /// The normal vector used for shading. It points to the same
+    /// side as `normal`, but may be interpolated (e.g. on meshes with
+    /// vertex normals) or perturbed (e.g. by a normal map).
+    pub shading_normal: Vec3,
+    /// A unit vector perpendicular to `shading_normal`, in the
+    /// direction where u increases.
+    pub tangent: Vec3,
+    /// `shading_normal` × `tangent`. Together with `tangent` and
+    /// `shading_normal`, this forms the tangent space.
+    pub bitangent: Vec3,
+    /// Whether the ray hits the outside of the primitive.
+    pub front_face: bool,
+    /// The surface coordinates of the intersection, usually in [0,
+    /// 1]. Unbounded primitives may have unbounded coordinates.
+    pub u: Float,
+    pub v: Float,
+    /// The partial derivatives of the position with respect to the
+    /// surface coordinates.
+    pub dpdu: Vec3,
+    pub dpdv: Vec3,
+    /// The change in surface coordinates from one pixel to the next
+    /// on the film, in x and y. These are all 0 if the ray does not
+    /// have differentials.
+    pub dudx: Float,
+    pub dudy: Float,
+    pub dvdx: Float,
+    pub dvdy: Float,
     /// The material index at the intersection.
     pub material: usize,
+    /// The index of the scene light whose surface was hit, if any.
+    pub light: Option<usize>,
+}
+
+impl Hit
+{
+    /// Construct the hit of ray `r` at ray time `t`, on a surface
+    /// with outward facing geometric `normal` (unit), and surface
+    /// coordinates (`u`, `v`). `dpdu` and `dpdv` are the partial
+    /// derivatives of the position with respect to u and v. If they
+    /// are degenerate (e.g. at the pole of a sphere), an arbitrary
+    /// tangent is used, and the surface coordinates do not change
+    /// across pixels.
+    // Every primitive passes the same surface description, and a
+    // struct for it would only repeat the fields of `Hit`.
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(r: &Ray, t: Float, normal: Vec3, u: Float, v: Float, dpdu: Vec3,
+               dpdv: Vec3, material: usize) -> Self
+    {
+        let mut hit = Self {
+            t: t,
+            p: r.at(t),
+            normal: normal,
+            shading_normal: normal,
+            tangent: Vec3::origin(),
+            bitangent: Vec3::origin(),
+            front_face: vec3::dot(&r.dir, &normal) < 0.0,
+            u: u,
+            v: v,
+            dpdu: dpdu,
+            dpdv: dpdv,
+            dudx: 0.0,
+            dudy: 0.0,
+            dvdx: 0.0,
+            dvdy: 0.0,
+            material: material,
+            light: None,
+        };
+        hit.setShadingFrame(normal, &dpdu);
+        if let Some(diff) = &r.differentials
+        {
+            hit.computeDifferentials(diff);
+        }
+        hit
+    }
+
+    /// Estimate the change of surface coordinates across pixels, by
+    /// intersecting the offset rays with the tangent plane at the
+    /// hit.
+    fn computeDifferentials(&mut self, diff: &RayDifferential)
+    {
+        let n = self.normal;
+        let d = vec3::dot(&n, &self.p);
+        let denomi_x = vec3::dot(&n, &diff.rx_dir);
+        let denomi_y = vec3::dot(&n, &diff.ry_dir);
+        if denomi_x == 0.0 || denomi_y == 0.0
+        {
+            return;
+        }
+        let tx = (d - vec3::dot(&n, &diff.rx_origin)) / denomi_x;
+        let ty = (d - vec3::dot(&n, &diff.ry_origin)) / denomi_y;
+        let dpdx = diff.rx_origin + diff.rx_dir * tx - self.p;
+        let dpdy = diff.ry_origin + diff.ry_dir * ty - self.p;
+
+        // Solve dpdx = dpdu * dudx + dpdv * dvdx (and the same for y)
+        // in the two axes where the surface is the least foreshortened.
+        let (a0, a1) = if n[0].abs() > n[1].abs() && n[0].abs() > n[2].abs() { (1, 2) }
+                       else if n[1].abs() > n[2].abs() { (0, 2) }
+                       else { (0, 1) };
+        let det = self.dpdu[a0] * self.dpdv[a1] - self.dpdv[a0] * self.dpdu[a1];
+        if det.abs() < 1e-12
+        {
+            return;
+        }
+        let solve = |b0: Float, b1: Float| -> (Float, Float) {
+            ((self.dpdv[a1] * b0 - self.dpdv[a0] * b1) / det,
+             (self.dpdu[a0] * b1 - self.dpdu[a1] * b0) / det)
+        };
+        let (dudx, dvdx) = solve(dpdx[a0], dpdx[a1]);
+        let (dudy, dvdy) = solve(dpdy[a0], dpdy[a1]);
+        if dudx.is_finite() && dvdx.is_finite() && dudy.is_finite() && dvdy.is_finite()
+        {
+            self.dudx = dudx;
+            self.dvdx = dvdx;
+            self.dudy = dudy;
+            self.dvdy = dvdy;
+        }
+    }
+
+    /// Set the shading normal (unit), and rebuild the tangent space
+    /// around it, with the tangent as close to `dpdu` as possible.
+    pub fn setShadingFrame(&mut self, shading_normal: Vec3, dpdu: &Vec3)
+    {
+        let n = shading_normal;
+        let tangent = *dpdu - n * vec3::dot(&n, dpdu);
+        let tangent_norm = tangent.norm();
+        self.tangent = if tangent_norm > 1e-6
+        {
+            tangent / tangent_norm
+        }
+        else
+        {
+            vec3::basis(&n).0
+        };
+        self.shading_normal = n;
+        self.bitangent = vec3::cross(&n, &self.tangent);
+    }
+
+    /// Set the shading normal (unit), keeping the tangent as close to
+    /// the current one as possible.
+    pub fn setShadingNormal(&mut self, shading_normal: Vec3)
+    {
+        let tangent = self.tangent;
+        self.setShadingFrame(shading_normal, &tangent);
+    }
+
+    /// Construct a ray from the hit in direction `dir`. The origin is
+    /// pushed off the surface along the geometric normal, to the side
+    /// `dir` goes to, so that the ray does not hit the same surface
+    /// again.
+    pub fn spawnRay(&self, dir: Vec3, time: Float) -> Ray
+    {
+        let offset = self.normal * (1e-4 * (1.0 + self.p.norm() * 1e-3));
+        let origin = if vec3::dot(&dir, &self.normal) > 0.0 { self.p + offset }
+                     else { self.p - offset };
+        Ray::new(origin, dir, time)
+    }
+
+    /// Return the shading normal flipped to the side of the surface
+    /// the ray comes from.
+    pub fn facingNormal(&self) -> Vec3
+    {
+        if self.front_face { self.shading_normal } else { -self.shading_normal }
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    fn assertNear(a: &Vec3, b: &Vec3)
+    {
+        assert!((*a - *b).norm() < 1e-5, "{:?} != {:?}", a, b);
+    }
+
+    #[test]
+    fn tangentSpaceIsOrthonormal()
+    {
+        let r = Ray::new(Vec3::new(0.0, 0.0, 1.0), Vec3::new(0.0, 0.0, -1.0), 0.0);
+        let normal = Vec3::new(0.0, 0.0, 1.0);
+        let mut hit = Hit::new(&r, 1.0, normal, 0.5, 0.5, Vec3::new(2.0, 0.0, 1.0),
+                               Vec3::new(0.0, 3.0, 0.0), 0);
+        assert!(hit.front_face);
+        assertNear(&hit.tangent, &Vec3::new(1.0, 0.0, 0.0));
+        assertNear(&hit.bitangent, &Vec3::new(0.0, 1.0, 0.0));
+
+        // Tilting the shading normal keeps the frame orthonormal, with
+        // the tangent close to the old one.
+        hit.setShadingNormal(Vec3::unit(&Vec3::new(1.0, 0.0, 1.0)));
+        assert!(vec3::dot(&hit.tangent, &hit.shading_normal).abs() < 1e-5);
+        assert!((hit.tangent.norm() - 1.0).abs() < 1e-5);
+        assertNear(&hit.bitangent, &vec3::cross(&hit.shading_normal, &hit.tangent));
+        assert!(hit.tangent[0] > 0.5);
+    }
+
+    #[test]
+    fn backFaceFlipsFacingNormal()
+    {
+        let r = Ray::new(Vec3::new(0.0, 0.0, -1.0), Vec3::new(0.0, 0.0, 1.0), 0.0);
+        let normal = Vec3::new(0.0, 0.0, 1.0);
+        let hit = Hit::new(&r, 1.0, normal, 0.0, 0.0, Vec3::new(1.0, 0.0, 0.0),
+                           Vec3::new(0.0, 1.0, 0.0), 0);
+        assert!(!hit.front_face);
+        assertNear(&hit.facingNormal(), &-normal);
+        assert!(hit.spawnRay(-normal, 0.0).origin[2] < 0.0);
+    }
+
+    #[test]
+    fn differentialsGiveSurfaceFootprint()
+    {
+        let mut r = Ray::new(Vec3::new(0.0, 0.0, 1.0), Vec3::new(0.0, 0.0, -1.0), 0.0);
+        r.differentials = Some(RayDifferential {
+            rx_origin: Vec3::new(0.1, 0.0, 1.0), rx_dir: r.dir,
+            ry_origin: Vec3::new(0.0, 0.1, 1.0), ry_dir: r.dir,
+        });
+        // u grows twice as fast as x on this surface.
+        let hit = Hit::new(&r, 1.0, Vec3::new(0.0, 0.0, 1.0), 0.0, 0.0,
+                           Vec3::new(0.5, 0.0, 0.0), Vec3::new(0.0, 1.0, 0.0), 0);
+        assert!((hit.dudx - 0.2).abs() < 1e-5 && hit.dvdx.abs() < 1e-5);
+        assert!(hit.dudy.abs() < 1e-5 && (hit.dvdy - 0.1).abs() < 1e-5);
+    }
 }
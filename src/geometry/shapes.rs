@@ -0,0 +1,873 @@
+//! Analytic primitives other than spheres and planes.
+
+use super::vec3::{self, Vec3};
+use crate::config::{Float, PI};
+use super::ray::{Ray, Hit};
+use crate::rand;
+use super::primitive_traits::{self, Primitive, BBox, BoundedPrimitive, SampledPrimitive};
+
+/// An orthonormal frame at `origin`, with `w` as the “up” axis.
+/// Oriented primitives are intersected in this frame, where their
+/// axis is the z axis. The frame is only rotated and translated, so
+/// ray time does not change between the two spaces.
+#[derive(Clone, Copy, Debug)]
+struct Frame
+{
+    origin: Vec3,
+    u: Vec3,
+    v: Vec3,
+    w: Vec3,
+}
+
+impl Frame
+{
+    fn new(origin: Vec3, axis: &Vec3) -> Self
+    {
+        let w = Vec3::unit(axis);
+        let (u, v) = vec3::basis(&w);
+        Self { origin, u, v, w }
+    }
+
+    /// Return the origin and direction of `r` in the frame.
+    fn localRay(&self, r: &Ray) -> (Vec3, Vec3)
+    {
+        let o = r.origin - self.origin;
+        (Vec3::new(vec3::dot(&o, &self.u), vec3::dot(&o, &self.v),
+                   vec3::dot(&o, &self.w)),
+         Vec3::new(vec3::dot(&r.dir, &self.u), vec3::dot(&r.dir, &self.v),
+                   vec3::dot(&r.dir, &self.w)))
+    }
+
+    fn toWorld(&self, local: &Vec3) -> Vec3
+    {
+        self.u * local[0] + self.v * local[1] + self.w * local[2]
+    }
+
+    /// Return the world space bbox of a local box from `lower` to
+    /// `higher`.
+    fn bbox(&self, lower: &Vec3, higher: &Vec3) -> BBox
+    {
+        let mut result: Option<BBox> = None;
+        for i in 0..8
+        {
+            let corner = Vec3::new(
+                if i & 1 == 0 { lower[0] } else { higher[0] },
+                if i & 2 == 0 { lower[1] } else { higher[1] },
+                if i & 4 == 0 { lower[2] } else { higher[2] });
+            let p = self.origin + self.toWorld(&corner);
+            let point_box = BBox { lower: p, higher: p };
+            result = Some(match result
+            {
+                Some(b) => b.union(&point_box),
+                None => point_box,
+            });
+        }
+        result.unwrap().padded(1e-4)
+    }
+}
+
+/// Return the angle of (`x`, `y`) around the origin, mapped to [0, 1).
+fn angleU(x: Float, y: Float) -> Float
+{
+    let phi = y.atan2(x);
+    if phi < 0.0 { (phi + 2.0 * PI) / (2.0 * PI) } else { phi / (2.0 * PI) }
+}
+
+/// The nearest intersection found so far, in the local frame of a
+/// primitive: (ray time, normal, dp/du, dp/dv, u, v).
+type LocalHit = Option<(Float, Vec3, Vec3, Vec3, Float, Float)>;
+
+fn closer(best: LocalHit, t: Float, normal: Vec3, dpdu: Vec3, dpdv: Vec3,
+          u: Float, v: Float) -> LocalHit
+{
+    match best
+    {
+        Some(b) if b.0 <= t => best,
+        _ => Some((t, normal, dpdu, dpdv, u, v)),
+    }
+}
+
+/// Return dp/du at (`x`, `y`) when u is from `angleU`.
+fn aroundZ(x: Float, y: Float) -> Vec3
+{
+    Vec3::new(-y, x, 0.0) * (2.0 * PI)
+}
+
+/// Return the unit vector pointing away from the z axis at (`x`,
+/// `y`), or 0 on the axis.
+fn awayFromZ(x: Float, y: Float) -> Vec3
+{
+    let rho = (x * x + y * y).sqrt();
+    if rho > 0.0 { Vec3::new(x / rho, y / rho, 0.0) } else { Vec3::origin() }
+}
+
+/// Intersect a local ray with the disk of `radius` at height `z` in
+/// the frame. The normal points to +z if `up`, otherwise -z.
+fn intersectLocalDisk(o: &Vec3, d: &Vec3, z: Float, radius: Float, up: bool,
+                      t_min: Float, t_max: Float) -> LocalHit
+{
+    if d[2] == 0.0
+    {
+        return None;
+    }
+    let t = (z - o[2]) / d[2];
+    if !(t < t_max && t > t_min)
+    {
+        return None;
+    }
+    let x = o[0] + d[0] * t;
+    let y = o[1] + d[1] * t;
+    let r2 = x * x + y * y;
+    if r2 > radius * radius
+    {
+        return None;
+    }
+    let normal = if up { Vec3::new(0.0, 0.0, 1.0) } else { Vec3::new(0.0, 0.0, -1.0) };
+    Some((t, normal, aroundZ(x, y), awayFromZ(x, y) * radius, angleU(x, y),
+          r2.sqrt() / radius))
+}
+
+/// A parallelogram spanned by `edge_u` and `edge_v` at corner
+/// `origin`. The surface coordinates go from 0 to 1 along the two
+/// edges. The normal is `edge_u` × `edge_v`.
+#[derive(Clone, Copy, Debug)]
+pub struct Quad
+{
+    pub origin: Vec3,
+    pub edge_u: Vec3,
+    pub edge_v: Vec3,
+    pub material: usize,
+}
+
+impl Quad
+{
+    /// Return the unit normal.
+    pub fn normal(&self) -> Vec3
+    {
+        Vec3::unit(&vec3::cross(&self.edge_u, &self.edge_v))
+    }
+}
+
+impl Primitive for Quad
+{
+    fn intersect(&self, r: &Ray, t_min: Float, t_max: Float) -> Option<Hit>
+    {
+        let n = vec3::cross(&self.edge_u, &self.edge_v);
+        let denomi = vec3::dot(&n, &r.dir);
+        if denomi == 0.0
+        {
+            return None;
+        }
+        let t = vec3::dot(&(self.origin - r.origin), &n) / denomi;
+        if !(t < t_max && t > t_min)
+        {
+            return None;
+        }
+        let p = r.at(t);
+        let planar = p - self.origin;
+        let w = n / n.normSquared();
+        let u = vec3::dot(&w, &vec3::cross(&planar, &self.edge_v));
+        let v = vec3::dot(&w, &vec3::cross(&self.edge_u, &planar));
+        if !(0.0..=1.0).contains(&u) || !(0.0..=1.0).contains(&v)
+        {
+            return None;
+        }
+        Some(Hit::new(r, t, Vec3::unit(&n), u, v, self.edge_u, self.edge_v,
+                      self.material))
+    }
+}
+
+impl BoundedPrimitive for Quad
+{
+    fn bbox(&self) -> BBox
+    {
+        let p0 = self.origin;
+        let p1 = self.origin + self.edge_u;
+        let p2 = self.origin + self.edge_v;
+        let p3 = p1 + self.edge_v;
+        BBox { lower: p0, higher: p0 }
+            .union(&BBox { lower: p1, higher: p1 })
+            .union(&BBox { lower: p2, higher: p2 })
+            .union(&BBox { lower: p3, higher: p3 })
+            .padded(1e-4)
+    }
+}
+
+impl SampledPrimitive for Quad
+{
+    /// Sample the surface uniformly.
+    fn sampleToward(&self, reference: &Vec3) -> Option<(Vec3, Float)>
+    {
+        let q = self.origin + self.edge_u * rand::random::<Float>() +
+            self.edge_v * rand::random::<Float>();
+        let d = q - *reference;
+        let distance2 = d.normSquared();
+        if distance2 == 0.0
+        {
+            return None;
+        }
+        let dir = d / distance2.sqrt();
+        let cos = vec3::dot(&dir, &self.normal()).abs();
+        if cos == 0.0
+        {
+            return None;
+        }
+        Some((dir, distance2 / (cos * self.area())))
+    }
+
+    fn pdfToward(&self, reference: &Vec3, dir: &Vec3) -> Float
+    {
+        primitive_traits::areaToSolidAngle(self, self.area(), reference, dir)
+    }
+
+    fn material(&self) -> usize
+    {
+        self.material
+    }
+
+    fn area(&self) -> Float
+    {
+        vec3::cross(&self.edge_u, &self.edge_v).norm()
+    }
+
+    fn normalBounds(&self) -> (Vec3, Float)
+    {
+        (self.normal(), 1.0)
+    }
+}
+
+/// A solid box aligned with the axes. Rotate it with an `Instance`.
+/// On every face, the surface coordinates go from 0 to 1 along the
+/// two other axes.
+#[derive(Clone, Copy, Debug)]
+pub struct AxisBox
+{
+    pub lower: Vec3,
+    pub higher: Vec3,
+    pub material: usize,
+}
+
+impl Primitive for AxisBox
+{
+    fn intersect(&self, r: &Ray, t_min: Float, t_max: Float) -> Option<Hit>
+    {
+        // Slab test, keeping track of which axis the entry and exit
+        // are at.
+        let mut t_near = Float::NEG_INFINITY;
+        let mut t_far = Float::INFINITY;
+        let mut axis_near = 0;
+        let mut axis_far = 0;
+        for i in 0..3
+        {
+            let dir_inverse = 1.0 / r.dir[i];
+            let mut t0 = (self.lower[i] - r.origin[i]) * dir_inverse;
+            let mut t1 = (self.higher[i] - r.origin[i]) * dir_inverse;
+            if t0 > t1
+            {
+                std::mem::swap(&mut t0, &mut t1);
+            }
+            if t0 > t_near
+            {
+                t_near = t0;
+                axis_near = i;
+            }
+            if t1 < t_far
+            {
+                t_far = t1;
+                axis_far = i;
+            }
+            if t_far < t_near
+            {
+                return None;
+            }
+        }
+
+        let (t, axis) = if t_near < t_max && t_near > t_min
+        {
+            (t_near, axis_near)
+        }
+        else if t_far < t_max && t_far > t_min
+        {
+            (t_far, axis_far)
+        }
+        else
+        {
+            return None;
+        };
+
+        let p = r.at(t);
+        let mut normal = Vec3::origin();
+        let center = (self.lower[axis] + self.higher[axis]) * 0.5;
+        normal[axis] = if p[axis] > center { 1.0 } else { -1.0 };
+        let a = (axis + 1) % 3;
+        let b = (axis + 2) % 3;
+        let mut dpdu = Vec3::origin();
+        dpdu[a] = self.higher[a] - self.lower[a];
+        let mut dpdv = Vec3::origin();
+        dpdv[b] = self.higher[b] - self.lower[b];
+        Some(Hit::new(r, t, normal,
+                      (p[a] - self.lower[a]) / (self.higher[a] - self.lower[a]),
+                      (p[b] - self.lower[b]) / (self.higher[b] - self.lower[b]),
+                      dpdu, dpdv, self.material))
+    }
+}
+
+impl BoundedPrimitive for AxisBox
+{
+    fn bbox(&self) -> BBox
+    {
+        BBox { lower: self.lower, higher: self.higher }.padded(1e-4)
+    }
+}
+
+/// A disk with a center, a normal and a radius. u goes around the
+/// normal, v goes from the center (0) to the rim (1).
+#[derive(Clone, Copy, Debug)]
+pub struct Disk
+{
+    frame: Frame,
+    pub radius: Float,
+    pub material: usize,
+}
+
+impl Disk
+{
+    pub fn new(center: Vec3, normal: Vec3, radius: Float, material: usize) -> Self
+    {
+        Self { frame: Frame::new(center, &normal), radius, material }
+    }
+
+    pub fn center(&self) -> Vec3
+    {
+        self.frame.origin
+    }
+
+    pub fn normal(&self) -> Vec3
+    {
+        self.frame.w
+    }
+}
+
+impl Primitive for Disk
+{
+    fn intersect(&self, r: &Ray, t_min: Float, t_max: Float) -> Option<Hit>
+    {
+        let (o, d) = self.frame.localRay(r);
+        let (t, _, dpdu, dpdv, u, v) = intersectLocalDisk(
+            &o, &d, 0.0, self.radius, true, t_min, t_max)?;
+        Some(Hit::new(r, t, self.frame.w, u, v, self.frame.toWorld(&dpdu),
+                      self.frame.toWorld(&dpdv), self.material))
+    }
+}
+
+impl BoundedPrimitive for Disk
+{
+    fn bbox(&self) -> BBox
+    {
+        let r = self.radius;
+        self.frame.bbox(&Vec3::new(-r, -r, 0.0), &Vec3::new(r, r, 0.0))
+    }
+}
+
+impl SampledPrimitive for Disk
+{
+    /// Sample the surface uniformly.
+    fn sampleToward(&self, reference: &Vec3) -> Option<(Vec3, Float)>
+    {
+        let rho = self.radius * rand::random::<Float>().sqrt();
+        let phi = 2.0 * PI * rand::random::<Float>();
+        let q = self.center() +
+            self.frame.toWorld(&Vec3::new(rho * phi.cos(), rho * phi.sin(), 0.0));
+        let d = q - *reference;
+        let distance2 = d.normSquared();
+        if distance2 == 0.0
+        {
+            return None;
+        }
+        let dir = d / distance2.sqrt();
+        let cos = vec3::dot(&dir, &self.normal()).abs();
+        if cos == 0.0
+        {
+            return None;
+        }
+        Some((dir, distance2 / (cos * self.area())))
+    }
+
+    fn pdfToward(&self, reference: &Vec3, dir: &Vec3) -> Float
+    {
+        primitive_traits::areaToSolidAngle(self, self.area(), reference, dir)
+    }
+
+    fn material(&self) -> usize
+    {
+        self.material
+    }
+
+    fn area(&self) -> Float
+    {
+        PI * self.radius * self.radius
+    }
+
+    fn normalBounds(&self) -> (Vec3, Float)
+    {
+        (self.normal(), 1.0)
+    }
+}
+
+/// A cylinder standing on a base center, along an axis. It is open
+/// unless `capped`. On the side, u goes around the axis, v goes from
+/// the base (0) to the top (1). On the caps, the coordinates are
+/// those of a `Disk`.
+#[derive(Clone, Copy, Debug)]
+pub struct Cylinder
+{
+    frame: Frame,
+    pub radius: Float,
+    pub height: Float,
+    pub capped: bool,
+    pub material: usize,
+}
+
+impl Cylinder
+{
+    pub fn new(base: Vec3, axis: Vec3, radius: Float, height: Float, capped: bool,
+               material: usize) -> Self
+    {
+        Self { frame: Frame::new(base, &axis), radius, height, capped, material }
+    }
+}
+
+impl Primitive for Cylinder
+{
+    fn intersect(&self, r: &Ray, t_min: Float, t_max: Float) -> Option<Hit>
+    {
+        let (o, d) = self.frame.localRay(r);
+        let mut best: LocalHit = None;
+
+        let a = d[0] * d[0] + d[1] * d[1];
+        if a != 0.0
+        {
+            let b = o[0] * d[0] + o[1] * d[1];
+            let c = o[0] * o[0] + o[1] * o[1] - self.radius * self.radius;
+            let discriminant = b * b - a * c;
+            if discriminant > 0.0
+            {
+                for t in [(-b - discriminant.sqrt()) / a, (-b + discriminant.sqrt()) / a]
+                {
+                    let z = o[2] + d[2] * t;
+                    if t < t_max && t > t_min && z >= 0.0 && z <= self.height
+                    {
+                        let x = o[0] + d[0] * t;
+                        let y = o[1] + d[1] * t;
+                        best = closer(best, t, Vec3::new(x, y, 0.0) / self.radius,
+                                      aroundZ(x, y), Vec3::new(0.0, 0.0, self.height),
+                                      angleU(x, y), z / self.height);
+                    }
+                }
+            }
+        }
+        if self.capped
+        {
+            if let Some((t, n, dpdu, dpdv, u, v)) = intersectLocalDisk(
+                &o, &d, 0.0, self.radius, false, t_min, t_max)
+            {
+                best = closer(best, t, n, dpdu, dpdv, u, v);
+            }
+            if let Some((t, n, dpdu, dpdv, u, v)) = intersectLocalDisk(
+                &o, &d, self.height, self.radius, true, t_min, t_max)
+            {
+                best = closer(best, t, n, dpdu, dpdv, u, v);
+            }
+        }
+
+        let (t, normal, dpdu, dpdv, u, v) = best?;
+        Some(Hit::new(r, t, self.frame.toWorld(&normal), u, v,
+                      self.frame.toWorld(&dpdu), self.frame.toWorld(&dpdv),
+                      self.material))
+    }
+}
+
+impl BoundedPrimitive for Cylinder
+{
+    fn bbox(&self) -> BBox
+    {
+        let r = self.radius;
+        self.frame.bbox(&Vec3::new(-r, -r, 0.0), &Vec3::new(r, r, self.height))
+    }
+}
+
+/// A cone standing on a base center, along an axis, with its apex at
+/// `height` above the base. It is open unless `capped`. The surface
+/// coordinates are like those of a `Cylinder`.
+#[derive(Clone, Copy, Debug)]
+pub struct Cone
+{
+    frame: Frame,
+    pub radius: Float,
+    pub height: Float,
+    pub capped: bool,
+    pub material: usize,
+}
+
+impl Cone
+{
+    pub fn new(base: Vec3, axis: Vec3, radius: Float, height: Float, capped: bool,
+               material: usize) -> Self
+    {
+        Self { frame: Frame::new(base, &axis), radius, height, capped, material }
+    }
+}
+
+impl Primitive for Cone
+{
+    fn intersect(&self, r: &Ray, t_min: Float, t_max: Float) -> Option<Hit>
+    {
+        let (o, d) = self.frame.localRay(r);
+        let mut best: LocalHit = None;
+
+        // The side is x² + y² = k²(h - z)².
+        let k = self.radius / self.height;
+        let k2 = k * k;
+        let h_oz = self.height - o[2];
+        let a = d[0] * d[0] + d[1] * d[1] - k2 * d[2] * d[2];
+        let b = o[0] * d[0] + o[1] * d[1] + k2 * h_oz * d[2];
+        let c = o[0] * o[0] + o[1] * o[1] - k2 * h_oz * h_oz;
+        let roots = if a == 0.0
+        {
+            if b == 0.0 { [None, None] } else { [Some(-c / (2.0 * b)), None] }
+        }
+        else
+        {
+            let discriminant = b * b - a * c;
+            if discriminant >= 0.0
+            {
+                [Some((-b - discriminant.sqrt()) / a),
+                 Some((-b + discriminant.sqrt()) / a)]
+            }
+            else
+            {
+                [None, None]
+            }
+        };
+        for t in roots.iter().flatten()
+        {
+            let t = *t;
+            let z = o[2] + d[2] * t;
+            if t < t_max && t > t_min && z >= 0.0 && z <= self.height
+            {
+                let x = o[0] + d[0] * t;
+                let y = o[1] + d[1] * t;
+                let normal = Vec3::unit(&Vec3::new(x, y, k2 * (self.height - z)));
+                // Going up the side, the point also moves to the axis.
+                let dpdv = (awayFromZ(x, y) * -k + Vec3::new(0.0, 0.0, 1.0)) *
+                    self.height;
+                best = closer(best, t, normal, aroundZ(x, y), dpdv, angleU(x, y),
+                              z / self.height);
+            }
+        }
+        if self.capped
+        {
+            if let Some((t, n, dpdu, dpdv, u, v)) = intersectLocalDisk(
+                &o, &d, 0.0, self.radius, false, t_min, t_max)
+            {
+                best = closer(best, t, n, dpdu, dpdv, u, v);
+            }
+        }
+
+        let (t, normal, dpdu, dpdv, u, v) = best?;
+        Some(Hit::new(r, t, self.frame.toWorld(&normal), u, v,
+                      self.frame.toWorld(&dpdu), self.frame.toWorld(&dpdv),
+                      self.material))
+    }
+}
+
+impl BoundedPrimitive for Cone
+{
+    fn bbox(&self) -> BBox
+    {
+        let r = self.radius;
+        self.frame.bbox(&Vec3::new(-r, -r, 0.0), &Vec3::new(r, r, self.height))
+    }
+}
+
+fn polyEval(coeffs: &[f64], x: f64) -> f64
+{
+    coeffs.iter().rev().fold(0.0, |acc, c| acc * x + c)
+}
+
+/// Return the real roots of a polynomial between `lo` and `hi` in
+/// increasing order. `coeffs[i]` is the coefficient of xⁱ. The roots
+/// of the derivative split the range into monotonic pieces, where
+/// each root is found by bisection. Roots of even multiplicity are
+/// only found if they land exactly on a piece boundary.
+fn polyRoots(coeffs: &[f64], lo: f64, hi: f64) -> Vec<f64>
+{
+    let mut n = coeffs.len();
+    while n > 1 && coeffs[n-1] == 0.0
+    {
+        n -= 1;
+    }
+    let coeffs = &coeffs[..n];
+    if n <= 1
+    {
+        return Vec::new();
+    }
+    if n == 2
+    {
+        let x = -coeffs[0] / coeffs[1];
+        return if x >= lo && x <= hi { vec![x] } else { Vec::new() };
+    }
+
+    let derivative: Vec<f64> = (1..n).map(|i| coeffs[i] * i as f64).collect();
+    let mut bounds = vec![lo];
+    bounds.extend(polyRoots(&derivative, lo, hi));
+    bounds.push(hi);
+
+    let mut roots = Vec::new();
+    for piece in bounds.windows(2)
+    {
+        let (mut a, mut b) = (piece[0], piece[1]);
+        let mut fa = polyEval(coeffs, a);
+        let fb = polyEval(coeffs, b);
+        if fa == 0.0
+        {
+            roots.push(a);
+            continue;
+        }
+        if (fa < 0.0) == (fb < 0.0)
+        {
+            continue;
+        }
+        for _ in 0..64
+        {
+            let m = 0.5 * (a + b);
+            let fm = polyEval(coeffs, m);
+            if (fm < 0.0) == (fa < 0.0)
+            {
+                a = m;
+                fa = fm;
+            }
+            else
+            {
+                b = m;
+            }
+        }
+        roots.push(0.5 * (a + b));
+    }
+    roots
+}
+
+/// A torus around an axis through `center`. `major_radius` is the
+/// distance from the center to the center of the tube, and
+/// `minor_radius` is the radius of the tube. u goes around the axis,
+/// v goes around the tube.
+#[derive(Clone, Copy, Debug)]
+pub struct Torus
+{
+    frame: Frame,
+    pub major_radius: Float,
+    pub minor_radius: Float,
+    pub material: usize,
+}
+
+impl Torus
+{
+    pub fn new(center: Vec3, axis: Vec3, major_radius: Float, minor_radius: Float,
+               material: usize) -> Self
+    {
+        Self { frame: Frame::new(center, &axis), major_radius, minor_radius, material }
+    }
+
+    fn localBBox(&self) -> BBox
+    {
+        let extent = self.major_radius + self.minor_radius;
+        BBox { lower: Vec3::new(-extent, -extent, -self.minor_radius),
+               higher: Vec3::new(extent, extent, self.minor_radius) }
+    }
+}
+
+impl Primitive for Torus
+{
+    fn intersect(&self, r: &Ray, t_min: Float, t_max: Float) -> Option<Hit>
+    {
+        let (o, d) = self.frame.localRay(r);
+        let local_box = self.localBBox();
+        let local_ray = Ray::new(o, d, r.time);
+        if !local_box.hit(&local_ray, t_min, t_max)
+        {
+            return None;
+        }
+
+        // Solve along a unit direction in double precision. The
+        // parameter s along it is |d| times the ray time.
+        let d_norm = d.norm() as f64;
+        let (ox, oy, oz) = (o[0] as f64, o[1] as f64, o[2] as f64);
+        let (dx, dy, dz) = (d[0] as f64 / d_norm, d[1] as f64 / d_norm,
+                            d[2] as f64 / d_norm);
+        let big_r2 = (self.major_radius as f64).powi(2);
+        let small_r2 = (self.minor_radius as f64).powi(2);
+
+        // (|p|² + R² - r²)² = 4R²(x² + y²), with p = o + s d.
+        let od = ox * dx + oy * dy + oz * dz;
+        let g = ox * ox + oy * oy + oz * oz + big_r2 - small_r2;
+        let coeffs = [
+            g * g - 4.0 * big_r2 * (ox * ox + oy * oy),
+            4.0 * od * g - 8.0 * big_r2 * (ox * dx + oy * dy),
+            4.0 * od * od + 2.0 * g - 4.0 * big_r2 * (dx * dx + dy * dy),
+            4.0 * od,
+            1.0,
+        ];
+
+        // Only search inside the local bbox.
+        let mut lo = t_min as f64 * d_norm;
+        let mut hi = t_max as f64 * d_norm;
+        for i in 0..3
+        {
+            let (oi, di) = ([ox, oy, oz][i], [dx, dy, dz][i]);
+            let l = local_box.lower[i] as f64;
+            let h = local_box.higher[i] as f64;
+            if di == 0.0
+            {
+                continue;
+            }
+            let (s0, s1) = if di > 0.0 { ((l - oi) / di, (h - oi) / di) }
+                           else { ((h - oi) / di, (l - oi) / di) };
+            lo = lo.max(s0);
+            hi = hi.min(s1);
+        }
+        if hi <= lo
+        {
+            return None;
+        }
+
+        let s = polyRoots(&coeffs, lo, hi).into_iter()
+            .find(|s| *s > t_min as f64 * d_norm)?;
+        let t = (s / d_norm) as Float;
+        if !(t < t_max && t > t_min)
+        {
+            return None;
+        }
+
+        let p = Vec3::new((ox + dx * s) as Float, (oy + dy * s) as Float,
+                          (oz + dz * s) as Float);
+        let big_r = self.major_radius;
+        let ring = (p[0] * p[0] + p[1] * p[1]).sqrt();
+        // The normal points from the center of the tube to p.
+        let tube_center = if ring > 0.0
+        {
+            Vec3::new(p[0], p[1], 0.0) * (big_r / ring)
+        }
+        else
+        {
+            Vec3::origin()
+        };
+        let normal = Vec3::unit(&(p - tube_center));
+        // Going around the tube, the point moves perpendicular to the
+        // normal in the plane of the axis.
+        let dpdv = vec3::cross(&normal, &Vec3::new(-p[1], p[0], 0.0));
+        let dpdv = if dpdv.normSquared() > 0.0
+        {
+            Vec3::unit(&dpdv) * (2.0 * PI * self.minor_radius)
+        }
+        else
+        {
+            dpdv
+        };
+        Some(Hit::new(r, t, self.frame.toWorld(&normal), angleU(p[0], p[1]),
+                      angleU(ring - big_r, p[2]),
+                      self.frame.toWorld(&aroundZ(p[0], p[1])),
+                      self.frame.toWorld(&dpdv), self.material))
+    }
+}
+
+impl BoundedPrimitive for Torus
+{
+    fn bbox(&self) -> BBox
+    {
+        let b = self.localBBox();
+        self.frame.bbox(&b.lower, &b.higher)
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    fn down(x: Float, y: Float) -> Ray
+    {
+        Ray::new(Vec3::new(x, y, 5.0), Vec3::new(0.0, 0.0, -1.0), 0.0)
+    }
+
+    #[test]
+    fn polyRootsFindsAllRootsOfQuartic()
+    {
+        // (x + 2)(x - 0.5)(x - 1)(x - 3)
+        let coeffs = [-3.0, 8.5, -4.0, -2.5, 1.0];
+        let roots = polyRoots(&coeffs, -10.0, 10.0);
+        assert_eq!(roots.len(), 4);
+        for (root, expected) in roots.iter().zip([-2.0, 0.5, 1.0, 3.0].iter())
+        {
+            assert!((root - expected).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn torusIsHitOnTubeAndMissedInHole()
+    {
+        let torus = Torus::new(Vec3::origin(), Vec3::new(0.0, 0.0, 1.0), 1.0, 0.25, 0);
+        let hit = torus.intersect(&down(1.0, 0.0), 0.0, Float::INFINITY).unwrap();
+        assert!((hit.t - 4.75).abs() < 1e-4);
+        assert!((hit.normal - Vec3::new(0.0, 0.0, 1.0)).norm() < 1e-3);
+        assert!(torus.intersect(&down(0.0, 0.0), 0.0, Float::INFINITY).is_none());
+
+        // Sideways, through both sides of the tube.
+        let r = Ray::new(Vec3::new(-5.0, 0.0, 0.0), Vec3::new(1.0, 0.0, 0.0), 0.0);
+        let hit = torus.intersect(&r, 0.0, Float::INFINITY).unwrap();
+        assert!((hit.t - 3.75).abs() < 1e-4);
+        let hit = torus.intersect(&r, 3.8, Float::INFINITY).unwrap();
+        assert!((hit.t - 4.25).abs() < 1e-4);
+        let hit = torus.intersect(&r, 4.5, Float::INFINITY).unwrap();
+        assert!((hit.t - 5.75).abs() < 1e-4);
+    }
+
+    #[test]
+    fn quadHasUnitSurfaceCoordinates()
+    {
+        let quad = Quad { origin: Vec3::new(0.0, 0.0, 0.0), edge_u: Vec3::new(2.0, 0.0, 0.0),
+                          edge_v: Vec3::new(0.0, 4.0, 0.0), material: 0 };
+        let hit = quad.intersect(&down(0.5, 3.0), 0.0, Float::INFINITY).unwrap();
+        assert!((hit.u - 0.25).abs() < 1e-5 && (hit.v - 0.75).abs() < 1e-5);
+        assert!(quad.intersect(&down(2.5, 3.0), 0.0, Float::INFINITY).is_none());
+    }
+
+    #[test]
+    fn boxNormalFacesOutOfEnteredSide()
+    {
+        let b = AxisBox { lower: Vec3::new(-1.0, -1.0, -1.0), higher: Vec3::new(1.0, 1.0, 1.0),
+                          material: 0 };
+        let hit = b.intersect(&down(0.5, 0.5), 0.0, Float::INFINITY).unwrap();
+        assert!((hit.t - 4.0).abs() < 1e-5);
+        assert!((hit.normal - Vec3::new(0.0, 0.0, 1.0)).norm() < 1e-5);
+    }
+
+    #[test]
+    fn cylinderCapsOnlyWhenCapped()
+    {
+        let z = Vec3::new(0.0, 0.0, 1.0);
+        let capped = Cylinder::new(Vec3::origin(), z, 1.0, 2.0, true, 0);
+        let open = Cylinder::new(Vec3::origin(), z, 1.0, 2.0, false, 0);
+        let hit = capped.intersect(&down(0.5, 0.0), 0.0, Float::INFINITY).unwrap();
+        assert!((hit.t - 3.0).abs() < 1e-5);
+        assert!(open.intersect(&down(0.5, 0.0), 0.0, Float::INFINITY).is_none());
+
+        let cone = Cone::new(Vec3::origin(), z, 1.0, 2.0, false, 0);
+        // At half the radius, the side of the cone is at half height.
+        let hit = cone.intersect(&down(0.5, 0.0), 0.0, Float::INFINITY).unwrap();
+        assert!((hit.t - 4.0).abs() < 1e-4);
+
+        let disk = Disk::new(Vec3::origin(), z, 1.0, 0);
+        assert!(disk.intersect(&down(0.0, 0.9), 0.0, Float::INFINITY).is_some());
+        assert!(disk.intersect(&down(0.8, 0.8), 0.0, Float::INFINITY).is_none());
+    }
+}
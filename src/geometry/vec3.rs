@@ -1,3 +1,5 @@
+#![allow(clippy::needless_return)]
+
 use std::ops;
 
 use rand;
@@ -215,6 +217,15 @@ impl Vec3
             }
         }
     }
+
+    /// Construct a random unit vector in the hemisphere around +z,
+    /// with density proportional to its z.
+    pub fn randCosineHemisphere() -> Vec3
+    {
+        let d = Vec3::randInUnitDisk();
+        let z = (1.0 - d.normSquared()).max(0.0).sqrt();
+        Vec3::new(d[0], d[1], z)
+    }
 }
 
 impl ops::Index<usize> for Vec3
@@ -229,7 +240,7 @@ impl ops::Index<usize> for Vec3
 
 impl ops::IndexMut<usize> for Vec3
 {
-    fn index_mut<'a>(&'a mut self, i: usize) -> &'a mut Self::Output
+    fn index_mut(&mut self, i: usize) -> &mut Self::Output
     {
         &mut self.data[i]
     }
@@ -248,3 +259,13 @@ pub fn cross(lhs: &Vec3, rhs: &Vec3) -> Vec3
               lhs[2] * rhs[0] - lhs[0] * rhs[2],
               lhs[0] * rhs[1] - lhs[1] * rhs[0])
 }
+
+/// Return two unit vectors that form an orthonormal basis together
+/// with unit vector `n`.
+pub fn basis(n: &Vec3) -> (Vec3, Vec3)
+{
+    let a = if n[0].abs() > 0.9 { Vec3::new(0.0, 1.0, 0.0) }
+            else { Vec3::new(1.0, 0.0, 0.0) };
+    let t = Vec3::unit(&cross(&a, n));
+    (t, cross(n, &t))
+}
@@ -0,0 +1,18 @@
+#![allow(non_snake_case)]
+
+extern crate image;
+extern crate rand;
+extern crate rayon;
+
+pub mod config;
+pub mod geometry;
+pub mod texture;
+pub mod material;
+pub mod medium;
+pub mod light;
+pub mod spectrum;
+pub mod scene;
+pub mod render;
+pub mod ref_scene_1;
+pub mod ref_scene_2;
+pub mod ref_scene_3;
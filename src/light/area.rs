@@ -0,0 +1,193 @@
+use std::sync::Arc;
+
+use crate::config::{Float, PI};
+use crate::geometry::{Vec3, Ray, Hit, BBox, Primitive, BoundedPrimitive, SampledPrimitive,
+                      TriangleMesh};
+use crate::material::AnyMaterial;
+use crate::texture;
+use super::source::{Light, LightSample, AnyLight};
+use super::bvh::LightBounds;
+
+/// The number of samples of the emitted radiance to estimate the power
+/// of an area light.
+const POWER_SAMPLES: usize = 16;
+
+/// A primitive with an emissive material (e.g. `DiffuseLight`) as a
+/// light. Use `add` to also get the primitive to put in the scene, so
+/// that rays can hit it.
+pub struct AreaLight
+{
+    shape: Arc<dyn SampledPrimitive + Send + Sync>,
+    material: AnyMaterial,
+    /// The largest luminance emitted, as far as it was sampled.
+    luminance: Float,
+}
+
+impl AreaLight
+{
+    /// Construct a light of `shape`, which uses one of the scene
+    /// `materials`.
+    fn new(shape: Arc<dyn SampledPrimitive + Send + Sync>,
+               materials: &[AnyMaterial]) -> Self
+    {
+        let material = materials[shape.material()].clone();
+        let mut light = Self { shape, material, luminance: 0.0 };
+        light.luminance = light.sampleLuminance();
+        light
+    }
+
+    /// Sample the surface from points all around it, and return the
+    /// largest luminance emitted toward them.
+    fn sampleLuminance(&self) -> Float
+    {
+        let bbox = self.shape.bbox();
+        let center = (bbox.lower + bbox.higher) * 0.5;
+        let radius = (bbox.higher - bbox.lower).norm() + 1.0;
+        let mut luminance: Float = 0.0;
+        for _ in 0..POWER_SAMPLES
+        {
+            let p = center + Vec3::unit(&Vec3::randInUnitSphere()) * radius;
+            if let Some(sample) = self.sample(&p)
+            {
+                luminance = luminance.max(texture::luminance(&(sample.radiance * sample.pdf)));
+            }
+        }
+        luminance
+    }
+
+    /// Add a light of `shape`, which uses one of the scene
+    /// `materials`, to `lights`. Return the primitive to put in the
+    /// scene for it, whose hits tell which light they are on.
+    pub fn add(shape: Arc<dyn SampledPrimitive + Send + Sync>, materials: &[AnyMaterial],
+               lights: &mut Vec<AnyLight>) -> Arc<dyn BoundedPrimitive + Send + Sync>
+    {
+        lights.push(Arc::new(Self::new(shape.clone(), materials)));
+        Arc::new(LightSurface { shape, light: lights.len() - 1 })
+    }
+
+    /// Add a light for every triangle of `mesh` to `lights`, and
+    /// return the primitives to put in the scene for them.
+    pub fn addMesh(mesh: &Arc<TriangleMesh>, materials: &[AnyMaterial],
+                   lights: &mut Vec<AnyLight>) -> Vec<Arc<dyn BoundedPrimitive + Send + Sync>>
+    {
+        mesh.sampledTriangles().into_iter().map(|t| Self::add(t, materials, lights)).collect()
+    }
+}
+
+/// The surface of the light at index `light` of the scene.
+struct LightSurface
+{
+    shape: Arc<dyn SampledPrimitive + Send + Sync>,
+    light: usize,
+}
+
+impl Primitive for LightSurface
+{
+    fn intersect(&self, r: &Ray, t_min: Float, t_max: Float) -> Option<Hit>
+    {
+        let mut hit = self.shape.intersect(r, t_min, t_max)?;
+        hit.light = Some(self.light);
+        Some(hit)
+    }
+}
+
+impl BoundedPrimitive for LightSurface
+{
+    fn bbox(&self) -> BBox
+    {
+        self.shape.bbox()
+    }
+}
+
+impl Light for AreaLight
+{
+    fn sample(&self, p: &Vec3) -> Option<LightSample>
+    {
+        let (dir, pdf) = self.shape.sampleToward(p)?;
+        if pdf <= 0.0
+        {
+            return None;
+        }
+        let r = Ray::new(*p, dir, 0.0);
+        let hit = self.shape.intersect(&r, 0.0, Float::INFINITY)?;
+        let emitted = self.material.emitted(&r, &hit);
+        Some(LightSample { dir, distance: hit.t, radiance: emitted / pdf, pdf })
+    }
+
+    fn pdf(&self, p: &Vec3, dir: &Vec3) -> Float
+    {
+        self.shape.pdfToward(p, dir)
+    }
+
+    fn bounds(&self) -> Option<LightBounds>
+    {
+        // Emitting surfaces are diffuse, on the front side.
+        let (axis, cos_normal) = self.shape.normalBounds();
+        Some(LightBounds {
+            bbox: self.shape.bbox(),
+            power: PI * self.shape.area() * self.luminance,
+            axis,
+            cos_normal,
+            cos_emission: 0.0,
+            two_sided: false,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use crate::geometry::{Color, Quad};
+    use crate::material::DiffuseLight;
+    use crate::texture::Constant;
+
+    #[test]
+    fn samplesCarryTheEmittedRadianceAndPower()
+    {
+        let materials: Vec<AnyMaterial> = vec![Arc::new(DiffuseLight::new(
+            Arc::new(Constant::new(Color::new(2.0, 1.0, 0.5)))))];
+        let quad = Quad { origin: Vec3::new(-1.0, 0.0, -1.0), edge_u: Vec3::new(2.0, 0.0, 0.0),
+                          edge_v: Vec3::new(0.0, 0.0, 2.0), material: 0 };
+        let light = AreaLight::new(Arc::new(quad), &materials);
+
+        // The quad faces down, and is dark from above.
+        let below = Vec3::new(0.3, -1.0, 0.2);
+        for _ in 0..50
+        {
+            let sample = light.sample(&below).unwrap();
+            assert!(((sample.radiance * sample.pdf) - Color::new(2.0, 1.0, 0.5)).norm() < 1e-4);
+            assert!((light.pdf(&below, &sample.dir) - sample.pdf).abs() < 1e-2 * sample.pdf);
+        }
+        let above = light.sample(&Vec3::new(0.3, 1.0, 0.2)).unwrap();
+        assert_eq!(above.radiance.norm(), 0.0);
+
+        let bounds = light.bounds().unwrap();
+        let power = PI * 4.0 * texture::luminance(&Color::new(2.0, 1.0, 0.5));
+        assert!((bounds.power - power).abs() < 1e-3 * power);
+        assert!((bounds.axis - Vec3::new(0.0, -1.0, 0.0)).norm() < 1e-5);
+    }
+
+    #[test]
+    fn addedSurfacesTellTheirLight()
+    {
+        let materials: Vec<AnyMaterial> = vec![Arc::new(DiffuseLight::new(
+            Arc::new(Constant::new(Color::new(1.0, 1.0, 1.0)))))];
+        let mut lights: Vec<AnyLight> = vec![Arc::new(AreaLight::new(
+            Arc::new(Quad { origin: Vec3::new(5.0, 0.0, 0.0), edge_u: Vec3::new(1.0, 0.0, 0.0),
+                            edge_v: Vec3::new(0.0, 1.0, 0.0), material: 0 }), &materials))];
+        let mesh = Arc::new(TriangleMesh::new(
+            vec![Vec3::new(-1.0, -1.0, -2.0), Vec3::new(1.0, -1.0, -2.0),
+                 Vec3::new(0.0, 1.0, -2.0), Vec3::new(0.0, 1.0, -3.0)],
+            vec![[0, 1, 2], [1, 3, 2]], 0));
+        let surfaces = AreaLight::addMesh(&mesh, &materials, &mut lights);
+        assert_eq!((surfaces.len(), lights.len()), (2, 3));
+
+        let r = Ray::new(Vec3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 0.0, -1.0), 0.0);
+        let hit = surfaces[0].intersect(&r, 0.0, Float::INFINITY).unwrap();
+        assert_eq!(hit.light, Some(1));
+        assert!(surfaces[1].intersect(&r, 0.0, Float::INFINITY).is_none());
+        let bbox = surfaces[1].bbox();
+        assert!(bbox.lower[2] <= -3.0 && bbox.higher[0] >= 1.0);
+    }
+}
@@ -0,0 +1,381 @@
+use crate::rand;
+use crate::config::{Float, PI};
+use crate::geometry::vec3;
+use crate::geometry::{Vec3, BBox};
+use super::source::AnyLight;
+
+/// Bounds of the light that a light source emits, to estimate how much
+/// of it reaches a point without sampling the light.
+#[derive(Clone, Debug)]
+pub struct LightBounds
+{
+    /// Where the light is emitted from.
+    pub bbox: BBox,
+    /// The total emitted power, as luminance.
+    pub power: Float,
+    /// The unit axis of a cone that bounds the normals of the emitting
+    /// surfaces (or the directions of emission for points).
+    pub axis: Vec3,
+    /// The cosine of the half angle of the cone of normals.
+    pub cos_normal: Float,
+    /// The cosine of how far from a normal light is emitted, e.g. 0
+    /// (90 degrees) for diffuse surfaces.
+    pub cos_emission: Float,
+    /// Whether light is emitted on both sides of the normals.
+    pub two_sided: bool,
+}
+
+/// Return the smallest cone that contains the cones with unit axes
+/// `a` and `b`, and the cosines of half angles `cos_a` and `cos_b`.
+fn coneUnion(a: &Vec3, cos_a: Float, b: &Vec3, cos_b: Float) -> (Vec3, Float)
+{
+    let theta_a = cos_a.clamp(-1.0, 1.0).acos();
+    let theta_b = cos_b.clamp(-1.0, 1.0).acos();
+    let theta_d = vec3::dot(a, b).clamp(-1.0, 1.0).acos();
+    if (theta_d + theta_b).min(PI) <= theta_a
+    {
+        return (*a, cos_a);
+    }
+    if (theta_d + theta_a).min(PI) <= theta_b
+    {
+        return (*b, cos_b);
+    }
+    let theta = (theta_a + theta_d + theta_b) * 0.5;
+    let normal = vec3::cross(a, b);
+    if theta >= PI || normal.normSquared() == 0.0
+    {
+        return (*a, -1.0);
+    }
+    // Turn `a` toward `b` to the middle of the union.
+    let toward = vec3::cross(&Vec3::unit(&normal), a);
+    let turn = theta - theta_a;
+    (*a * turn.cos() + toward * turn.sin(), theta.cos())
+}
+
+impl LightBounds
+{
+    /// Return bounds that contain both these and `rhs`.
+    pub fn union(&self, rhs: &LightBounds) -> LightBounds
+    {
+        if self.power <= 0.0
+        {
+            return rhs.clone();
+        }
+        if rhs.power <= 0.0
+        {
+            return self.clone();
+        }
+        let (axis, cos_normal) = coneUnion(&self.axis, self.cos_normal,
+                                           &rhs.axis, rhs.cos_normal);
+        LightBounds {
+            bbox: self.bbox.union(&rhs.bbox),
+            power: self.power + rhs.power,
+            axis,
+            cos_normal,
+            cos_emission: self.cos_emission.min(rhs.cos_emission),
+            two_sided: self.two_sided || rhs.two_sided,
+        }
+    }
+
+    /// Return a conservative estimate of how much of the light reaches
+    /// `p`: the power, over the squared distance, times the cosine of
+    /// the smallest angle any light could leave at toward `p`.
+    pub fn importance(&self, p: &Vec3) -> Float
+    {
+        let center = (self.bbox.lower + self.bbox.higher) * 0.5;
+        let radius2 = (self.bbox.higher - center).normSquared();
+        let d = *p - center;
+        let distance2 = d.normSquared();
+        // Do not let points close to the light get all the samples.
+        let clamped2 = distance2.max(radius2.sqrt());
+        if clamped2 <= 0.0
+        {
+            return self.power;
+        }
+
+        // The angle between the axis and the direction to `p`, and the
+        // angle that the bounding box covers seen from `p`.
+        let mut cos_w = if distance2 > 0.0 { vec3::dot(&self.axis, &Vec3::unit(&d)) } else { 1.0 };
+        if self.two_sided
+        {
+            cos_w = cos_w.abs();
+        }
+        let theta_w = cos_w.clamp(-1.0, 1.0).acos();
+        let theta_b = if distance2 > radius2 { (radius2 / distance2).sqrt().asin() } else { PI };
+        let theta_o = self.cos_normal.clamp(-1.0, 1.0).acos();
+        let theta = (theta_w - theta_o - theta_b).max(0.0);
+        if theta.cos() <= self.cos_emission
+        {
+            return 0.0;
+        }
+        self.power * theta.cos() / clamped2
+    }
+}
+
+enum LightBvhData
+{
+    Branch(Box<LightBvhNode>, Box<LightBvhNode>),
+    /// The index of a light.
+    Leaf(usize),
+}
+
+struct LightBvhNode
+{
+    data: LightBvhData,
+    bounds: LightBounds,
+}
+
+impl LightBvhNode
+{
+    /// Build a tree of `lights`, which are (index, bounds) pairs.
+    /// `trails` gets the path from the root to each leaf, as a bit per
+    /// level that is set to go right, starting at bit `depth`.
+    fn new(lights: &mut [(usize, LightBounds)], trail: u64, depth: u32,
+           trails: &mut Vec<Option<u64>>) -> Self
+    {
+        if lights.len() == 1
+        {
+            trails[lights[0].0] = Some(trail);
+            return Self { data: LightBvhData::Leaf(lights[0].0), bounds: lights[0].1.clone() };
+        }
+        // Split at the median along the longest axis of the centers.
+        let centroid = |b: &LightBounds| (b.bbox.lower + b.bbox.higher) * 0.5;
+        let mut lower = centroid(&lights[0].1);
+        let mut higher = lower;
+        for (_, b) in lights.iter()
+        {
+            let c = centroid(b);
+            for i in 0..3
+            {
+                lower[i] = lower[i].min(c[i]);
+                higher[i] = higher[i].max(c[i]);
+            }
+        }
+        let extent = higher - lower;
+        let ax = if extent[0] > extent[1] && extent[0] > extent[2] { 0 }
+            else if extent[1] > extent[2] { 1 } else { 2 };
+        lights.sort_by(|a, b| centroid(&a.1)[ax].total_cmp(&centroid(&b.1)[ax]));
+
+        let len = lights.len();
+        let left = Self::new(&mut lights[0..len / 2], trail, depth + 1, trails);
+        let right = Self::new(&mut lights[len / 2..len], trail | (1 << depth), depth + 1,
+                              trails);
+        Self {
+            bounds: left.bounds.union(&right.bounds),
+            data: LightBvhData::Branch(Box::new(left), Box::new(right)),
+        }
+    }
+
+    /// Return the probability of going to the left child from `p`, or
+    /// None if neither gets any light.
+    fn leftProbability(left: &LightBvhNode, right: &LightBvhNode, p: &Vec3) -> Option<Float>
+    {
+        let importance_left = left.bounds.importance(p);
+        let importance_right = right.bounds.importance(p);
+        let total = importance_left + importance_right;
+        if total > 0.0 { Some(importance_left / total) } else { None }
+    }
+}
+
+/// Picks one of many lights at random for a point, with probability
+/// by how much of its light may reach the point, so that far or
+/// dim lights rarely get samples. Lights without bounds (e.g. the sky)
+/// are picked uniformly instead.
+pub struct LightBvh
+{
+    root: Option<LightBvhNode>,
+    /// The indices of the lights without bounds.
+    infinite: Vec<usize>,
+    /// The path from the root to the leaf of each light with bounds.
+    trails: Vec<Option<u64>>,
+}
+
+impl LightBvh
+{
+    pub fn new(lights: &[AnyLight]) -> Self
+    {
+        let mut bounded = Vec::new();
+        let mut infinite = Vec::new();
+        for (i, light) in lights.iter().enumerate()
+        {
+            match light.bounds()
+            {
+                Some(bounds) => if bounds.power > 0.0 { bounded.push((i, bounds)) },
+                None => infinite.push(i),
+            }
+        }
+        let mut trails = vec![None; lights.len()];
+        let root = if bounded.is_empty()
+        {
+            None
+        }
+        else
+        {
+            Some(LightBvhNode::new(&mut bounded, 0, 0, &mut trails))
+        };
+        Self { root, infinite, trails }
+    }
+
+    /// Return the probability of picking one of the lights without
+    /// bounds instead of the tree.
+    fn infiniteProbability(&self) -> Float
+    {
+        let n = self.infinite.len() as Float;
+        let tree = if self.root.is_some() { 1.0 } else { 0.0 };
+        if n + tree > 0.0 { n / (n + tree) } else { 0.0 }
+    }
+
+    /// Pick a light for `p`. Return its index and the probability of
+    /// picking it, or None if no light reaches `p`.
+    pub fn sample(&self, p: &Vec3) -> Option<(usize, Float)>
+    {
+        let p_infinite = self.infiniteProbability();
+        if rand::random::<Float>() < p_infinite
+        {
+            let n = self.infinite.len();
+            let i = ((rand::random::<Float>() * n as Float) as usize).min(n - 1);
+            return Some((self.infinite[i], p_infinite / n as Float));
+        }
+
+        let mut node = self.root.as_ref()?;
+        let mut pmf = 1.0 - p_infinite;
+        loop
+        {
+            match &node.data
+            {
+                LightBvhData::Leaf(i) =>
+                {
+                    return if node.bounds.importance(p) > 0.0 { Some((*i, pmf)) } else { None };
+                },
+                LightBvhData::Branch(left, right) =>
+                {
+                    let p_left = LightBvhNode::leftProbability(left, right, p)?;
+                    if rand::random::<Float>() < p_left
+                    {
+                        node = left;
+                        pmf *= p_left;
+                    }
+                    else
+                    {
+                        node = right;
+                        pmf *= 1.0 - p_left;
+                    }
+                },
+            }
+        }
+    }
+
+    /// Return the probability that `sample` at `p` picks the light at
+    /// `index`.
+    pub fn pmf(&self, p: &Vec3, index: usize) -> Float
+    {
+        if self.infinite.contains(&index)
+        {
+            return self.infiniteProbability() / self.infinite.len() as Float;
+        }
+        let (mut node, trail) = match (&self.root, self.trails.get(index))
+        {
+            (Some(root), Some(Some(trail))) => (root, *trail),
+            _ => return 0.0,
+        };
+        let mut pmf = 1.0 - self.infiniteProbability();
+        let mut depth = 0;
+        loop
+        {
+            match &node.data
+            {
+                LightBvhData::Leaf(_) =>
+                {
+                    return if node.bounds.importance(p) > 0.0 { pmf } else { 0.0 };
+                },
+                LightBvhData::Branch(left, right) =>
+                {
+                    let p_left = match LightBvhNode::leftProbability(left, right, p)
+                    {
+                        Some(p_left) => p_left,
+                        None => return 0.0,
+                    };
+                    if trail & (1 << depth) == 0
+                    {
+                        node = left;
+                        pmf *= p_left;
+                    }
+                    else
+                    {
+                        node = right;
+                        pmf *= 1.0 - p_left;
+                    }
+                    depth += 1;
+                },
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::geometry::Color;
+    use crate::light::{PointLight, SpotLight, DirectionalLight};
+
+    fn lights() -> Vec<AnyLight>
+    {
+        let mut lights: Vec<AnyLight> = (0..6)
+            .map(|i| Arc::new(PointLight::new(Vec3::new(i as Float, 0.0, (i * i) as Float * 0.3),
+                                              Color::new(1.0, 0.5, 0.2) * (i + 1) as Float))
+                 as AnyLight)
+            .collect();
+        lights.push(Arc::new(SpotLight::new(Vec3::new(0.0, 3.0, 0.0), Vec3::new(0.0, -1.0, 0.0),
+                                            Color::new(4.0, 4.0, 4.0), 30.0)));
+        lights.push(Arc::new(DirectionalLight::new(Vec3::new(0.0, -1.0, 0.0),
+                                                   Color::new(1.0, 1.0, 1.0))));
+        lights
+    }
+
+    #[test]
+    fn pmfMatchesTheFrequencyOfSamples()
+    {
+        let lights = lights();
+        let bvh = LightBvh::new(&lights);
+        let p = Vec3::new(1.5, 0.5, 0.5);
+        let n = 200000;
+        let mut counts = vec![0; lights.len()];
+        for _ in 0..n
+        {
+            let (i, pmf) = bvh.sample(&p).unwrap();
+            assert!((pmf - bvh.pmf(&p, i)).abs() < 1e-6);
+            counts[i] += 1;
+        }
+        let mut total = 0.0;
+        for (i, &count) in counts.iter().enumerate()
+        {
+            let pmf = bvh.pmf(&p, i);
+            total += pmf;
+            assert!((count as Float / n as Float - pmf).abs() < 5e-3,
+                    "{}: {} vs {}", i, count, pmf);
+        }
+        assert!((total - 1.0).abs() < 1e-5);
+        // The sun is one of two choices: itself or the tree.
+        assert!((bvh.pmf(&p, 7) - 0.5).abs() < 1e-6);
+    }
+
+    #[test]
+    fn lightsBehindTheSpotAreNeverPicked()
+    {
+        let lights: Vec<AnyLight> = vec![
+            Arc::new(SpotLight::new(Vec3::new(0.0, 3.0, 0.0), Vec3::new(0.0, -1.0, 0.0),
+                                    Color::new(4.0, 4.0, 4.0), 30.0)),
+            Arc::new(PointLight::new(Vec3::new(2.0, 0.0, 0.0), Color::new(1.0, 1.0, 1.0))),
+        ];
+        let bvh = LightBvh::new(&lights);
+        let above = Vec3::new(0.0, 5.0, 0.0);
+        assert_eq!(bvh.pmf(&above, 0), 0.0);
+        for _ in 0..100
+        {
+            assert_eq!(bvh.sample(&above).unwrap().0, 1);
+        }
+    }
+}
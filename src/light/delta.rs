@@ -0,0 +1,244 @@
+use crate::rand;
+use crate::config::{Float, PI};
+use crate::geometry::vec3;
+use crate::geometry::{Vec3, Color, BBox};
+use crate::texture;
+use super::source::{Light, LightSample};
+use super::bvh::LightBounds;
+
+/// A light that shines equally in all directions from a point. It
+/// falls off with the inverse square of the distance.
+pub struct PointLight
+{
+    pub position: Vec3,
+    /// The power per solid angle.
+    pub intensity: Color,
+}
+
+impl PointLight
+{
+    pub fn new(position: Vec3, intensity: Color) -> Self
+    {
+        Self { position, intensity }
+    }
+}
+
+impl Light for PointLight
+{
+    fn sample(&self, p: &Vec3) -> Option<LightSample>
+    {
+        let d = self.position - *p;
+        let distance2 = d.normSquared();
+        if distance2 == 0.0
+        {
+            return None;
+        }
+        let distance = distance2.sqrt();
+        Some(LightSample { dir: d / distance, distance,
+                           radiance: self.intensity / distance2, pdf: Float::INFINITY })
+    }
+
+    fn bounds(&self) -> Option<LightBounds>
+    {
+        Some(LightBounds {
+            bbox: BBox { lower: self.position, higher: self.position },
+            power: 4.0 * PI * texture::luminance(&self.intensity),
+            axis: Vec3::new(0.0, 1.0, 0.0),
+            cos_normal: -1.0,
+            cos_emission: 0.0,
+            two_sided: false,
+        })
+    }
+}
+
+/// A point light that only shines in a cone, e.g. a flashlight. It
+/// is at full intensity inside the falloff angle, and fades out
+/// smoothly to the cone angle.
+pub struct SpotLight
+{
+    pub position: Vec3,
+    /// The unit direction of the axis of the cone.
+    pub direction: Vec3,
+    /// The power per solid angle on the axis.
+    pub intensity: Color,
+    cos_cone: Float,
+    cos_falloff: Float,
+}
+
+impl SpotLight
+{
+    /// Construct a spot light shining along `direction`, with a cone
+    /// of half angle `cone_angle` in degrees and a hard edge.
+    pub fn new(position: Vec3, direction: Vec3, intensity: Color, cone_angle: Float) -> Self
+    {
+        let cos_cone = (cone_angle * PI / 180.0).cos();
+        Self { position, direction: Vec3::unit(&direction),
+               intensity, cos_cone, cos_falloff: cos_cone }
+    }
+
+    /// Start fading out at half angle `falloff_angle` in degrees
+    /// from the axis, for a soft edge.
+    pub fn withFalloff(mut self, falloff_angle: Float) -> Self
+    {
+        self.cos_falloff = (falloff_angle * PI / 180.0).cos().max(self.cos_cone);
+        self
+    }
+
+    /// The fraction of the intensity shining in unit direction `w`.
+    fn falloff(&self, w: &Vec3) -> Float
+    {
+        let cos = vec3::dot(w, &self.direction);
+        if cos >= self.cos_falloff
+        {
+            return 1.0;
+        }
+        if cos <= self.cos_cone
+        {
+            return 0.0;
+        }
+        let t = (cos - self.cos_cone) / (self.cos_falloff - self.cos_cone);
+        t * t * (3.0 - 2.0 * t)
+    }
+}
+
+impl Light for SpotLight
+{
+    fn sample(&self, p: &Vec3) -> Option<LightSample>
+    {
+        let d = self.position - *p;
+        let distance2 = d.normSquared();
+        if distance2 == 0.0
+        {
+            return None;
+        }
+        let distance = distance2.sqrt();
+        let dir = d / distance;
+        let falloff = self.falloff(&-dir);
+        if falloff <= 0.0
+        {
+            return None;
+        }
+        Some(LightSample { dir, distance,
+                           radiance: self.intensity * (falloff / distance2),
+                           pdf: Float::INFINITY })
+    }
+
+    fn bounds(&self) -> Option<LightBounds>
+    {
+        // Full intensity inside the falloff angle, fading out to the
+        // cone angle beyond it.
+        let theta_falloff = self.cos_falloff.acos();
+        let theta_cone = self.cos_cone.acos();
+        Some(LightBounds {
+            bbox: BBox { lower: self.position, higher: self.position },
+            power: 2.0 * PI * (1.0 - self.cos_cone) * texture::luminance(&self.intensity),
+            axis: self.direction,
+            cos_normal: self.cos_falloff,
+            cos_emission: (theta_cone - theta_falloff).cos(),
+            two_sided: false,
+        })
+    }
+}
+
+/// Light from infinitely far away in one direction, e.g. the sun.
+/// With an angular diameter, it comes from a small disk in the sky
+/// instead, which softens the shadows.
+pub struct DirectionalLight
+{
+    /// The unit direction the light travels in.
+    pub direction: Vec3,
+    /// The power per area facing the light.
+    pub irradiance: Color,
+    /// The cosine of the angular radius of the light.
+    cos_radius: Float,
+}
+
+impl DirectionalLight
+{
+    pub fn new(direction: Vec3, irradiance: Color) -> Self
+    {
+        Self { direction: Vec3::unit(&direction), irradiance, cos_radius: 1.0 }
+    }
+
+    /// Set the angular diameter of the light in degrees, about 0.53
+    /// for the sun.
+    pub fn withAngularDiameter(mut self, degrees: Float) -> Self
+    {
+        self.cos_radius = (0.5 * degrees * PI / 180.0).cos();
+        self
+    }
+}
+
+impl Light for DirectionalLight
+{
+    fn sample(&self, _p: &Vec3) -> Option<LightSample>
+    {
+        // Sample the cone uniformly. The irradiance is the radiance
+        // times the solid angle, so every sample carries all of it.
+        let axis = -self.direction;
+        let cos = 1.0 - rand::random::<Float>() * (1.0 - self.cos_radius);
+        let sin = (1.0 - cos * cos).max(0.0).sqrt();
+        let phi = 2.0 * PI * rand::random::<Float>();
+        let (t, b) = vec3::basis(&axis);
+        let dir = axis * cos + (t * phi.cos() + b * phi.sin()) * sin;
+        Some(LightSample { dir, distance: Float::INFINITY, radiance: self.irradiance,
+                           pdf: Float::INFINITY })
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    fn assertNear(a: Float, b: Float, tolerance: Float)
+    {
+        assert!((a - b).abs() <= tolerance, "{} is not near {}", a, b);
+    }
+
+    #[test]
+    fn pointLightFallsOffWithSquaredDistance()
+    {
+        let light = PointLight::new(Vec3::new(0.0, 2.0, 0.0), Color::new(8.0, 8.0, 8.0));
+        let sample = light.sample(&Vec3::origin()).unwrap();
+        assertNear(sample.distance, 2.0, 1e-6);
+        assertNear(sample.radiance[0], 2.0, 1e-6);
+        assert!((sample.dir - Vec3::new(0.0, 1.0, 0.0)).norm() < 1e-6);
+        assert!(sample.pdf.is_infinite());
+        assertNear(light.bounds().unwrap().power, 32.0 * PI, 1e-3);
+    }
+
+    #[test]
+    fn spotLightFadesBetweenFalloffAndCone()
+    {
+        let light = SpotLight::new(Vec3::new(0.0, 1.0, 0.0), Vec3::new(0.0, -1.0, 0.0),
+                                   Color::new(1.0, 1.0, 1.0), 30.0).withFalloff(20.0);
+        // At 10, 25 and 40 degrees from the axis, 1 below the light.
+        let at = |degrees: Float| {
+            let x = (degrees * PI / 180.0).tan();
+            let p = Vec3::new(x, 0.0, 0.0);
+            light.sample(&p).map(|s| s.radiance[0] * (1.0 + x * x))
+        };
+        assertNear(at(10.0).unwrap(), 1.0, 1e-5);
+        let edge = at(25.0).unwrap();
+        assert!(edge > 0.0 && edge < 1.0);
+        assert!(at(40.0).is_none());
+    }
+
+    #[test]
+    fn directionalLightSamplesItsDisk()
+    {
+        let light = DirectionalLight::new(Vec3::new(0.0, -1.0, 0.0), Color::new(2.0, 2.0, 2.0))
+            .withAngularDiameter(10.0);
+        let cos_radius = (5.0 * PI / 180.0).cos();
+        for _ in 0..1000
+        {
+            let sample = light.sample(&Vec3::origin()).unwrap();
+            assert!(sample.dir[1] >= cos_radius - 1e-5);
+            assertNear(sample.dir.norm(), 1.0, 1e-5);
+            assertNear(sample.radiance[0], 2.0, 1e-6);
+            assert!(sample.distance.is_infinite());
+        }
+        assert!(light.bounds().is_none());
+    }
+}
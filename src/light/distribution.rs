@@ -0,0 +1,174 @@
+use crate::config::Float;
+
+/// A piecewise-constant probability distribution over [0, 1), with
+/// densities proportional to a function tabulated at even steps.
+pub struct Distribution1D
+{
+    func: Vec<Float>,
+    /// The cumulative distribution at the start of each step, and 1
+    /// at the end.
+    cdf: Vec<Float>,
+    /// The integral of the function over [0, 1).
+    integral: Float,
+}
+
+impl Distribution1D
+{
+    /// Construct from the values of the function, which must not be
+    /// negative. If they are all 0, the distribution is uniform.
+    pub fn new(func: Vec<Float>) -> Self
+    {
+        let n = func.len();
+        let mut cdf = Vec::with_capacity(n + 1);
+        cdf.push(0.0);
+        for i in 0..n
+        {
+            cdf.push(cdf[i] + func[i] / n as Float);
+        }
+        let integral = cdf[n];
+        for (i, c) in cdf.iter_mut().enumerate()
+        {
+            *c = if integral > 0.0 { *c / integral } else { i as Float / n as Float };
+        }
+        Self { func, cdf, integral }
+    }
+
+    pub fn integral(&self) -> Float
+    {
+        self.integral
+    }
+
+    /// Return the density at step `index`.
+    fn density(&self, index: usize) -> Float
+    {
+        if self.integral > 0.0 { self.func[index] / self.integral } else { 1.0 }
+    }
+
+    /// Sample a point from a uniform random number `u`. Return it,
+    /// its density and the index of its step.
+    pub fn sample(&self, u: Float) -> (Float, Float, usize)
+    {
+        let n = self.func.len();
+        let index = (self.cdf.partition_point(|&c| c <= u).max(1) - 1).min(n - 1);
+        let width = self.cdf[index + 1] - self.cdf[index];
+        let offset = if width > 0.0 { ((u - self.cdf[index]) / width).min(1.0) } else { 0.5 };
+        let x = ((index as Float + offset) / n as Float).min(1.0 - Float::EPSILON);
+        (x, self.density(index), index)
+    }
+
+    /// Return the density at `x` in [0, 1).
+    pub fn pdf(&self, x: Float) -> Float
+    {
+        let n = self.func.len();
+        self.density(((x * n as Float) as usize).min(n - 1))
+    }
+}
+
+/// A piecewise-constant probability distribution over [0, 1)², with
+/// densities proportional to a function tabulated on a grid.
+pub struct Distribution2D
+{
+    /// The distribution along x in each row.
+    rows: Vec<Distribution1D>,
+    /// The distribution of the rows.
+    marginal: Distribution1D,
+}
+
+impl Distribution2D
+{
+    /// Construct from the values of the function on a grid of
+    /// `width` × `height`, row by row.
+    pub fn new(func: &[Float], width: usize, height: usize) -> Self
+    {
+        let rows: Vec<Distribution1D> = (0..height)
+            .map(|y| Distribution1D::new(func[y * width..(y + 1) * width].to_vec()))
+            .collect();
+        let marginal = Distribution1D::new(rows.iter().map(|r| r.integral()).collect());
+        Self { rows, marginal }
+    }
+
+    /// Sample a point (x, y) from uniform random numbers `u1` and
+    /// `u2`. Return it and its density.
+    pub fn sample(&self, u1: Float, u2: Float) -> ((Float, Float), Float)
+    {
+        let (y, pdf_y, row) = self.marginal.sample(u2);
+        let (x, pdf_x, _) = self.rows[row].sample(u1);
+        ((x, y), pdf_x * pdf_y)
+    }
+
+    /// Return the density at (`x`, `y`).
+    pub fn pdf(&self, x: Float, y: Float) -> Float
+    {
+        let n = self.rows.len();
+        let row = ((y * n as Float) as usize).min(n - 1);
+        self.marginal.pdf(y) * self.rows[row].pdf(x)
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    fn distribution() -> Distribution2D
+    {
+        let func = [1.0, 3.0, 0.0, 2.0,
+                    0.0, 0.0, 0.0, 0.0,
+                    5.0, 1.0, 1.0, 1.0];
+        Distribution2D::new(&func, 4, 3)
+    }
+
+    #[test]
+    fn pdfIntegratesToOneAndFollowsTheFunction()
+    {
+        let d = distribution();
+        let n = 96;
+        let mut total = 0.0;
+        for i in 0..n
+        {
+            for j in 0..n
+            {
+                let (x, y) = ((i as Float + 0.5) / n as Float, (j as Float + 0.5) / n as Float);
+                total += d.pdf(x, y) / (n * n) as Float;
+            }
+        }
+        assert!((total - 1.0).abs() < 1e-4);
+        // The integral of the function is 14 / 12.
+        assert!((d.pdf(0.3, 0.1) - 3.0 * 12.0 / 14.0).abs() < 1e-4);
+        assert_eq!(d.pdf(0.6, 0.5), 0.0);
+    }
+
+    #[test]
+    fn samplesAreDistributedByThePdf()
+    {
+        let d = distribution();
+        let n = 100;
+        let mut counts = [0; 12];
+        for i in 0..n
+        {
+            for j in 0..n
+            {
+                let ((x, y), pdf) = d.sample((i as Float + 0.5) / n as Float,
+                                             (j as Float + 0.5) / n as Float);
+                assert!((pdf - d.pdf(x, y)).abs() < 1e-4);
+                counts[(y * 3.0) as usize * 4 + (x * 4.0) as usize] += 1;
+            }
+        }
+        for (cell, &count) in counts.iter().enumerate()
+        {
+            let (x, y) = ((cell % 4) as Float * 0.25 + 0.1, (cell / 4) as Float / 3.0 + 0.1);
+            let expected = d.pdf(x, y) / 12.0;
+            assert!((count as Float / (n * n) as Float - expected).abs() < 1e-2,
+                    "{}: {} vs {}", cell, count, expected);
+        }
+    }
+
+    #[test]
+    fn zeroFunctionIsUniform()
+    {
+        let d = Distribution1D::new(vec![0.0; 4]);
+        let (x, pdf, index) = d.sample(0.6);
+        assert!((x - 0.6).abs() < 1e-5);
+        assert_eq!((pdf, index), (1.0, 2));
+    }
+}
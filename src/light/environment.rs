@@ -0,0 +1,224 @@
+use std::path::Path;
+use std::sync::Arc;
+
+use image::ImageResult;
+
+use crate::rand;
+use crate::config::{Float, PI};
+use crate::geometry::{self, Vec3, Color};
+use crate::texture::{self, Image, ColorSpace, WrapMode, Filter};
+use super::source::{Light, LightSample};
+use super::distribution::Distribution2D;
+
+/// Return the unit direction of position (`x`, `y`) on an
+/// equirectangular image, with y from the top, both in [0, 1]. This is
+/// the inverse of `geometry::sphereUV`, with v = 1 - y.
+pub fn equirectangularDirection(x: Float, y: Float) -> Vec3
+{
+    let theta = PI * y;
+    let phi = 2.0 * PI * x - PI;
+    let sin = theta.sin();
+    Vec3::new(sin * phi.cos(), theta.cos(), -sin * phi.sin())
+}
+
+/// Light from an equirectangular image around the scene, infinitely
+/// far away (image based lighting). The top of the image is +y.
+/// Directions are sampled by the luminance of the image, so small,
+/// bright parts (e.g. the sun) are found by shadow rays.
+pub struct EnvironmentLight
+{
+    image: Arc<Image>,
+    scale: Float,
+    /// The rotation around +y, in radians.
+    rotation: Float,
+    /// The distribution of (x, y) on the image, with y from the top.
+    distribution: Distribution2D,
+}
+
+impl EnvironmentLight
+{
+    pub fn new(image: Arc<Image>) -> Self
+    {
+        let width = image.width() as usize;
+        let height = image.height() as usize;
+        let mut func = Vec::with_capacity(width * height);
+        for y in 0..height
+        {
+            // Rows near the poles cover less solid angle.
+            let sin = (PI * (y as Float + 0.5) / height as Float).sin();
+            for x in 0..width
+            {
+                // Bilinear filtering spreads texels into their
+                // neighbors, so each texel is sampled by the
+                // brightest one around it, lest they become fireflies.
+                let mut brightest: Float = 0.0;
+                for dy in -1..=1
+                {
+                    for dx in -1..=1
+                    {
+                        let c = image.texel(x as i64 + dx, y as i64 + dy, WrapMode::Repeat);
+                        brightest = brightest.max(texture::luminance(&c));
+                    }
+                }
+                func.push(brightest * sin);
+            }
+        }
+        Self { image, scale: 1.0, rotation: 0.0,
+               distribution: Distribution2D::new(&func, width, height) }
+    }
+
+    /// Load an image file, e.g. a Radiance `.hdr` or an OpenEXR `.exr`.
+    pub fn load(path: &Path) -> ImageResult<Self>
+    {
+        Ok(Self::new(Arc::new(Image::load(path, ColorSpace::Srgb)?)))
+    }
+
+    /// Multiply the radiance by `scale`.
+    pub fn withScale(mut self, scale: Float) -> Self
+    {
+        self.scale = scale;
+        self
+    }
+
+    /// Rotate the environment around +y by `degrees`.
+    pub fn withRotation(mut self, degrees: Float) -> Self
+    {
+        self.rotation = degrees * PI / 180.0;
+        self
+    }
+
+    fn rotate(&self, v: &Vec3, angle: Float) -> Vec3
+    {
+        let (sin, cos) = angle.sin_cos();
+        Vec3::new(cos * v[0] + sin * v[2], v[1], -sin * v[0] + cos * v[2])
+    }
+
+    /// Return the position on the image (x, y from the top, in [0,
+    /// 1)) of unit direction `dir`.
+    fn imagePosition(&self, dir: &Vec3) -> (Float, Float)
+    {
+        let (u, v) = geometry::sphereUV(&self.rotate(dir, -self.rotation));
+        (u, 1.0 - v)
+    }
+
+    /// Return the unit direction of position (`x`, `y`) on the image,
+    /// and the sine of its angle to +y.
+    fn direction(&self, x: Float, y: Float) -> (Vec3, Float)
+    {
+        (self.rotate(&equirectangularDirection(x, y), self.rotation), (PI * y).sin())
+    }
+
+    fn radianceAt(&self, x: Float, y: Float) -> Color
+    {
+        self.image.sample(x, 1.0 - y, WrapMode::Repeat, Filter::Bilinear) * self.scale
+    }
+}
+
+impl Light for EnvironmentLight
+{
+    fn sample(&self, _p: &Vec3) -> Option<LightSample>
+    {
+        let ((x, y), pdf) = self.distribution.sample(rand::random::<Float>(),
+                                                     rand::random::<Float>());
+        let (dir, sin) = self.direction(x, y);
+        if pdf <= 0.0 || sin <= 0.0
+        {
+            return None;
+        }
+        // From density on the image to density per solid angle.
+        let pdf = pdf / (2.0 * PI * PI * sin);
+        Some(LightSample { dir, distance: Float::INFINITY,
+                           radiance: self.radianceAt(x, y) / pdf, pdf })
+    }
+
+    fn pdf(&self, _p: &Vec3, dir: &Vec3) -> Float
+    {
+        let (x, y) = self.imagePosition(dir);
+        let sin = (PI * y).sin();
+        if sin <= 0.0
+        {
+            return 0.0;
+        }
+        self.distribution.pdf(x, y) / (2.0 * PI * PI * sin)
+    }
+
+    fn environment(&self, dir: &Vec3) -> Option<Color>
+    {
+        let (x, y) = self.imagePosition(dir);
+        Some(self.radianceAt(x, y))
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    /// An environment that is dark but for a bright band.
+    fn light() -> EnvironmentLight
+    {
+        let (width, height) = (16, 8);
+        let data = (0..width * height)
+            .map(|i| if i / width == 2 && i % width < 5 { Color::new(10.0, 10.0, 10.0) }
+                 else { Color::new(0.1, 0.2, 0.3) })
+            .collect();
+        EnvironmentLight::new(Arc::new(Image::new(data, width, height))).withRotation(40.0)
+    }
+
+    #[test]
+    fn directionsMapBackToTheirImagePosition()
+    {
+        let env = light();
+        for &(x, y) in &[(0.1, 0.2), (0.5, 0.5), (0.8, 0.9), (0.3, 0.7)]
+        {
+            let dir = equirectangularDirection(x, y);
+            assert!((dir.norm() - 1.0).abs() < 1e-5);
+            let (u, v) = geometry::sphereUV(&dir);
+            assert!((u - x).abs() < 1e-4 && (1.0 - v - y).abs() < 1e-4);
+
+            let (dir, _) = env.direction(x, y);
+            let (x1, y1) = env.imagePosition(&dir);
+            assert!((x1 - x).abs() < 1e-4 && (y1 - y).abs() < 1e-4);
+        }
+        assert!((equirectangularDirection(0.3, 0.0)[1] - 1.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn pdfIntegratesToOneAndMatchesSamples()
+    {
+        let env = light();
+        let p = Vec3::origin();
+        let (n_theta, n_phi) = (200, 400);
+        let (d_theta, d_phi) = (PI / n_theta as Float, 2.0 * PI / n_phi as Float);
+        let mut total = 0.0;
+        for i in 0..n_theta
+        {
+            let theta = (i as Float + 0.5) * d_theta;
+            for j in 0..n_phi
+            {
+                let phi = (j as Float + 0.5) * d_phi;
+                let dir = Vec3::new(theta.sin() * phi.cos(), theta.cos(),
+                                    theta.sin() * phi.sin());
+                total += env.pdf(&p, &dir) * theta.sin() * d_theta * d_phi;
+            }
+        }
+        assert!((total - 1.0).abs() < 1e-2, "{}", total);
+
+        for _ in 0..100
+        {
+            let sample = env.sample(&p).unwrap();
+            // On the edge of a texel, the direction may round into the
+            // next one, which has another density.
+            let (x, y) = env.imagePosition(&sample.dir);
+            let edge = |t: Float| (t - t.round()).abs() < 1e-3;
+            if edge(x * 16.0) || edge(y * 8.0)
+            {
+                continue;
+            }
+            let pdf = env.pdf(&p, &sample.dir);
+            assert!((sample.pdf - pdf).abs() < 1e-2 * pdf);
+            let radiance = env.environment(&sample.dir).unwrap();
+            assert!((sample.radiance * sample.pdf - radiance).norm() < 1e-3 * radiance.norm());
+        }
+    }
+}
@@ -0,0 +1,21 @@
+//! Light sources that are sampled directly by the integrator.
+
+mod source;
+pub use self::source::*;
+
+mod delta;
+pub use self::delta::*;
+
+mod area;
+pub use self::area::*;
+
+mod bvh;
+pub use self::bvh::*;
+
+mod distribution;
+
+mod environment;
+pub use self::environment::*;
+
+mod sky;
+pub use self::sky::*;
@@ -0,0 +1,290 @@
+use std::sync::Arc;
+
+use crate::config::{Float, PI};
+use crate::geometry::vec3;
+use crate::geometry::{Vec3, Color};
+use crate::texture::Image;
+use crate::spectrum;
+use super::source::AnyLight;
+use super::delta::DirectionalLight;
+use super::environment::{self, EnvironmentLight};
+
+/// The size of the image the sky is tabulated in for sampling.
+const TABLE_WIDTH: usize = 512;
+const TABLE_HEIGHT: usize = 256;
+
+/// The illuminance of the sun outside the atmosphere, in the units of
+/// `PhysicalSky`.
+const SUN_ILLUMINANCE: Float = 13.3;
+
+/// The angular diameter of the sun in degrees.
+const SUN_DIAMETER: Float = 0.53;
+
+/// The coefficients of the Perez model of the distribution of sky
+/// luminance (or chromaticity).
+struct Perez
+{
+    a: Float,
+    b: Float,
+    c: Float,
+    d: Float,
+    e: Float,
+}
+
+impl Perez
+{
+    /// Return the relative value at zenith angle `theta` and angle
+    /// `gamma` from the sun.
+    fn eval(&self, theta: Float, gamma: Float) -> Float
+    {
+        let cos_gamma = gamma.cos();
+        (1.0 + self.a * (self.b / theta.cos().max(1e-3)).exp()) *
+            (1.0 + self.c * (self.d * gamma).exp() + self.e * cos_gamma * cos_gamma)
+    }
+}
+
+/// A clear daylight sky, with the model of Preetham et al. "A
+/// Practical Analytic Model for Daylight" (1999). Radiance is in units
+/// of 10 kcd/m², so that a sky at noon is about 1, like the default
+/// background. The ground below the horizon is diffuse, and lit by
+/// the sky and the sun.
+pub struct PhysicalSky
+{
+    /// The unit direction toward the sun.
+    sun: Vec3,
+    turbidity: Float,
+    ground_albedo: Color,
+    scale: Float,
+}
+
+impl PhysicalSky
+{
+    /// Construct with the sun at `elevation` degrees above the
+    /// horizon and `azimuth` degrees from north (-z) toward east (+x).
+    pub fn new(elevation: Float, azimuth: Float) -> Self
+    {
+        let (elevation, azimuth) = (elevation * PI / 180.0, azimuth * PI / 180.0);
+        let sun = Vec3::new(elevation.cos() * azimuth.sin(), elevation.sin(),
+                            -elevation.cos() * azimuth.cos());
+        Self { sun, turbidity: 3.0, ground_albedo: Color::new(0.3, 0.3, 0.3), scale: 1.0 }
+    }
+
+    /// Construct with the sun where it is on `day` of the year (from
+    /// 1), at `hour` of local solar time, seen from `latitude` degrees
+    /// north.
+    pub fn at(day: u32, hour: Float, latitude: Float) -> Self
+    {
+        let latitude = latitude * PI / 180.0;
+        let declination = 23.44 * PI / 180.0 *
+            (2.0 * PI * (284.0 + day as Float) / 365.0).sin();
+        let hour_angle = (hour - 12.0) * PI / 12.0;
+        let sin_elevation = latitude.sin() * declination.sin() +
+            latitude.cos() * declination.cos() * hour_angle.cos();
+        let elevation = sin_elevation.clamp(-1.0, 1.0).asin();
+        let cos_azimuth = (declination.sin() - sin_elevation * latitude.sin()) /
+            (elevation.cos() * latitude.cos()).max(1e-6);
+        let mut azimuth = cos_azimuth.clamp(-1.0, 1.0).acos();
+        // In the afternoon, the sun is in the west.
+        if hour_angle > 0.0
+        {
+            azimuth = 2.0 * PI - azimuth;
+        }
+        Self::new(elevation * 180.0 / PI, azimuth * 180.0 / PI)
+    }
+
+    /// Set the turbidity, from 2 (very clear) to about 10 (hazy).
+    pub fn withTurbidity(mut self, turbidity: Float) -> Self
+    {
+        self.turbidity = turbidity;
+        self
+    }
+
+    /// Set the albedo of the ground below the horizon.
+    pub fn withGroundAlbedo(mut self, albedo: Color) -> Self
+    {
+        self.ground_albedo = albedo;
+        self
+    }
+
+    /// Multiply the radiance of the sky and the sun by `scale`.
+    pub fn withScale(mut self, scale: Float) -> Self
+    {
+        self.scale = scale;
+        self
+    }
+
+    /// Return the unit direction toward the sun.
+    pub fn sunDirection(&self) -> Vec3
+    {
+        self.sun
+    }
+
+    /// Return the zenith angle of the sun. The model does not hold
+    /// below the horizon, so there it is at the horizon.
+    fn sunTheta(&self) -> Float
+    {
+        self.sun[1].clamp(0.0, 1.0).acos()
+    }
+
+    /// Return the radiance of the sky from unit direction `dir`,
+    /// which is above the horizon. The sun is not included.
+    pub fn radiance(&self, dir: &Vec3) -> Color
+    {
+        let t = self.turbidity;
+        let theta_s = self.sunTheta();
+        let theta = dir[1].clamp(0.0, 1.0).acos();
+        let gamma = vec3::dot(dir, &self.sun).clamp(-1.0, 1.0).acos();
+
+        let luminance = Perez {
+            a: 0.1787 * t - 1.4630, b: -0.3554 * t + 0.4275, c: -0.0227 * t + 5.3251,
+            d: 0.1206 * t - 2.5771, e: -0.0670 * t + 0.3703,
+        };
+        let x = Perez {
+            a: -0.0193 * t - 0.2592, b: -0.0665 * t + 0.0008, c: -0.0004 * t + 0.2125,
+            d: -0.0641 * t - 0.8989, e: -0.0033 * t + 0.0452,
+        };
+        let y = Perez {
+            a: -0.0167 * t - 0.2608, b: -0.0950 * t + 0.0092, c: -0.0079 * t + 0.2102,
+            d: -0.0441 * t - 1.6537, e: -0.0109 * t + 0.0529,
+        };
+
+        // The values at the zenith, with luminance in kcd/m².
+        let chi = (4.0 / 9.0 - t / 120.0) * (PI - 2.0 * theta_s);
+        let zenith_luminance = (4.0453 * t - 4.9710) * chi.tan() - 0.2155 * t + 2.4192;
+        let (s, s2, s3) = (theta_s, theta_s * theta_s, theta_s * theta_s * theta_s);
+        let zenith_x = t * t * (0.00166 * s3 - 0.00375 * s2 + 0.00209 * s) +
+            t * (-0.02903 * s3 + 0.06377 * s2 - 0.03202 * s + 0.00394) +
+            (0.11693 * s3 - 0.21196 * s2 + 0.06052 * s + 0.25886);
+        let zenith_y = t * t * (0.00275 * s3 - 0.00610 * s2 + 0.00317 * s) +
+            t * (-0.04214 * s3 + 0.08970 * s2 - 0.04153 * s + 0.00516) +
+            (0.15346 * s3 - 0.26756 * s2 + 0.06670 * s + 0.26688);
+
+        let relative = |p: &Perez| p.eval(theta, gamma) / p.eval(0.0, theta_s);
+        let big_y = (zenith_luminance * relative(&luminance)).max(0.0) * 0.1;
+        let small_x = zenith_x * relative(&x);
+        let small_y = zenith_y * relative(&y);
+        if small_y <= 0.0
+        {
+            return Color::origin();
+        }
+        let xyz = Vec3::new(small_x / small_y * big_y, big_y,
+                            (1.0 - small_x - small_y) / small_y * big_y);
+        // Saturated colors near the sun may be out of the gamut.
+        let c = spectrum::xyzToLinearSrgb(&xyz);
+        Color::new(c[0].max(0.0), c[1].max(0.0), c[2].max(0.0)) * self.scale
+    }
+
+    /// Return the irradiance of the sun on a surface facing it, after
+    /// going through the atmosphere. It is 0 below the horizon.
+    pub fn sunIrradiance(&self) -> Color
+    {
+        if self.sun[1] <= 0.0
+        {
+            return Color::origin();
+        }
+        // The relative optical mass of air (Kasten), and the
+        // extinction by molecules (Rayleigh) and by aerosols
+        // (Ångström), at the red, green and blue wavelengths in µm.
+        let theta_degrees = self.sunTheta() * 180.0 / PI;
+        let mass = 1.0 / (self.sun[1] + 0.15 * (93.885 - theta_degrees).powf(-1.253));
+        let beta = 0.04608 * self.turbidity - 0.04586;
+        let transmittance = |lambda: Float| {
+            let rayleigh = 0.008735 * lambda.powf(-4.08);
+            let aerosol = beta * lambda.powf(-1.3);
+            (-mass * (rayleigh + aerosol)).exp()
+        };
+        Color::new(transmittance(0.61), transmittance(0.55), transmittance(0.465)) *
+            SUN_ILLUMINANCE * self.scale
+    }
+
+    /// Return the lights of the sky and the sun. The sky is tabulated
+    /// in an image to importance sample it, with the ground below the
+    /// horizon.
+    pub fn lights(&self) -> Vec<AnyLight>
+    {
+        let mut data = Vec::with_capacity(TABLE_WIDTH * TABLE_HEIGHT);
+        let mut sky_irradiance = Color::origin();
+        let texel_area = 2.0 * PI * PI / (TABLE_WIDTH * TABLE_HEIGHT) as Float;
+        for y in 0..TABLE_HEIGHT / 2
+        {
+            for x in 0..TABLE_WIDTH
+            {
+                let dir = environment::equirectangularDirection(
+                    (x as Float + 0.5) / TABLE_WIDTH as Float,
+                    (y as Float + 0.5) / TABLE_HEIGHT as Float);
+                let radiance = self.radiance(&dir);
+                // The texel covers solid angle sin θ dθ dφ.
+                let weight = dir[1] * (1.0 - dir[1] * dir[1]).sqrt() * texel_area;
+                sky_irradiance += radiance * weight;
+                data.push(radiance);
+            }
+        }
+        let irradiance = sky_irradiance + self.sunIrradiance() * self.sun[1].max(0.0);
+        let ground = self.ground_albedo * irradiance / PI;
+        data.resize(TABLE_WIDTH * TABLE_HEIGHT, ground);
+
+        let image = Image::new(data, TABLE_WIDTH as u32, TABLE_HEIGHT as u32);
+        let mut lights: Vec<AnyLight> = vec![Arc::new(EnvironmentLight::new(Arc::new(image)))];
+        if self.sun[1] > 0.0
+        {
+            lights.push(Arc::new(DirectionalLight::new(-self.sun, self.sunIrradiance())
+                                 .withAngularDiameter(SUN_DIAMETER)));
+        }
+        lights
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use crate::texture;
+
+    #[test]
+    fn zenithMatchesPreethamLuminance()
+    {
+        // With turbidity 3 and the sun 30° high, Preetham's zenith
+        // luminance is 5.14 kcd/m².
+        let sky = PhysicalSky::new(30.0, 0.0).withTurbidity(3.0);
+        let zenith = sky.radiance(&Vec3::new(0.0, 1.0, 0.0));
+        assert!((texture::luminance(&zenith) - 0.514).abs() < 5e-3);
+        // The zenith of a clear sky is blue.
+        assert!(zenith[2] > zenith[0]);
+
+        // Around the sun the sky is brighter than opposite it.
+        let toward = Vec3::unit(&Vec3::new(0.0, 0.6, -1.0));
+        let away = Vec3::unit(&Vec3::new(0.0, 0.6, 1.0));
+        assert!(texture::luminance(&sky.radiance(&toward)) >
+                2.0 * texture::luminance(&sky.radiance(&away)));
+    }
+
+    #[test]
+    fn sunIsPlacedByDateAndReddensLow()
+    {
+        // At noon on the summer solstice at 48°N, the sun is 65.4°
+        // high, in the south.
+        let sun = PhysicalSky::at(172, 12.0, 48.0).sunDirection();
+        assert!((sun[1].asin() * 180.0 / PI - 65.44).abs() < 0.05);
+        assert!(sun[0].abs() < 1e-4 && sun[2] > 0.0);
+        // In the afternoon, it is in the west.
+        assert!(PhysicalSky::at(172, 16.0, 48.0).sunDirection()[0] < 0.0);
+
+        let high = PhysicalSky::new(60.0, 0.0).sunIrradiance();
+        let low = PhysicalSky::new(5.0, 0.0).sunIrradiance();
+        assert!(high[1] < SUN_ILLUMINANCE && low[1] < high[1]);
+        assert!(low[2] / low[0] < high[2] / high[0]);
+        assert_eq!(PhysicalSky::new(-5.0, 0.0).sunIrradiance().norm(), 0.0);
+    }
+
+    #[test]
+    fn lightsAreTheSkyAndTheSun()
+    {
+        let sky = PhysicalSky::new(40.0, 90.0).withScale(2.0);
+        let lights = sky.lights();
+        assert_eq!(lights.len(), 2);
+        let dir = Vec3::unit(&Vec3::new(0.3, 1.0, -0.5));
+        let seen = lights[0].environment(&dir).unwrap();
+        assert!((seen - sky.radiance(&dir)).norm() < 0.05 * sky.radiance(&dir).norm());
+        assert_eq!(PhysicalSky::new(-10.0, 0.0).lights().len(), 1);
+    }
+}
@@ -0,0 +1,55 @@
+use std::sync::Arc;
+
+use crate::config::Float;
+use crate::geometry::{Vec3, Color};
+use super::bvh::LightBounds;
+
+/// Light arriving at a point from a light source.
+pub struct LightSample
+{
+    /// The unit direction from the point to the light.
+    pub dir: Vec3,
+    /// The distance to the light along `dir`. Infinite for lights
+    /// infinitely far away.
+    pub distance: Float,
+    /// The radiance arriving along `dir`, over the probability
+    /// density of sampling it. For lights without area, this is the
+    /// irradiance on a surface facing the light.
+    pub radiance: Color,
+    /// The probability density per solid angle of sampling `dir`.
+    /// Infinite for lights that rays cannot hit.
+    pub pdf: Float,
+}
+
+pub trait Light
+{
+    /// Sample the light arriving at `p`, ignoring anything in the
+    /// way. Return None if there is none.
+    fn sample(&self, p: &Vec3) -> Option<LightSample>;
+
+    /// Return the probability density per solid angle of `sample` at
+    /// `p` choosing unit direction `dir`. It is 0 for lights that
+    /// rays cannot hit, which is the default.
+    fn pdf(&self, _p: &Vec3, _dir: &Vec3) -> Float
+    {
+        0.0
+    }
+
+    /// For lights around the scene, infinitely far away, return the
+    /// radiance that rays escaping the scene along unit direction
+    /// `dir` see. None for other lights, which is the default.
+    fn environment(&self, _dir: &Vec3) -> Option<Color>
+    {
+        None
+    }
+
+    /// Return bounds of the light emitted, to choose among many
+    /// lights. None for lights infinitely far away, which is the
+    /// default.
+    fn bounds(&self) -> Option<LightBounds>
+    {
+        None
+    }
+}
+
+pub type AnyLight = Arc<dyn Light + Send + Sync>;
@@ -1,20 +1,82 @@
 #![allow(non_snake_case)]
 
 extern crate image;
-extern crate rand;
-extern crate rayon;
 
-mod config;
-mod geometry;
-mod texture;
-mod material;
-mod scene;
-mod render;
-mod ref_scene_1;
+use std::path::Path;
+
+use lorentz::{scene, render, texture, ref_scene_1, ref_scene_2, ref_scene_3};
+
+/// The options on the command line, which start with "--".
+struct Options
+{
+    /// "--stats": print how the acceleration structures were built.
+    stats: bool,
+    /// "--filter=nearest|bilinear|trilinear|ewa": how scene 2 filters
+    /// the texture of its floor. EWA by default.
+    filter: texture::Filter,
+}
+
+impl Options
+{
+    fn parse(args: &[String]) -> Self
+    {
+        let mut options = Self { stats: false, filter: texture::Filter::Ewa };
+        for arg in args
+        {
+            match arg.as_str()
+            {
+                "--stats" => options.stats = true,
+                "--filter=nearest" => options.filter = texture::Filter::Nearest,
+                "--filter=bilinear" => options.filter = texture::Filter::Bilinear,
+                "--filter=trilinear" => options.filter = texture::Filter::Trilinear,
+                "--filter=ewa" => options.filter = texture::Filter::Ewa,
+                _ => panic!("Unknown option {}", arg),
+            }
+        }
+        options
+    }
+}
+
+/// Build the scene called `name`, or return None if there is no such
+/// scene. Some scenes use the image file at `image`, if given.
+fn buildScene(name: &str, width: u32, height: u32, image: Option<&Path>, options: &Options) ->
+    Option<scene::Scene>
+{
+    match name
+    {
+        "1" => Some(ref_scene_1::buildScene(width, height)),
+        "2" =>
+        {
+            let (scene, instances) = ref_scene_2::buildScene(
+                width, height, image, options.filter);
+            if options.stats
+            {
+                println!("{}", instances);
+            }
+            Some(scene)
+        },
+        "3" => Some(ref_scene_3::buildScene(width, height, image)),
+        "4" => Some(ref_scene_3::buildSkyScene(width, height)),
+        _ => None,
+    }
+}
 
 fn main()
 {
-    let img = render::render();
+    // The scene to render is the first argument, by default the first
+    // reference scene. The second argument is an optional image file
+    // for the scene. Options may go anywhere.
+    let (options, args): (Vec<String>, Vec<String>) =
+        std::env::args().skip(1).partition(|arg| arg.starts_with("--"));
+    let options = Options::parse(&options);
+    let name = args.first().map_or("1", |s| s.as_str());
+    let image = args.get(1).map(Path::new);
+    let scene = match buildScene(name, 800, 500, image, &options)
+    {
+        Some(scene) => scene,
+        None => panic!("Unknown scene {}", name),
+    };
+    let img = render::render(scene);
     let width = img.width();
     let height = img.height();
 
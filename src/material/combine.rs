@@ -0,0 +1,349 @@
+use crate::rand;
+use crate::config::Float;
+use crate::geometry::vec3;
+use crate::geometry::{Vec3, Color, Ray, Hit};
+use crate::texture::{self, AnyTexture};
+use crate::medium::AnyMedium;
+use super::material::{Material, AnyMaterial};
+use super::microfacet::{self, ShadingFrame};
+
+/// A random choice between two materials at every scatter, e.g. rust
+/// patches on a metal.
+pub struct MixMaterial
+{
+    a: AnyMaterial,
+    b: AnyMaterial,
+    factor: AnyTexture,
+}
+
+impl MixMaterial
+{
+    /// Use `a` where the luminance of `factor` is 0, and `b` where it
+    /// is 1.
+    pub fn new(a: AnyMaterial, b: AnyMaterial, factor: AnyTexture) -> Self
+    {
+        Self { a, b, factor }
+    }
+
+    /// The luminance of `factor` at `hit`, clamped to [0, 1]: the
+    /// probability of `b`, which is also its weight.
+    fn factorAt(&self, hit: &Hit) -> Float
+    {
+        texture::luminance(&self.factor.filtered(hit)).clamp(0.0, 1.0)
+    }
+}
+
+impl Material for MixMaterial
+{
+    fn scatter(&self, r_in: &Ray, hit: &Hit) -> Option<(Ray, Vec3)>
+    {
+        let t = self.factorAt(hit);
+        if rand::random::<Float>() < t
+        {
+            self.b.scatter(r_in, hit)
+        }
+        else
+        {
+            self.a.scatter(r_in, hit)
+        }
+    }
+
+    fn eval(&self, r_in: &Ray, hit: &Hit, wi: &Vec3) -> Color
+    {
+        let t = self.factorAt(hit);
+        (1.0 - t) * self.a.eval(r_in, hit, wi) + t * self.b.eval(r_in, hit, wi)
+    }
+
+    fn pdf(&self, r_in: &Ray, hit: &Hit, wi: &Vec3) -> Float
+    {
+        let t = self.factorAt(hit);
+        (1.0 - t) * self.a.pdf(r_in, hit, wi) + t * self.b.pdf(r_in, hit, wi)
+    }
+
+    fn emitted(&self, r_in: &Ray, hit: &Hit) -> Color
+    {
+        let t = self.factorAt(hit);
+        (1.0 - t) * self.a.emitted(r_in, hit) + t * self.b.emitted(r_in, hit)
+    }
+
+    /// The medium inside is that of `a`, or else of `b`.
+    fn interior(&self) -> Option<&AnyMedium>
+    {
+        self.a.interior().or_else(|| self.b.interior())
+    }
+}
+
+/// A dielectric coat (e.g. varnish) over a base material. Light
+/// either reflects off the coat by its Fresnel reflectance, or goes
+/// through the coat to the base and back out, absorbed by the coat
+/// on the way.
+pub struct Layered
+{
+    base: AnyMaterial,
+    /// Index of refraction of the coat.
+    ior: Float,
+    roughness: Float,
+    /// The color of light after passing through the coat once at
+    /// normal incidence.
+    color: Color,
+}
+
+impl Layered
+{
+    /// Construct a smooth, clear coat of index of refraction `ior`
+    /// over `base`.
+    pub fn new(base: AnyMaterial, ior: Float) -> Self
+    {
+        Self { base, ior, roughness: 0.0, color: Vec3::new(1.0, 1.0, 1.0) }
+    }
+
+    /// Set the perceptual roughness of the coat surface.
+    pub fn withRoughness(mut self, roughness: Float) -> Self
+    {
+        self.roughness = roughness;
+        self
+    }
+
+    /// Set the color of the coat, as the color of light after
+    /// passing through it once at normal incidence. Light at grazing
+    /// angles goes through more of the coat.
+    pub fn withColor(mut self, color: Color) -> Self
+    {
+        self.color = color;
+        self
+    }
+
+    /// Return the cosine inside the coat of light at cosine `cos`
+    /// outside.
+    fn refractedCos(&self, cos: Float) -> Float
+    {
+        let sin2 = (1.0 - cos * cos) / (self.ior * self.ior);
+        (1.0 - sin2).max(1e-4).sqrt()
+    }
+
+    /// The transmittance of the coat along a path with cosine `cos`
+    /// inside.
+    fn transmittance(&self, cos: Float) -> Color
+    {
+        let c = self.color;
+        let power = 1.0 / cos;
+        Vec3::new(c[0].powf(power), c[1].powf(power), c[2].powf(power))
+    }
+}
+
+impl Material for Layered
+{
+    fn scatter(&self, r_in: &Ray, hit: &Hit) -> Option<(Ray, Vec3)>
+    {
+        if !hit.front_face
+        {
+            return self.base.scatter(r_in, hit);
+        }
+        let frame = ShadingFrame::new(hit);
+        let wo = frame.toLocal(&-Vec3::unit(&r_in.dir));
+        if wo[2] <= 0.0
+        {
+            return None;
+        }
+
+        // Pick the coat by its reflectance at the normal, as `pdf`
+        // does, and weight its microfacets by theirs.
+        let f_in = microfacet::fresnelDielectric(wo[2], self.ior);
+        if rand::random::<Float>() < f_in
+        {
+            let (wi, m, weight) = microfacet::sampleGlossy(&wo, self.roughness)?;
+            let weight = weight * microfacet::fresnelDielectric(vec3::dot(&wo, &m), self.ior) / f_in;
+            return Some((hit.spawnRay(frame.toWorld(&wi), r_in.time),
+                         Vec3::new(weight, weight, weight)));
+        }
+
+        // Through the coat. The bending of the light by the coat is
+        // ignored at the base.
+        let (scattered, attenuation) = self.base.scatter(r_in, hit)?;
+        let mut weight = attenuation * self.transmittance(self.refractedCos(wo[2]));
+        let cos_out = vec3::dot(&Vec3::unit(&scattered.dir), &frame.normal);
+        if cos_out > 0.0
+        {
+            // Light reflected back by the coat from the inside is
+            // lost.
+            let f_out = microfacet::fresnelDielectric(cos_out, self.ior);
+            weight = weight * (1.0 - f_out) *
+                self.transmittance(self.refractedCos(cos_out));
+        }
+        Some((scattered, weight))
+    }
+
+    fn eval(&self, r_in: &Ray, hit: &Hit, wi: &Vec3) -> Color
+    {
+        let base = self.base.eval(r_in, hit, wi);
+        if !hit.front_face
+        {
+            return base;
+        }
+        let frame = ShadingFrame::new(hit);
+        let wo = frame.toLocal(&-Vec3::unit(&r_in.dir));
+        let wi_local = frame.toLocal(wi);
+        if wo[2] <= 0.0 || wi_local[2] <= 0.0
+        {
+            return base;
+        }
+        let m = Vec3::unit(&(wo + wi_local));
+        let coat = microfacet::fresnelDielectric(vec3::dot(&wo, &m), self.ior) *
+            microfacet::evalGlossy(&wo, &wi_local, self.roughness);
+        // Light through the coat both ways, as in `scatter`.
+        let f_in = microfacet::fresnelDielectric(wo[2], self.ior);
+        let f_out = microfacet::fresnelDielectric(wi_local[2], self.ior);
+        let through = base * (1.0 - f_in) * (1.0 - f_out) *
+            self.transmittance(self.refractedCos(wo[2])) *
+            self.transmittance(self.refractedCos(wi_local[2]));
+        Vec3::new(coat, coat, coat) + through
+    }
+
+    fn pdf(&self, r_in: &Ray, hit: &Hit, wi: &Vec3) -> Float
+    {
+        let base = self.base.pdf(r_in, hit, wi);
+        if !hit.front_face
+        {
+            return base;
+        }
+        let frame = ShadingFrame::new(hit);
+        let wo = frame.toLocal(&-Vec3::unit(&r_in.dir));
+        let wi_local = frame.toLocal(wi);
+        if wo[2] <= 0.0 || wi_local[2] <= 0.0
+        {
+            return base;
+        }
+        let f_in = microfacet::fresnelDielectric(wo[2], self.ior);
+        f_in * microfacet::pdfGlossy(&wo, &wi_local, self.roughness) + (1.0 - f_in) * base
+    }
+
+    fn emitted(&self, r_in: &Ray, hit: &Hit) -> Color
+    {
+        self.base.emitted(r_in, hit)
+    }
+
+    fn interior(&self) -> Option<&AnyMedium>
+    {
+        self.base.interior()
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use std::sync::Arc;
+    use super::*;
+    use crate::config::PI;
+    use crate::texture::Constant;
+    use crate::medium::Homogeneous;
+    use crate::material::{Lambertian, DiffuseLight, MediumBoundary};
+
+    fn assertNear(a: Float, b: Float, tolerance: Float)
+    {
+        assert!((a - b).abs() <= tolerance, "{} is not near {}", a, b);
+    }
+
+    fn gray(x: Float) -> AnyTexture
+    {
+        Arc::new(Constant::new(Color::new(x, x, x)))
+    }
+
+    /// A ray toward the origin from direction `wo`, hitting the plane
+    /// z = 0 there.
+    fn hit(wo: Vec3) -> (Ray, Hit)
+    {
+        let r = Ray::new(wo, -wo, 0.0);
+        let hit = Hit::new(&r, 1.0, Vec3::new(0.0, 0.0, 1.0), 0.5, 0.5,
+                           Vec3::new(1.0, 0.0, 0.0), Vec3::new(0.0, 1.0, 0.0), 0);
+        (r, hit)
+    }
+
+    #[test]
+    fn mixWeighsEvalEmissionAndKeepsMedium()
+    {
+        let (r, hit) = hit(Vec3::new(0.0, 0.0, 1.0));
+        let medium = Arc::new(Homogeneous::new(Color::new(1.0, 1.0, 1.0), Color::origin()));
+        let mix = MixMaterial::new(Arc::new(DiffuseLight::new(gray(4.0))),
+                                   Arc::new(MediumBoundary::new(medium)), gray(0.25));
+        assertNear(mix.emitted(&r, &hit)[0], 3.0, 1e-5);
+        assert!(mix.interior().is_some());
+
+        let mix = MixMaterial::new(Arc::new(Lambertian { albedo: gray(0.8) }),
+                                   Arc::new(Lambertian { albedo: gray(0.4) }), gray(0.25));
+        let wi = Vec3::new(0.0, 0.0, 1.0);
+        assertNear(mix.eval(&r, &hit, &wi)[0], 0.7 / PI, 1e-4);
+        assertNear(mix.pdf(&r, &hit, &wi), 1.0 / PI, 1e-4);
+        assert!(mix.interior().is_none());
+        assertNear(mix.emitted(&r, &hit)[0], 0.0, 1e-6);
+    }
+
+    #[test]
+    fn mixFactorIsClamped()
+    {
+        let (r, hit) = hit(Vec3::new(0.0, 0.0, 1.0));
+        let wi = Vec3::new(0.0, 0.0, 1.0);
+        for &(factor, albedo) in &[(-0.5, 0.8), (1.5, 0.4)]
+        {
+            let mix = MixMaterial::new(Arc::new(Lambertian { albedo: gray(0.8) }),
+                                       Arc::new(Lambertian { albedo: gray(0.4) }), gray(factor));
+            assertNear(mix.eval(&r, &hit, &wi)[0], albedo / PI, 1e-4);
+            assertNear(mix.pdf(&r, &hit, &wi), 1.0 / PI, 1e-4);
+            for _ in 0..100
+            {
+                assertNear(mix.scatter(&r, &hit).unwrap().1[0], albedo, 1e-4);
+            }
+        }
+    }
+
+    #[test]
+    fn coatReflectsByFresnelAndConservesEnergy()
+    {
+        let white: AnyMaterial = Arc::new(Lambertian { albedo: gray(1.0) });
+        let varnish = Layered::new(white, 1.5);
+        for &cos in &[1.0 as Float, 0.5, 0.1]
+        {
+            let sin = (1.0 - cos * cos).sqrt();
+            let (r, hit) = hit(Vec3::new(sin, 0.0, cos));
+            let mirror = Vec3::new(-sin, 0.0, cos);
+            let samples = 20000;
+            let mut mean = 0.0;
+            let mut coat = 0;
+            for _ in 0..samples
+            {
+                let (scattered, weight) = varnish.scatter(&r, &hit).unwrap();
+                mean += weight[0] / samples as Float;
+                // The smooth coat reflects into the mirror direction.
+                let dir = Vec3::unit(&scattered.dir);
+                if (dir - mirror).norm() < 1e-4
+                {
+                    coat += 1;
+                }
+            }
+            assert!(mean <= 1.0, "{} at cosine {}", mean, cos);
+            assertNear(coat as Float / samples as Float,
+                       microfacet::fresnelDielectric(cos, 1.5), 0.01);
+        }
+    }
+
+    #[test]
+    fn roughCoatPdfIsTheDensityOfScatter()
+    {
+        // If `pdf` is the density of the scattered directions, the
+        // mean of cos / pdf is the integral of the cosine over the
+        // hemisphere. Seen at a grazing angle, the coat reflects much.
+        let white: AnyMaterial = Arc::new(Lambertian { albedo: gray(1.0) });
+        let varnish = Layered::new(white, 1.5).withRoughness(0.4);
+        let (r, hit) = hit(Vec3::unit(&Vec3::new(0.95, 0.0, 0.2)));
+        let samples = 40000;
+        let mut mean = 0.0;
+        for _ in 0..samples
+        {
+            if let Some((scattered, _)) = varnish.scatter(&r, &hit)
+            {
+                let wi = Vec3::unit(&scattered.dir);
+                mean += wi[2] / varnish.pdf(&r, &hit, &wi) / samples as Float;
+            }
+        }
+        assertNear(mean, PI, 0.03);
+    }
+}
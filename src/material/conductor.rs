@@ -0,0 +1,161 @@
+use crate::rand;
+use crate::config::Float;
+use crate::geometry::vec3;
+use crate::geometry::{Vec3, Color, Ray, Hit};
+use crate::texture::AnyTexture;
+use crate::spectrum::{self, Wavelengths};
+use super::material::{self, Material};
+use super::microfacet::{self, Ggx, ShadingFrame};
+use super::thin_film::{ThinFilm, FilmInterface};
+
+/// The complex index of refraction (`eta`, `k`) of a metal, at the
+/// red, green and blue wavelengths.
+#[derive(Clone, Copy, Debug)]
+pub struct MetalIor
+{
+    pub eta: Vec3,
+    pub k: Vec3,
+}
+
+pub const GOLD: MetalIor = MetalIor {
+    eta: Vec3::new(0.143, 0.374, 1.442),
+    k: Vec3::new(3.983, 2.385, 1.603),
+};
+
+pub const COPPER: MetalIor = MetalIor {
+    eta: Vec3::new(0.200, 0.924, 1.102),
+    k: Vec3::new(3.912, 2.452, 2.142),
+};
+
+pub const ALUMINIUM: MetalIor = MetalIor {
+    eta: Vec3::new(1.657, 0.880, 0.521),
+    k: Vec3::new(9.224, 6.270, 4.837),
+};
+
+pub const SILVER: MetalIor = MetalIor {
+    eta: Vec3::new(0.155, 0.117, 0.138),
+    k: Vec3::new(4.828, 3.122, 2.147),
+};
+
+/// A metal with GGX microfacets. The color comes from the Fresnel
+/// reflectance of its complex index of refraction.
+pub struct Conductor
+{
+    pub ior: MetalIor,
+    pub distribution: Ggx,
+    /// A thin film coating the surface, e.g. an oxide layer.
+    pub film: Option<ThinFilm>,
+}
+
+impl Conductor
+{
+    /// Construct with isotropic perceptual `roughness` in [0, 1].
+    pub fn new(ior: MetalIor, roughness: Float) -> Self
+    {
+        let alpha = microfacet::roughnessToAlpha(roughness);
+        Self { ior, distribution: Ggx::new(alpha, alpha), film: None }
+    }
+
+    /// Set different roughness along the tangent (`roughness_u`) and
+    /// the bitangent (`roughness_v`) of the surface.
+    pub fn withAnisotropy(mut self, roughness_u: Float, roughness_v: Float) -> Self
+    {
+        self.distribution = Ggx::new(microfacet::roughnessToAlpha(roughness_u),
+                                     microfacet::roughnessToAlpha(roughness_v));
+        self
+    }
+
+    /// Coat the surface with a thin film of index of refraction
+    /// `ior`, `thickness` nm thick, e.g. the oxide on heated steel.
+    pub fn withThinFilm(mut self, thickness: AnyTexture, ior: Float) -> Self
+    {
+        self.film = Some(ThinFilm::new(thickness, ior));
+        self
+    }
+
+    fn fresnel(&self, hit: &Hit, cos: Float, wavelengths: &mut Option<Wavelengths>) -> Color
+    {
+        match &self.film
+        {
+            // The index of refraction of the metal is only known at
+            // RGB, so its spectrum is upsampled.
+            Some(film) => film.reflectanceAt(hit, cos, |lambda| FilmInterface {
+                outside: 1.0,
+                substrate_eta: spectrum::rgbToSpectrum(&self.ior.eta, lambda),
+                substrate_k: spectrum::rgbToSpectrum(&self.ior.k, lambda),
+            }, wavelengths),
+            None => microfacet::fresnelConductorRgb(cos, &self.ior.eta, &self.ior.k),
+        }
+    }
+}
+
+impl Material for Conductor
+{
+    fn scatter(&self, r_in: &Ray, hit: &Hit) -> Option<(Ray, Vec3)>
+    {
+        let frame = ShadingFrame::new(hit);
+        let wo = frame.toLocal(&-Vec3::unit(&r_in.dir));
+        if wo[2] <= 0.0
+        {
+            return None;
+        }
+
+        // A film in spectral mode may terminate the secondary
+        // wavelengths.
+        let mut wavelengths = r_in.wavelengths;
+        if self.distribution.isSmooth()
+        {
+            let wi = Vec3::new(-wo[0], -wo[1], wo[2]);
+            let f = self.fresnel(hit, wo[2], &mut wavelengths);
+            let mut scattered = hit.spawnRay(frame.toWorld(&wi), r_in.time);
+            scattered.wavelengths = wavelengths;
+            return Some((scattered, f));
+        }
+
+        let m = self.distribution.sampleVisibleNormal(
+            &wo, rand::random::<Float>(), rand::random::<Float>());
+        let wi = microfacet::reflectAbout(&wo, &m);
+        if wi[2] <= 0.0
+        {
+            return None;
+        }
+        // With visible normal sampling, the weight is F · G2 / G1(wo).
+        let cos = vec3::dot(&wo, &m);
+        let weight = self.distribution.g(&wo, &wi) / self.distribution.g1(&wo);
+        let f = self.fresnel(hit, cos, &mut wavelengths);
+        let mut scattered = hit.spawnRay(frame.toWorld(&wi), r_in.time);
+        scattered.wavelengths = wavelengths;
+        Some((scattered, f * weight))
+    }
+
+    fn eval(&self, r_in: &Ray, hit: &Hit, wi: &Vec3) -> Color
+    {
+        if self.distribution.isSmooth() || !material::isReflection(r_in, hit, wi)
+        {
+            return Color::origin();
+        }
+        let frame = ShadingFrame::new(hit);
+        let wo = frame.toLocal(&-Vec3::unit(&r_in.dir));
+        let wi = frame.toLocal(wi);
+        let value = self.distribution.evalReflection(&wo, &wi);
+        if value <= 0.0
+        {
+            return Color::origin();
+        }
+        // As in scatter, a film reflects by the hero wavelength.
+        let mut wavelengths = r_in.wavelengths;
+        let m = Vec3::unit(&(wo + wi));
+        self.fresnel(hit, vec3::dot(&wo, &m), &mut wavelengths) * value
+    }
+
+    fn pdf(&self, r_in: &Ray, hit: &Hit, wi: &Vec3) -> Float
+    {
+        if self.distribution.isSmooth() || !material::isReflection(r_in, hit, wi)
+        {
+            return 0.0;
+        }
+        let frame = ShadingFrame::new(hit);
+        let wo = frame.toLocal(&-Vec3::unit(&r_in.dir));
+        self.distribution.pdfReflection(&wo, &frame.toLocal(wi))
+    }
+}
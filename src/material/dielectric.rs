@@ -0,0 +1,448 @@
+use crate::rand;
+use crate::config::Float;
+use crate::geometry::vec3;
+use crate::geometry::{Vec3, Color, Ray, Hit};
+use crate::texture::AnyTexture;
+use crate::spectrum::Wavelengths;
+use super::material::{self, Material};
+use super::microfacet::{self, Ggx, ShadingFrame};
+use super::thin_film::{ThinFilm, FilmInterface};
+
+/// How the index of refraction of a dielectric varies with the
+/// wavelength, with the wavelength in µm in the formulas.
+#[derive(Clone, Copy, Debug)]
+pub enum Dispersion
+{
+    /// n = a + b / λ².
+    Cauchy { a: Float, b: Float },
+    /// n² = 1 + Σ b_i λ² / (λ² − c_i).
+    Sellmeier { b: [Float; 3], c: [Float; 3] },
+}
+
+impl Dispersion
+{
+    /// The Sellmeier coefficients of Schott N-BK7 crown glass.
+    pub const BK7: Dispersion = Dispersion::Sellmeier {
+        b: [1.039612, 0.2317923, 1.010469],
+        c: [0.006000699, 0.02001791, 103.5607],
+    };
+
+    /// Return the index of refraction at wavelength `lambda` in nm.
+    pub fn ior(&self, lambda: Float) -> Float
+    {
+        let l2 = (lambda * 1e-3) * (lambda * 1e-3);
+        match self
+        {
+            Dispersion::Cauchy { a, b } => a + b / l2,
+            Dispersion::Sellmeier { b, c } =>
+            {
+                let sum: Float = (0..3).map(|i| b[i] * l2 / (l2 - c[i])).sum();
+                (1.0 + sum).sqrt()
+            },
+        }
+    }
+}
+
+/// The wavelength at which the index of refraction of dispersive
+/// dielectrics is taken outside of spectral mode: the sodium D line,
+/// in nm.
+const D_LINE: Float = 589.3;
+
+/// A dielectric (glass, water, etc.) with exact Fresnel, optionally
+/// rough with GGX microfacets, optionally absorbing light inside by
+/// the Beer–Lambert law, and optionally dispersive.
+pub struct Dielectric
+{
+    /// Index of refraction of the inside over the outside.
+    pub ior: Float,
+    /// The variation of `ior` with the wavelength, used in spectral
+    /// mode.
+    pub dispersion: Option<Dispersion>,
+    pub distribution: Ggx,
+    /// The absorption coefficient inside, per unit distance.
+    pub absorption: Vec3,
+    /// Whether the surface is a thin sheet (e.g. a window pane),
+    /// which does not bend the light going through.
+    pub thin: bool,
+    /// A thin film coating the outside of the surface.
+    pub film: Option<ThinFilm>,
+}
+
+impl Dielectric
+{
+    /// Construct a smooth, clear dielectric.
+    pub fn new(ior: Float) -> Self
+    {
+        Self { ior, dispersion: None, distribution: Ggx::new(0.0, 0.0),
+               absorption: Vec3::origin(), thin: false, film: None }
+    }
+
+    /// Set isotropic perceptual `roughness` in [0, 1].
+    pub fn withRoughness(mut self, roughness: Float) -> Self
+    {
+        let alpha = microfacet::roughnessToAlpha(roughness);
+        self.distribution = Ggx::new(alpha, alpha);
+        self
+    }
+
+    /// Make light that travels `distance` inside the medium keep
+    /// `color` of its power. Thicker parts are darker.
+    pub fn withAbsorption(mut self, color: Color, distance: Float) -> Self
+    {
+        let coef = |c: Float| -c.max(1e-6).ln() / distance;
+        self.absorption = Vec3::new(coef(color[0]), coef(color[1]), coef(color[2]));
+        self
+    }
+
+    /// Make the index of refraction vary with the wavelength by
+    /// `dispersion`. Outside of spectral mode, the index at the
+    /// sodium D line is used.
+    pub fn withDispersion(mut self, dispersion: Dispersion) -> Self
+    {
+        self.ior = dispersion.ior(D_LINE);
+        self.dispersion = Some(dispersion);
+        self
+    }
+
+    /// Disperse by Cauchy's equation, n = a + b / λ² with λ in µm.
+    pub fn withCauchy(self, a: Float, b: Float) -> Self
+    {
+        self.withDispersion(Dispersion::Cauchy { a, b })
+    }
+
+    /// Disperse by the Sellmeier equation, with λ in µm.
+    pub fn withSellmeier(self, b: [Float; 3], c: [Float; 3]) -> Self
+    {
+        self.withDispersion(Dispersion::Sellmeier { b, c })
+    }
+
+    /// Make the surface thin walled. Absorption does not apply to
+    /// thin walls.
+    pub fn withThinWalls(mut self) -> Self
+    {
+        self.thin = true;
+        self
+    }
+
+    /// Coat the outside with a thin film of index of refraction
+    /// `ior`, `thickness` nm thick, e.g. a soap bubble (a thin walled
+    /// dielectric of index 1 with a film of index 1.33) or a lens
+    /// coating.
+    pub fn withThinFilm(mut self, thickness: AnyTexture, ior: Float) -> Self
+    {
+        self.film = Some(ThinFilm::new(thickness, ior));
+        self
+    }
+
+    /// The index of refraction at wavelength `lambda` in nm.
+    fn iorAt(&self, lambda: Float) -> Float
+    {
+        self.dispersion.map_or(self.ior, |d| d.ior(lambda))
+    }
+
+    /// The index of refraction for a path along `r`: with dispersion
+    /// in spectral mode, that at the hero wavelength. Thin walls do
+    /// not disperse.
+    fn rayIor(&self, r: &Ray) -> Float
+    {
+        match (self.dispersion, r.wavelengths)
+        {
+            (Some(dispersion), Some(w)) if !self.thin => dispersion.ior(w.hero()),
+            _ => self.ior,
+        }
+    }
+
+    /// The reflectance at `hit` through microfacet `m` as seen from
+    /// `wo`.
+    fn fresnel(&self, hit: &Hit, wo: &Vec3, m: &Vec3, eta: Float,
+               wavelengths: &mut Option<Wavelengths>) -> Color
+    {
+        let mut f = self.interfaceFresnel(hit, wo, m, eta, wavelengths);
+        if self.thin
+        {
+            // Sum of all the internal reflections in the sheet.
+            for i in 0..3
+            {
+                f[i] = 2.0 * f[i] / (1.0 + f[i]);
+            }
+        }
+        f
+    }
+
+    /// The reflectance of a single interface, as in `fresnel`.
+    fn interfaceFresnel(&self, hit: &Hit, wo: &Vec3, m: &Vec3, eta: Float,
+                        wavelengths: &mut Option<Wavelengths>) -> Color
+    {
+        let cos = vec3::dot(wo, m);
+        let film = match &self.film
+        {
+            Some(film) => film,
+            None =>
+            {
+                let f = microfacet::fresnelDielectric(cos, eta);
+                return Vec3::new(f, f, f);
+            },
+        };
+        let inside = !hit.front_face && !self.thin;
+        film.reflectanceAt(hit, cos, |lambda| {
+            let ior = self.iorAt(lambda);
+            FilmInterface {
+                outside: if inside { ior } else { 1.0 },
+                substrate_eta: if inside { 1.0 } else { ior },
+                substrate_k: 0.0,
+            }
+        }, wavelengths)
+    }
+
+    /// Choose between reflection and transmission through microfacet
+    /// `m` as seen from `wo`. Return the local scattered direction
+    /// and its weight, or None if it goes to the wrong side of the
+    /// surface.
+    fn sampleDirection(&self, hit: &Hit, wo: &Vec3, m: &Vec3, eta: Float,
+                       wavelengths: &mut Option<Wavelengths>) -> Option<(Vec3, Color)>
+    {
+        let f = self.fresnel(hit, wo, m, eta, wavelengths);
+        // Reflect by the average reflectance. It only differs from
+        // the reflectance of each channel with a film.
+        let white = Vec3::new(1.0, 1.0, 1.0);
+        let p = (f[0] + f[1] + f[2]) / 3.0;
+        let reflected = microfacet::reflectAbout(wo, m);
+        if rand::random::<Float>() < p
+        {
+            return if reflected[2] > 0.0 { Some((reflected, f / p)) } else { None };
+        }
+        let weight = (white - f) / (1.0 - p);
+
+        let transmitted = if self.thin
+        {
+            Vec3::new(reflected[0], reflected[1], -reflected[2])
+        }
+        else
+        {
+            microfacet::refractAbout(wo, m, eta)?
+        };
+        if transmitted[2] < 0.0 { Some((transmitted, weight)) } else { None }
+    }
+}
+
+impl Material for Dielectric
+{
+    fn scatter(&self, r_in: &Ray, hit: &Hit) -> Option<(Ray, Vec3)>
+    {
+        let frame = ShadingFrame::new(hit);
+        let wo = frame.toLocal(&-Vec3::unit(&r_in.dir));
+        if wo[2] <= 0.0
+        {
+            return None;
+        }
+        // With dispersion in spectral mode, each wavelength bends
+        // differently, so only the hero wavelength goes on.
+        let mut wavelengths = r_in.wavelengths;
+        let ior = self.rayIor(r_in);
+        if let (Some(_), Some(w)) = (self.dispersion, wavelengths.as_mut())
+        {
+            if !self.thin
+            {
+                w.terminateSecondary();
+            }
+        }
+        let eta = if hit.front_face || self.thin { ior } else { 1.0 / ior };
+
+        // Arriving from the inside, the light has gone through the
+        // medium since the last surface.
+        let mut attenuation = Vec3::new(1.0, 1.0, 1.0);
+        if !hit.front_face && !self.thin
+        {
+            let distance = hit.t * r_in.dir.norm();
+            attenuation = Vec3::new((-self.absorption[0] * distance).exp(),
+                                    (-self.absorption[1] * distance).exp(),
+                                    (-self.absorption[2] * distance).exp());
+        }
+
+        if self.distribution.isSmooth()
+        {
+            let (wi, f) = self.sampleDirection(hit, &wo, &Vec3::new(0.0, 0.0, 1.0), eta,
+                                               &mut wavelengths)?;
+            let mut scattered = hit.spawnRay(frame.toWorld(&wi), r_in.time);
+            scattered.wavelengths = wavelengths;
+            return Some((scattered, attenuation * f));
+        }
+
+        let m = self.distribution.sampleVisibleNormal(
+            &wo, rand::random::<Float>(), rand::random::<Float>());
+        let (wi, f) = self.sampleDirection(hit, &wo, &m, eta, &mut wavelengths)?;
+        // With visible normal sampling and Fresnel chosen by
+        // probability, the weight is G2 / G1(wo).
+        let weight = self.distribution.g(&wo, &wi) / self.distribution.g1(&wo);
+        let mut scattered = hit.spawnRay(frame.toWorld(&wi), r_in.time);
+        scattered.wavelengths = wavelengths;
+        Some((scattered, attenuation * f * weight))
+    }
+
+    /// Only the reflection off rough surfaces is evaluated.
+    fn eval(&self, r_in: &Ray, hit: &Hit, wi: &Vec3) -> Color
+    {
+        if self.distribution.isSmooth() || !material::isReflection(r_in, hit, wi)
+        {
+            return Color::origin();
+        }
+        let frame = ShadingFrame::new(hit);
+        let wo = frame.toLocal(&-Vec3::unit(&r_in.dir));
+        let wi = frame.toLocal(wi);
+        let value = self.distribution.evalReflection(&wo, &wi);
+        if value <= 0.0
+        {
+            return Color::origin();
+        }
+        let ior = self.rayIor(r_in);
+        let eta = if hit.front_face || self.thin { ior } else { 1.0 / ior };
+        // As in scatter, a film reflects by the hero wavelength.
+        let mut wavelengths = r_in.wavelengths;
+        let m = Vec3::unit(&(wo + wi));
+        self.fresnel(hit, &wo, &m, eta, &mut wavelengths) * value
+    }
+
+    fn pdf(&self, r_in: &Ray, hit: &Hit, wi: &Vec3) -> Float
+    {
+        if self.distribution.isSmooth() || !material::isReflection(r_in, hit, wi)
+        {
+            return 0.0;
+        }
+        let frame = ShadingFrame::new(hit);
+        let wo = frame.toLocal(&-Vec3::unit(&r_in.dir));
+        let wi = frame.toLocal(wi);
+        let pdf = self.distribution.pdfReflection(&wo, &wi);
+        if pdf <= 0.0
+        {
+            return 0.0;
+        }
+        // Reflection is chosen by the average reflectance.
+        let ior = self.rayIor(r_in);
+        let eta = if hit.front_face || self.thin { ior } else { 1.0 / ior };
+        let mut wavelengths = r_in.wavelengths;
+        let m = Vec3::unit(&(wo + wi));
+        let f = self.fresnel(hit, &wo, &m, eta, &mut wavelengths);
+        pdf * (f[0] + f[1] + f[2]) / 3.0
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use std::sync::Arc;
+    use super::*;
+    use crate::texture::Constant;
+
+    fn assertNear(a: Float, b: Float, tolerance: Float)
+    {
+        assert!((a - b).abs() <= tolerance, "{} is not near {}", a, b);
+    }
+
+    /// A ray from `origin` toward the origin, hitting the plane z = 0
+    /// there.
+    fn hit(origin: Vec3) -> (Ray, Hit)
+    {
+        let r = Ray::new(origin, -origin, 0.0);
+        let hit = Hit::new(&r, 1.0, Vec3::new(0.0, 0.0, 1.0), 0.5, 0.5,
+                           Vec3::new(1.0, 0.0, 0.0), Vec3::new(0.0, 1.0, 0.0), 0);
+        (r, hit)
+    }
+
+    #[test]
+    fn dispersionMatchesKnownIndices()
+    {
+        // N-BK7 at the helium d line, and normal dispersion.
+        assertNear(Dispersion::BK7.ior(587.6), 1.5168, 1e-3);
+        assert!(Dispersion::BK7.ior(450.0) > Dispersion::BK7.ior(650.0));
+        let cauchy = Dispersion::Cauchy { a: 1.5, b: 0.01 };
+        assertNear(cauchy.ior(500.0), 1.54, 1e-5);
+        assertNear(Dielectric::new(1.3).withDispersion(cauchy).ior, cauchy.ior(D_LINE), 1e-6);
+    }
+
+    #[test]
+    fn smoothGlassReflectsByFresnelAndRefractsBySnell()
+    {
+        let glass = Dielectric::new(1.5);
+        let (r, hit) = hit(Vec3::new(-1.0, 0.0, 1.0));
+        let sin_i = (0.5 as Float).sqrt();
+        let n = 20000;
+        let mut reflected = 0;
+        for _ in 0..n
+        {
+            let (scattered, weight) = glass.scatter(&r, &hit).unwrap();
+            assertNear(weight[1], 1.0, 1e-5);
+            let dir = Vec3::unit(&scattered.dir);
+            if dir[2] > 0.0
+            {
+                assertNear(dir[0], sin_i, 1e-4);
+                reflected += 1;
+            }
+            else
+            {
+                assertNear(dir[0], sin_i / 1.5, 1e-4);
+            }
+        }
+        let f = microfacet::fresnelDielectric(sin_i, 1.5);
+        assertNear(reflected as Float / n as Float, f, 0.01);
+    }
+
+    #[test]
+    fn absorptionFollowsBeerLambert()
+    {
+        // Half the light is left after a unit distance, so a quarter
+        // after 2.
+        let glass = Dielectric::new(1.5).withAbsorption(Color::new(0.5, 1.0, 1.0), 1.0);
+        let (r, hit) = hit(Vec3::new(0.0, 0.0, -2.0));
+        assert!(!hit.front_face);
+        for _ in 0..100
+        {
+            let (_, weight) = glass.scatter(&r, &hit).unwrap();
+            assertNear(weight[0], 0.25, 1e-4);
+            assertNear(weight[1], 1.0, 1e-4);
+        }
+        // Thin walls do not absorb.
+        let (_, weight) = glass.withThinWalls().scatter(&r, &hit).unwrap();
+        assertNear(weight[0], 1.0, 1e-4);
+    }
+
+    #[test]
+    fn roughDispersionUsesHeroWavelength()
+    {
+        let (mut r, hit) = hit(Vec3::new(-1.0, 0.0, 1.0));
+        r.wavelengths = Some(Wavelengths { lambda: [400.0, 560.0, 720.0], active: 3 });
+        let prism = Dielectric::new(1.5).withDispersion(Dispersion::BK7).withRoughness(0.3);
+        let violet = Dielectric::new(Dispersion::BK7.ior(400.0)).withRoughness(0.3);
+        let wi = Vec3::unit(&Vec3::new(0.9, 0.1, 1.0));
+        assertNear(prism.eval(&r, &hit, &wi)[0], violet.eval(&r, &hit, &wi)[0], 1e-6);
+        assertNear(prism.pdf(&r, &hit, &wi), violet.pdf(&r, &hit, &wi), 1e-6);
+        // Without wavelengths, it is the index at the D line.
+        r.wavelengths = None;
+        assert!((prism.eval(&r, &hit, &wi)[0] - violet.eval(&r, &hit, &wi)[0]).abs() > 1e-5);
+    }
+
+    #[test]
+    fn roughFilmReflectsByHeroWavelength()
+    {
+        let (mut r, hit) = hit(Vec3::new(-1.0, 0.0, 1.0));
+        r.wavelengths = Some(Wavelengths { lambda: [450.0, 560.0, 720.0], active: 3 });
+        let glass = Dielectric::new(1.5).withRoughness(0.3)
+            .withThinFilm(Arc::new(Constant::scalar(300.0)), 1.33);
+        let wo = Vec3::unit(&-r.dir);
+        let wi = Vec3::unit(&Vec3::new(0.9, 0.1, 1.0));
+        let cos = vec3::dot(&wo, &Vec3::unit(&(wo + wi)));
+        let interface = FilmInterface { outside: 1.0, substrate_eta: 1.5, substrate_k: 0.0 };
+        let f = ThinFilm::new(Arc::new(Constant::scalar(300.0)), 1.33)
+            .reflectance(300.0, cos, &interface, 450.0);
+        let value = glass.distribution.evalReflection(&wo, &wi);
+        let pdf = glass.distribution.pdfReflection(&wo, &wi);
+        let eval = glass.eval(&r, &hit, &wi);
+        for i in 0..3
+        {
+            assertNear(eval[i], f * value, 1e-5);
+        }
+        assertNear(glass.pdf(&r, &hit, &wi), f * pdf, 1e-5);
+        // Without wavelengths, the film has its RGB color.
+        r.wavelengths = None;
+        assert!((glass.eval(&r, &hit, &wi)[0] - f * value).abs() > 1e-3);
+    }
+}
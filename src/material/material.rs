@@ -1,9 +1,15 @@
+#![allow(clippy::redundant_field_names, clippy::needless_return, clippy::new_without_default)]
+
+use std::sync::Arc;
+
 use crate::rand;
-use crate::config::Float;
+use crate::config::{Float, PI};
 use crate::geometry::vec3;
 use crate::geometry::{Vec3, Color};
 use crate::geometry::{Ray,Hit};
 use crate::texture::{self, Texture, AnyTexture};
+use crate::medium::AnyMedium;
+use super::microfacet::ShadingFrame;
 
 fn reflect(v_in: &Vec3, normal: &Vec3) -> Vec3
 {
@@ -37,6 +43,78 @@ pub trait Material
 {
     // Return (ray, attenuation).
     fn scatter(&self, r_in: &Ray, hit: &Hit) -> Option<(Ray, Vec3)>;
+
+    /// The medium inside surfaces of this material, if any. Rays
+    /// scattered through such a surface travel in this medium until
+    /// they leave.
+    fn interior(&self) -> Option<&AnyMedium>
+    {
+        None
+    }
+
+    /// Return the BSDF times the cosine of `wi` to the shading
+    /// normal, for light arriving at `hit` from unit direction `wi`
+    /// and leaving back along `r_in`. This is how light sources are
+    /// sampled directly. Specular lobes, which only scatter to
+    /// single directions, are 0, and so are lobes that are not
+    /// evaluated (e.g. rough transmission).
+    fn eval(&self, _r_in: &Ray, _hit: &Hit, _wi: &Vec3) -> Color
+    {
+        Color::origin()
+    }
+
+    /// Return the density per solid angle of `scatter` choosing unit
+    /// direction `wi`, for the lobes in `eval`. Specular lobes are 0.
+    fn pdf(&self, _r_in: &Ray, _hit: &Hit, _wi: &Vec3) -> Float
+    {
+        0.0
+    }
+
+    /// Return the radiance emitted at `hit` back along `r_in`.
+    fn emitted(&self, _r_in: &Ray, _hit: &Hit) -> Color
+    {
+        Color::origin()
+    }
+
+    /// Whether the surface does nothing to light, and only bounds a
+    /// medium. Shadow rays go through it.
+    fn isInvisible(&self) -> bool
+    {
+        false
+    }
+}
+
+pub type AnyMaterial = Arc<dyn Material + Send + Sync>;
+
+/// Whether unit direction `wi` is on the side of the surface at
+/// `hit` that `r_in` comes from.
+pub fn isReflection(r_in: &Ray, hit: &Hit, wi: &Vec3) -> bool
+{
+    vec3::dot(wi, &hit.normal) * vec3::dot(&r_in.dir, &hit.normal) < 0.0
+}
+
+/// `Material::pdf` of a Lambertian surface.
+fn pdfDiffuse(r_in: &Ray, hit: &Hit, wi: &Vec3) -> Float
+{
+    let cos = vec3::dot(wi, &hit.facingNormal());
+    if cos <= 0.0 || !isReflection(r_in, hit, wi)
+    {
+        return 0.0;
+    }
+    cos / PI
+}
+
+/// Sample a direction of a Lambertian surface, by the cosine to the
+/// shading normal on the side of the incoming light.
+fn sampleDiffuse(hit: &Hit) -> Vec3
+{
+    ShadingFrame::new(hit).toWorld(&Vec3::randCosineHemisphere())
+}
+
+/// `Material::eval` of a Lambertian surface of color `albedo`.
+fn evalDiffuse(albedo: &Color, r_in: &Ray, hit: &Hit, wi: &Vec3) -> Color
+{
+    *albedo * pdfDiffuse(r_in, hit, wi)
 }
 
 pub struct Lambertian
@@ -46,11 +124,19 @@ pub struct Lambertian
 
 impl Material for Lambertian
 {
-    fn scatter(&self, _r_in: &Ray, hit: &Hit) -> Option<(Ray, Vec3)>
+    fn scatter(&self, r_in: &Ray, hit: &Hit) -> Option<(Ray, Vec3)>
+    {
+        Some((hit.spawnRay(sampleDiffuse(hit), r_in.time), self.albedo.filtered(hit)))
+    }
+
+    fn eval(&self, r_in: &Ray, hit: &Hit, wi: &Vec3) -> Color
+    {
+        evalDiffuse(&self.albedo.filtered(hit), r_in, hit, wi)
+    }
+
+    fn pdf(&self, r_in: &Ray, hit: &Hit, wi: &Vec3) -> Float
     {
-        let target = hit.p + hit.normal + Vec3::randInUnitSphere();
-        Some((Ray { origin: hit.p, dir: target - hit.p },
-              self.albedo.value(0.0, 0.0, &hit.p)))
+        pdfDiffuse(r_in, hit, wi)
     }
 }
 
@@ -76,11 +162,19 @@ impl LambertianRandomColor
 
 impl Material for LambertianRandomColor
 {
-    fn scatter(&self, _r_in: &Ray, hit: &Hit) -> Option<(Ray, Vec3)>
+    fn scatter(&self, r_in: &Ray, hit: &Hit) -> Option<(Ray, Vec3)>
+    {
+        Some((hit.spawnRay(sampleDiffuse(hit), r_in.time), self.albedo.filtered(hit)))
+    }
+
+    fn eval(&self, r_in: &Ray, hit: &Hit, wi: &Vec3) -> Color
+    {
+        evalDiffuse(&self.albedo.filtered(hit), r_in, hit, wi)
+    }
+
+    fn pdf(&self, r_in: &Ray, hit: &Hit, wi: &Vec3) -> Float
     {
-        let target = hit.p + hit.normal + Vec3::randInUnitSphere();
-        Some((Ray { origin: hit.p, dir: target - hit.p },
-              self.albedo.value(0.0, 0.0, &hit.p)))
+        pdfDiffuse(r_in, hit, wi)
     }
 }
 
@@ -94,12 +188,9 @@ impl Material for Metal
 {
     fn scatter(&self, r_in: &Ray, hit: &Hit) -> Option<(Ray, Vec3)>
     {
-        let reflected = reflect(&(Vec3::unit(&r_in.dir)), &hit.normal);
-        let scattered = Ray
-        {
-            origin: hit.p,
-            dir: self.roughness * Vec3::randInUnitSphere() + reflected,
-        };
+        let reflected = reflect(&(Vec3::unit(&r_in.dir)), &hit.shading_normal);
+        let scattered = hit.spawnRay(
+            self.roughness * Vec3::randInUnitSphere() + reflected, r_in.time);
 
         if vec3::dot(&scattered.dir, &hit.normal) > 0.0
         {
@@ -126,17 +217,18 @@ impl Material for Glass
         let attenuation = Vec3::new(1.0, 1.0, 1.0);
         let cos: Float;
 
-        if vec3::dot(&r_in.dir, &hit.normal) > 0.0
+        if !hit.front_face
         {
-            ref_normal = -(hit.normal);
+            ref_normal = -(hit.shading_normal);
             ni_over_nt = self.ref_index;
-            cos = self.ref_index * vec3::dot(&r_in.dir, &hit.normal) / r_in.dir.norm();
+            cos = self.ref_index * vec3::dot(&r_in.dir, &hit.shading_normal) /
+                r_in.dir.norm();
         }
         else
         {
-            ref_normal = hit.normal;
+            ref_normal = hit.shading_normal;
             ni_over_nt = 1.0 / self.ref_index;
-            cos = -vec3::dot(&r_in.dir, &hit.normal) / r_in.dir.norm();
+            cos = -vec3::dot(&r_in.dir, &hit.shading_normal) / r_in.dir.norm();
         }
 
         let reflect_prob = schlick(cos, self.ref_index);
@@ -144,22 +236,51 @@ impl Material for Glass
         {
             if rand::random::<Float>() < reflect_prob
             {
-                let reflected = reflect(&r_in.dir, &hit.normal);
-                return Some((Ray{origin: hit.p, dir: reflected}, attenuation));
+                let reflected = reflect(&r_in.dir, &hit.shading_normal);
+                return Some((hit.spawnRay(reflected, r_in.time), attenuation));
             }
             else
             {
-                return Some((Ray{origin: hit.p, dir: refracted}, attenuation));
+                return Some((hit.spawnRay(refracted, r_in.time), attenuation));
             }
         }
         else
         {
-            let reflected = reflect(&r_in.dir, &hit.normal);
-            return Some((Ray{origin: hit.p, dir: reflected}, attenuation));
+            let reflected = reflect(&r_in.dir, &hit.shading_normal);
+            return Some((hit.spawnRay(reflected, r_in.time), attenuation));
         }
     }
 }
 
+/// A surface that emits light evenly in all directions from its front
+/// face, and reflects none.
+pub struct DiffuseLight
+{
+    /// The emitted radiance.
+    pub emit: AnyTexture,
+}
+
+impl DiffuseLight
+{
+    pub fn new(emit: AnyTexture) -> Self
+    {
+        Self { emit: emit }
+    }
+}
+
+impl Material for DiffuseLight
+{
+    fn scatter(&self, _r_in: &Ray, _hit: &Hit) -> Option<(Ray, Vec3)>
+    {
+        None
+    }
+
+    fn emitted(&self, _r_in: &Ray, hit: &Hit) -> Color
+    {
+        if hit.front_face { self.emit.filtered(hit) } else { Color::origin() }
+    }
+}
+
 pub struct Null {}
 
 impl Material for Null
@@ -170,4 +291,76 @@ impl Material for Null
     }
 }
 
+/// An invisible surface that bounds a medium, e.g. a sphere of smoke.
+/// Rays go straight through it.
+pub struct MediumBoundary
+{
+    pub medium: AnyMedium,
+}
+
+impl MediumBoundary
+{
+    pub fn new(medium: AnyMedium) -> Self
+    {
+        Self { medium: medium }
+    }
+}
+
+impl Material for MediumBoundary
+{
+    fn scatter(&self, r_in: &Ray, hit: &Hit) -> Option<(Ray, Vec3)>
+    {
+        let mut r = hit.spawnRay(r_in.dir, r_in.time);
+        r.differentials = r_in.differentials;
+        Some((r, Vec3::new(1.0, 1.0, 1.0)))
+    }
+
+    fn interior(&self) -> Option<&AnyMedium>
+    {
+        Some(&self.medium)
+    }
+
+    fn isInvisible(&self) -> bool
+    {
+        true
+    }
+}
+
 // pub static NULL: Null = Null{};
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use crate::texture::Constant;
+
+    #[test]
+    fn lambertianSamplesCosineAboutFacingNormal()
+    {
+        let gray = Lambertian { albedo: Arc::new(Constant::new(Color::new(0.5, 0.5, 0.5))) };
+        let random = LambertianRandomColor::new();
+        // From the front and from the back of a tilted surface.
+        let normal = Vec3::unit(&Vec3::new(0.0, 1.0, 1.0));
+        for &side in &[1.0, -1.0]
+        {
+            let r = Ray::new(normal * (2.0 * side), normal * -side, 0.0);
+            let hit = Hit::new(&r, 2.0, normal, 0.5, 0.5, Vec3::new(1.0, 0.0, 0.0),
+                               vec3::cross(&normal, &Vec3::new(1.0, 0.0, 0.0)), 0);
+            for material in &[&gray as &dyn Material, &random]
+            {
+                let n = 20000;
+                let mut mean_cos = 0.0;
+                for _ in 0..n
+                {
+                    let (scattered, _) = material.scatter(&r, &hit).unwrap();
+                    let dir = Vec3::unit(&scattered.dir);
+                    let cos = vec3::dot(&dir, &hit.facingNormal());
+                    assert!(cos >= 0.0);
+                    assert!(material.pdf(&r, &hit, &dir) > 0.0 || cos < 1e-3);
+                    mean_cos += cos / n as Float;
+                }
+                assert!((mean_cos - 2.0 / 3.0).abs() < 0.01, "{}", mean_cos);
+            }
+        }
+    }
+}
@@ -0,0 +1,404 @@
+use crate::rand;
+use crate::config::{Float, PI};
+use crate::geometry::vec3;
+use crate::geometry::{Vec3, Hit};
+
+/// The tangent space at a hit, with the normal on the side of the
+/// incoming ray. Local coordinates are (tangent, bitangent, normal).
+pub struct ShadingFrame
+{
+    pub tangent: Vec3,
+    pub bitangent: Vec3,
+    pub normal: Vec3,
+}
+
+impl ShadingFrame
+{
+    pub fn new(hit: &Hit) -> Self
+    {
+        Self { tangent: hit.tangent, bitangent: hit.bitangent,
+               normal: hit.facingNormal() }
+    }
+
+    pub fn toLocal(&self, v: &Vec3) -> Vec3
+    {
+        Vec3::new(vec3::dot(v, &self.tangent), vec3::dot(v, &self.bitangent),
+                  vec3::dot(v, &self.normal))
+    }
+
+    pub fn toWorld(&self, v: &Vec3) -> Vec3
+    {
+        self.tangent * v[0] + self.bitangent * v[1] + self.normal * v[2]
+    }
+}
+
+/// Map a perceptual roughness in [0, 1] to a GGX alpha.
+pub fn roughnessToAlpha(roughness: Float) -> Float
+{
+    (roughness * roughness).max(1e-4)
+}
+
+/// Below this alpha a microfacet surface is treated as perfectly
+/// smooth.
+pub const SMOOTH_ALPHA: Float = 1e-3;
+
+/// The GGX (Trowbridge-Reitz) microfacet distribution, with separate
+/// roughness along the tangent (x) and the bitangent (y). All the
+/// directions are in the local shading frame, and point away from the
+/// surface.
+#[derive(Clone, Copy, Debug)]
+pub struct Ggx
+{
+    pub alpha_x: Float,
+    pub alpha_y: Float,
+}
+
+impl Ggx
+{
+    pub fn new(alpha_x: Float, alpha_y: Float) -> Self
+    {
+        Self { alpha_x, alpha_y }
+    }
+
+    pub fn isSmooth(&self) -> bool
+    {
+        self.alpha_x.max(self.alpha_y) < SMOOTH_ALPHA
+    }
+
+    /// The density of microfacets with normal `m`.
+    pub fn d(&self, m: &Vec3) -> Float
+    {
+        if m[2] <= 0.0
+        {
+            return 0.0;
+        }
+        let x = m[0] / self.alpha_x;
+        let y = m[1] / self.alpha_y;
+        let e = x * x + y * y + m[2] * m[2];
+        1.0 / (PI * self.alpha_x * self.alpha_y * e * e)
+    }
+
+    /// Smith’s Λ function.
+    pub fn lambda(&self, w: &Vec3) -> Float
+    {
+        let cos2 = w[2] * w[2];
+        if cos2 == 0.0
+        {
+            return Float::INFINITY;
+        }
+        let a2 = self.alpha_x * self.alpha_x * w[0] * w[0] +
+            self.alpha_y * self.alpha_y * w[1] * w[1];
+        0.5 * (-1.0 + (1.0 + a2 / cos2).sqrt())
+    }
+
+    /// The fraction of microfacets visible from `w`.
+    pub fn g1(&self, w: &Vec3) -> Float
+    {
+        1.0 / (1.0 + self.lambda(w))
+    }
+
+    /// The fraction of microfacets visible from both `wo` and `wi`
+    /// (height-correlated).
+    pub fn g(&self, wo: &Vec3, wi: &Vec3) -> Float
+    {
+        1.0 / (1.0 + self.lambda(wo) + self.lambda(wi))
+    }
+
+    /// The density of visible normals `m` seen from `wo`.
+    pub fn visibleD(&self, wo: &Vec3, m: &Vec3) -> Float
+    {
+        if wo[2] <= 0.0
+        {
+            return 0.0;
+        }
+        self.g1(wo) * vec3::dot(wo, m).max(0.0) * self.d(m) / wo[2]
+    }
+
+    /// The BRDF times the cosine of `wi` for reflection off the
+    /// microfacets from `wo` to `wi`, without Fresnel.
+    pub fn evalReflection(&self, wo: &Vec3, wi: &Vec3) -> Float
+    {
+        if wo[2] <= 0.0 || wi[2] <= 0.0
+        {
+            return 0.0;
+        }
+        let m = Vec3::unit(&(*wo + *wi));
+        self.d(&m) * self.g(wo, wi) / (4.0 * wo[2])
+    }
+
+    /// The density of `sampleVisibleNormal` followed by reflection
+    /// choosing `wi` from `wo`, per solid angle.
+    pub fn pdfReflection(&self, wo: &Vec3, wi: &Vec3) -> Float
+    {
+        if wo[2] <= 0.0 || wi[2] <= 0.0
+        {
+            return 0.0;
+        }
+        let m = Vec3::unit(&(*wo + *wi));
+        self.visibleD(wo, &m) / (4.0 * vec3::dot(wo, &m))
+    }
+
+    /// Sample a microfacet normal visible from `wo` (Heitz 2018),
+    /// from uniform random numbers `u1` and `u2`.
+    pub fn sampleVisibleNormal(&self, wo: &Vec3, u1: Float, u2: Float) -> Vec3
+    {
+        // Stretch to the hemisphere configuration.
+        let vh = Vec3::unit(&Vec3::new(self.alpha_x * wo[0], self.alpha_y * wo[1], wo[2]));
+        let len2 = vh[0] * vh[0] + vh[1] * vh[1];
+        let t1 = if len2 > 0.0
+        {
+            Vec3::new(-vh[1], vh[0], 0.0) / len2.sqrt()
+        }
+        else
+        {
+            Vec3::new(1.0, 0.0, 0.0)
+        };
+        let t2 = vec3::cross(&vh, &t1);
+
+        // Sample the projected area of the hemisphere.
+        let r = u1.sqrt();
+        let phi = 2.0 * PI * u2;
+        let p1 = r * phi.cos();
+        let s = 0.5 * (1.0 + vh[2]);
+        let p2 = (1.0 - s) * (1.0 - p1 * p1).sqrt() + s * r * phi.sin();
+        let nh = t1 * p1 + t2 * p2 + vh * (1.0 - p1 * p1 - p2 * p2).max(0.0).sqrt();
+
+        // Unstretch.
+        Vec3::unit(&Vec3::new(self.alpha_x * nh[0], self.alpha_y * nh[1],
+                              nh[2].max(1e-6)))
+    }
+}
+
+/// Reflect `w` about `n`. Both point away from the surface.
+pub fn reflectAbout(w: &Vec3, n: &Vec3) -> Vec3
+{
+    2.0 * vec3::dot(w, n) * *n - *w
+}
+
+/// The reflectance of a conductor with complex index of refraction
+/// `eta` + i`k` (relative to the outside), at incident angle with
+/// cosine `cos`, for unpolarized light.
+pub fn fresnelConductor(cos: Float, eta: Float, k: Float) -> Float
+{
+    let cos = cos.clamp(0.0, 1.0);
+    let cos2 = cos * cos;
+    let sin2 = 1.0 - cos2;
+    let eta2 = eta * eta;
+    let k2 = k * k;
+
+    let t0 = eta2 - k2 - sin2;
+    let a2_plus_b2 = (t0 * t0 + 4.0 * eta2 * k2).sqrt();
+    let t1 = a2_plus_b2 + cos2;
+    let a = (0.5 * (a2_plus_b2 + t0)).max(0.0).sqrt();
+    let t2 = 2.0 * cos * a;
+    let rs = (t1 - t2) / (t1 + t2);
+
+    let t3 = cos2 * a2_plus_b2 + sin2 * sin2;
+    let t4 = t2 * sin2;
+    let rp = rs * (t3 - t4) / (t3 + t4);
+    0.5 * (rp + rs)
+}
+
+/// `fresnelConductor` for each channel.
+pub fn fresnelConductorRgb(cos: Float, eta: &Vec3, k: &Vec3) -> Vec3
+{
+    Vec3::new(fresnelConductor(cos, eta[0], k[0]), fresnelConductor(cos, eta[1], k[1]),
+              fresnelConductor(cos, eta[2], k[2]))
+}
+
+/// The reflectance of a smooth dielectric interface with relative
+/// index of refraction `eta` (inside over outside), for light
+/// arriving from the outside at cosine `cos`. This is 1 for total
+/// internal reflection.
+pub fn fresnelDielectric(cos: Float, eta: Float) -> Float
+{
+    let cos_i = cos.clamp(0.0, 1.0);
+    let sin2_t = (1.0 - cos_i * cos_i) / (eta * eta);
+    if sin2_t >= 1.0
+    {
+        return 1.0;
+    }
+    let cos_t = (1.0 - sin2_t).sqrt();
+    let rs = (cos_i - eta * cos_t) / (cos_i + eta * cos_t);
+    let rp = (eta * cos_i - cos_t) / (eta * cos_i + cos_t);
+    0.5 * (rs * rs + rp * rp)
+}
+
+/// Refract `w` through a surface with normal `n` (on the side of
+/// `w`) and relative index of refraction `eta` (other side over the
+/// side of `w`). Both directions point away from the surface. Return
+/// None for total internal reflection.
+pub fn refractAbout(w: &Vec3, n: &Vec3, eta: Float) -> Option<Vec3>
+{
+    let cos_i = vec3::dot(w, n);
+    let sin2_t = (1.0 - cos_i * cos_i).max(0.0) / (eta * eta);
+    if sin2_t >= 1.0
+    {
+        return None;
+    }
+    let cos_t = (1.0 - sin2_t).sqrt();
+    Some(-*w / eta + (cos_i / eta - cos_t) * *n)
+}
+
+/// Reflect `wo` about a visible microfacet normal of GGX with
+/// perceptual `roughness`. Return the local direction and the weight
+/// without Fresnel, or None if it goes below the surface.
+pub fn sampleGlossy(wo: &Vec3, roughness: Float) -> Option<(Vec3, Vec3, Float)>
+{
+    let alpha = roughnessToAlpha(roughness);
+    let ggx = Ggx::new(alpha, alpha);
+    let m = if ggx.isSmooth()
+    {
+        Vec3::new(0.0, 0.0, 1.0)
+    }
+    else
+    {
+        ggx.sampleVisibleNormal(wo, rand::random::<Float>(), rand::random::<Float>())
+    };
+    let wi = reflectAbout(wo, &m);
+    if wi[2] <= 0.0
+    {
+        return None;
+    }
+    let weight = if ggx.isSmooth() { 1.0 } else { ggx.g(wo, &wi) / ggx.g1(wo) };
+    Some((wi, m, weight))
+}
+
+/// Whether the lobe that `sampleGlossy` samples is perfectly smooth.
+pub fn isSmoothGlossy(roughness: Float) -> bool
+{
+    let alpha = roughnessToAlpha(roughness);
+    Ggx::new(alpha, alpha).isSmooth()
+}
+
+/// `Ggx::evalReflection` of the lobe that `sampleGlossy` samples. It
+/// is 0 when the lobe is smooth.
+pub fn evalGlossy(wo: &Vec3, wi: &Vec3, roughness: Float) -> Float
+{
+    let alpha = roughnessToAlpha(roughness);
+    let ggx = Ggx::new(alpha, alpha);
+    if ggx.isSmooth() { 0.0 } else { ggx.evalReflection(wo, wi) }
+}
+
+/// `Ggx::pdfReflection` of the lobe that `sampleGlossy` samples. It
+/// is 0 when the lobe is smooth.
+pub fn pdfGlossy(wo: &Vec3, wi: &Vec3, roughness: Float) -> Float
+{
+    let alpha = roughnessToAlpha(roughness);
+    let ggx = Ggx::new(alpha, alpha);
+    if ggx.isSmooth() { 0.0 } else { ggx.pdfReflection(wo, wi) }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    fn assertNear(a: Float, b: Float, tolerance: Float)
+    {
+        assert!((a - b).abs() <= tolerance, "{} is not near {}", a, b);
+    }
+
+    /// Integrate `f` over the upper hemisphere with the midpoint rule.
+    fn integrateHemisphere(f: impl Fn(&Vec3) -> Float) -> Float
+    {
+        let n = 600;
+        let d_theta = 0.5 * PI / n as Float;
+        let d_phi = 2.0 * PI / n as Float;
+        let mut sum = 0.0;
+        for i in 0..n
+        {
+            let theta = (i as Float + 0.5) * d_theta;
+            for j in 0..n
+            {
+                let phi = (j as Float + 0.5) * d_phi;
+                let w = Vec3::new(theta.sin() * phi.cos(), theta.sin() * phi.sin(), theta.cos());
+                sum += f(&w) * theta.sin() * d_theta * d_phi;
+            }
+        }
+        sum
+    }
+
+    #[test]
+    fn projectedNormalsCoverUnitArea()
+    {
+        for ggx in &[Ggx::new(0.3, 0.3), Ggx::new(0.5, 0.2)]
+        {
+            assertNear(integrateHemisphere(|m| ggx.d(m) * m[2]), 1.0, 0.01);
+            let wo = Vec3::unit(&Vec3::new(0.5, -0.3, 0.6));
+            assertNear(integrateHemisphere(|m| ggx.visibleD(&wo, m)), 1.0, 0.01);
+        }
+    }
+
+    #[test]
+    fn visibleNormalSamplingMatchesDensity()
+    {
+        let ggx = Ggx::new(0.4, 0.2);
+        let wo = Vec3::unit(&Vec3::new(-0.4, 0.7, 0.5));
+        // Compare moments of the sampled normals, on a grid of
+        // uniform numbers, with moments of the density.
+        let n = 200;
+        let mut mean = Vec3::origin();
+        for i in 0..n
+        {
+            for j in 0..n
+            {
+                let m = ggx.sampleVisibleNormal(&wo, (i as Float + 0.5) / n as Float,
+                                                (j as Float + 0.5) / n as Float);
+                assertNear(m.norm(), 1.0, 1e-4);
+                assert!(vec3::dot(&wo, &m) >= -1e-4);
+                mean += m / (n * n) as Float;
+            }
+        }
+        for axis in 0..3
+        {
+            let expected = integrateHemisphere(|m| m[axis] * ggx.visibleD(&wo, m));
+            assertNear(mean[axis], expected, 0.01);
+        }
+    }
+
+    #[test]
+    fn reflectionPdfMatchesSampledDirections()
+    {
+        let ggx = Ggx::new(0.3, 0.3);
+        let wo = Vec3::unit(&Vec3::new(0.2, 0.1, 0.9));
+        // The density misses the reflections that go below the
+        // surface, as many as the samples that do.
+        let n = 300;
+        let mut above = 0;
+        for i in 0..n
+        {
+            for j in 0..n
+            {
+                let m = ggx.sampleVisibleNormal(&wo, (i as Float + 0.5) / n as Float,
+                                                (j as Float + 0.5) / n as Float);
+                if reflectAbout(&wo, &m)[2] > 0.0
+                {
+                    above += 1;
+                }
+            }
+        }
+        let total = integrateHemisphere(|wi| ggx.pdfReflection(&wo, wi));
+        assertNear(total, above as Float / (n * n) as Float, 0.01);
+    }
+
+    #[test]
+    fn conductorFresnelMatchesKnownCases()
+    {
+        // At normal incidence, ((eta - 1)^2 + k^2) / ((eta + 1)^2 + k^2).
+        let (eta, k) = (0.2, 3.9);
+        let expected = ((eta - 1.0) * (eta - 1.0) + k * k) / ((eta + 1.0) * (eta + 1.0) + k * k);
+        assertNear(fresnelConductor(1.0, eta, k), expected, 1e-4);
+        // Without absorption it is the dielectric Fresnel.
+        for &cos in &[0.1, 0.4, 0.7, 1.0]
+        {
+            assertNear(fresnelConductor(cos, 1.5, 0.0), fresnelDielectric(cos, 1.5), 1e-4);
+        }
+        // Everything is reflected at grazing angles.
+        assertNear(fresnelConductor(0.0, eta, k), 1.0, 1e-4);
+        // Gold reflects red more than blue.
+        let gold = fresnelConductorRgb(0.8, &Vec3::new(0.143, 0.374, 1.442),
+                                       &Vec3::new(3.983, 2.385, 1.603));
+        assert!(gold[0] > gold[1] && gold[1] > gold[2]);
+    }
+}
@@ -1,2 +1,28 @@
+#[allow(clippy::module_inception)]
 mod material;
 pub use self::material::*;
+
+mod normal_map;
+pub use self::normal_map::*;
+
+mod microfacet;
+
+mod thin_film;
+
+mod conductor;
+pub use self::conductor::*;
+
+mod dielectric;
+pub use self::dielectric::*;
+
+mod principled;
+pub use self::principled::*;
+
+mod combine;
+pub use self::combine::*;
+
+mod two_sided;
+pub use self::two_sided::*;
+
+mod subsurface;
+pub use self::subsurface::*;
@@ -0,0 +1,285 @@
+use crate::config::Float;
+use crate::geometry::vec3;
+use crate::geometry::{Vec3, Color, Ray, Hit};
+use crate::texture::{self, AnyTexture};
+use crate::medium::AnyMedium;
+use super::material::{Material, AnyMaterial};
+
+/// Scatter with `material` at `hit`, whose shading normal has been
+/// perturbed. A scattered ray that the shading normal and the
+/// geometric normal disagree on (e.g. it goes into the surface while
+/// the material thinks it is reflected) would leak light through the
+/// surface, so it is absorbed instead.
+fn scatterPerturbed(material: &AnyMaterial, r_in: &Ray, hit: &Hit) ->
+    Option<(Ray, Vec3)>
+{
+    let (scattered, attenuation) = material.scatter(r_in, hit)?;
+    let geometric = vec3::dot(&scattered.dir, &hit.normal);
+    let shading = vec3::dot(&scattered.dir, &hit.shading_normal);
+    if geometric * shading <= 0.0
+    {
+        return None;
+    }
+    Some((scattered, attenuation))
+}
+
+/// Evaluate `material` at `hit`, whose shading normal has been
+/// perturbed. Light that the normals disagree on is absorbed, as in
+/// `scatterPerturbed`.
+fn evalPerturbed(material: &AnyMaterial, r_in: &Ray, hit: &Hit, wi: &Vec3) -> Color
+{
+    if vec3::dot(wi, &hit.normal) * vec3::dot(wi, &hit.shading_normal) <= 0.0
+    {
+        return Color::origin();
+    }
+    material.eval(r_in, hit, wi)
+}
+
+/// `Material::pdf` of `material` at `hit`, whose shading normal has
+/// been perturbed.
+fn pdfPerturbed(material: &AnyMaterial, r_in: &Ray, hit: &Hit, wi: &Vec3) -> Float
+{
+    if vec3::dot(wi, &hit.normal) * vec3::dot(wi, &hit.shading_normal) <= 0.0
+    {
+        return 0.0;
+    }
+    material.pdf(r_in, hit, wi)
+}
+
+/// A material with its shading normal from a tangent space normal
+/// map. The normal map should be loaded as linear, not sRGB.
+pub struct NormalMapped
+{
+    material: AnyMaterial,
+    map: AnyTexture,
+    strength: Float,
+}
+
+impl NormalMapped
+{
+    pub fn new(material: AnyMaterial, map: AnyTexture) -> Self
+    {
+        Self { material, map, strength: 1.0 }
+    }
+
+    /// Scale the tilt of the normals in the map. 0 is a flat surface.
+    pub fn withStrength(mut self, strength: Float) -> Self
+    {
+        self.strength = strength;
+        self
+    }
+
+    /// Return `hit` with the shading normal from the map.
+    fn perturb(&self, hit: &Hit) -> Hit
+    {
+        let c = self.map.filtered(hit);
+        let x = (2.0 * c[0] - 1.0) * self.strength;
+        let y = (2.0 * c[1] - 1.0) * self.strength;
+        let z = (2.0 * c[2] - 1.0).max(1e-3);
+        let n = hit.tangent * x + hit.bitangent * y + hit.shading_normal * z;
+        let mut h = hit.clone();
+        h.setShadingNormal(Vec3::unit(&n));
+        h
+    }
+}
+
+impl Material for NormalMapped
+{
+    fn scatter(&self, r_in: &Ray, hit: &Hit) -> Option<(Ray, Vec3)>
+    {
+        scatterPerturbed(&self.material, r_in, &self.perturb(hit))
+    }
+
+    fn eval(&self, r_in: &Ray, hit: &Hit, wi: &Vec3) -> Color
+    {
+        evalPerturbed(&self.material, r_in, &self.perturb(hit), wi)
+    }
+
+    fn pdf(&self, r_in: &Ray, hit: &Hit, wi: &Vec3) -> Float
+    {
+        pdfPerturbed(&self.material, r_in, &self.perturb(hit), wi)
+    }
+
+    fn interior(&self) -> Option<&AnyMedium>
+    {
+        self.material.interior()
+    }
+}
+
+/// A material with its shading normal perturbed by a height map.
+/// The height is the luminance of a texture.
+pub struct BumpMapped
+{
+    material: AnyMaterial,
+    height: AnyTexture,
+    /// The world distance of 1 unit of height.
+    scale: Float,
+}
+
+impl BumpMapped
+{
+    pub fn new(material: AnyMaterial, height: AnyTexture, scale: Float) -> Self
+    {
+        Self { material, height, scale }
+    }
+
+    fn heightAt(&self, hit: &Hit) -> Float
+    {
+        self.scale * texture::luminance(&self.height.filtered(hit))
+    }
+
+    /// Return `hit` with the shading normal of the bumps.
+    fn perturb(&self, hit: &Hit) -> Hit
+    {
+        // Take finite differences over about the footprint of the
+        // ray.
+        let mut du = 0.5 * (hit.dudx.abs() + hit.dudy.abs());
+        if du == 0.0
+        {
+            du = 5e-4;
+        }
+        let mut dv = 0.5 * (hit.dvdx.abs() + hit.dvdy.abs());
+        if dv == 0.0
+        {
+            dv = 5e-4;
+        }
+
+        let height = self.heightAt(hit);
+        let mut shifted = hit.clone();
+        shifted.u = hit.u + du;
+        shifted.p = hit.p + hit.dpdu * du;
+        let height_u = self.heightAt(&shifted);
+        shifted.u = hit.u;
+        shifted.v = hit.v + dv;
+        shifted.p = hit.p + hit.dpdv * dv;
+        let height_v = self.heightAt(&shifted);
+
+        let n = hit.shading_normal;
+        let dpdu = hit.dpdu + n * ((height_u - height) / du);
+        let dpdv = hit.dpdv + n * ((height_v - height) / dv);
+        let mut bumped = vec3::cross(&dpdu, &dpdv);
+        if vec3::dot(&bumped, &n) < 0.0
+        {
+            bumped = -bumped;
+        }
+        let norm = bumped.norm();
+        let mut h = hit.clone();
+        if norm > 0.0 && norm.is_finite()
+        {
+            h.setShadingFrame(bumped / norm, &dpdu);
+        }
+        h
+    }
+}
+
+impl Material for BumpMapped
+{
+    fn scatter(&self, r_in: &Ray, hit: &Hit) -> Option<(Ray, Vec3)>
+    {
+        scatterPerturbed(&self.material, r_in, &self.perturb(hit))
+    }
+
+    fn eval(&self, r_in: &Ray, hit: &Hit, wi: &Vec3) -> Color
+    {
+        evalPerturbed(&self.material, r_in, &self.perturb(hit), wi)
+    }
+
+    fn pdf(&self, r_in: &Ray, hit: &Hit, wi: &Vec3) -> Float
+    {
+        pdfPerturbed(&self.material, r_in, &self.perturb(hit), wi)
+    }
+
+    fn interior(&self) -> Option<&AnyMedium>
+    {
+        self.material.interior()
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use std::sync::Arc;
+    use super::*;
+    use crate::texture::Constant;
+
+    /// Reflects everything equally in every direction.
+    struct Uniform;
+
+    impl Material for Uniform
+    {
+        fn scatter(&self, _: &Ray, _: &Hit) -> Option<(Ray, Vec3)>
+        {
+            None
+        }
+
+        fn eval(&self, _: &Ray, _: &Hit, _: &Vec3) -> Color
+        {
+            Color::new(1.0, 1.0, 1.0)
+        }
+    }
+
+    /// A texture with the surface coordinate u in every channel.
+    struct RampU;
+
+    impl texture::Texture for RampU
+    {
+        fn value(&self, u: Float, _: Float, _: &Vec3) -> Color
+        {
+            Color::new(u, u, u)
+        }
+    }
+
+    /// A hit on the plane z = 0 from above, with u along x.
+    fn hit() -> (Ray, Hit)
+    {
+        let r = Ray::new(Vec3::new(0.0, 0.0, 1.0), Vec3::new(0.0, 0.0, -1.0), 0.0);
+        let hit = Hit::new(&r, 1.0, Vec3::new(0.0, 0.0, 1.0), 0.5, 0.5,
+                           Vec3::new(1.0, 0.0, 0.0), Vec3::new(0.0, 1.0, 0.0), 0);
+        (r, hit)
+    }
+
+    fn assertNear(a: &Vec3, b: &Vec3)
+    {
+        assert!((*a - *b).norm() < 1e-3, "{:?} != {:?}", a, b);
+    }
+
+    #[test]
+    fn normalMapTiltsInTangentSpace()
+    {
+        let (_, hit) = hit();
+        let flat = NormalMapped::new(Arc::new(Uniform),
+                                     Arc::new(Constant::new(Color::new(0.5, 0.5, 1.0))));
+        assertNear(&flat.perturb(&hit).shading_normal, &hit.normal);
+        let tilted = NormalMapped::new(Arc::new(Uniform),
+                                       Arc::new(Constant::new(Color::new(1.0, 0.5, 1.0))));
+        assertNear(&tilted.perturb(&hit).shading_normal,
+                   &Vec3::unit(&Vec3::new(1.0, 0.0, 1.0)));
+        // Strength 0 is flat.
+        assertNear(&tilted.withStrength(0.0).perturb(&hit).shading_normal, &hit.normal);
+    }
+
+    #[test]
+    fn bumpsTiltAwayFromRisingHeight()
+    {
+        let (_, hit) = hit();
+        let flat = BumpMapped::new(Arc::new(Uniform), Arc::new(Constant::scalar(0.7)), 1.0);
+        assertNear(&flat.perturb(&hit).shading_normal, &hit.normal);
+        // The height rises by 1 along x per unit, a 45 degree slope.
+        let ramp = BumpMapped::new(Arc::new(Uniform), Arc::new(RampU), 1.0);
+        assertNear(&ramp.perturb(&hit).shading_normal,
+                   &Vec3::unit(&Vec3::new(-1.0, 0.0, 1.0)));
+    }
+
+    #[test]
+    fn lightBehindGeometryIsAbsorbed()
+    {
+        let (r, hit) = hit();
+        let tilted = NormalMapped::new(Arc::new(Uniform),
+                                       Arc::new(Constant::new(Color::new(1.0, 0.5, 1.0))));
+        // Above the perturbed surface, but below the geometry.
+        let below = Vec3::unit(&Vec3::new(1.0, 0.0, -0.2));
+        assertNear(&tilted.eval(&r, &hit, &below), &Color::origin());
+        let above = Vec3::unit(&Vec3::new(1.0, 0.0, 0.2));
+        assertNear(&tilted.eval(&r, &hit, &above), &Color::new(1.0, 1.0, 1.0));
+    }
+}
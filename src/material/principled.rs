@@ -0,0 +1,521 @@
+use std::sync::Arc;
+
+use crate::rand;
+use crate::config::{Float, PI};
+use crate::geometry::vec3;
+use crate::geometry::{Vec3, Color, Ray, Hit};
+use crate::texture::{self, AnyTexture, Constant};
+use super::material::{self, Material};
+use super::dielectric::Dielectric;
+use super::microfacet::{self, ShadingFrame};
+
+/// Schlick’s approximation of the Fresnel reflectance, with
+/// reflectance `f0` at normal incidence.
+fn schlick(f0: &Color, cos: Float) -> Color
+{
+    let w = (1.0 - cos.clamp(0.0, 1.0)).powi(5);
+    *f0 + (Vec3::new(1.0, 1.0, 1.0) - *f0) * w
+}
+
+/// The parameters of `Principled` evaluated at a hit.
+struct Params
+{
+    base_color: Color,
+    metallic: Float,
+    roughness: Float,
+    specular: Float,
+    clearcoat: Float,
+    clearcoat_roughness: Float,
+    sheen: Float,
+    sheen_tint: Float,
+    transmission: Float,
+}
+
+impl Params
+{
+    /// The probability that the coat reflects light from `wo`.
+    fn coat(&self, wo: &Vec3) -> Float
+    {
+        self.clearcoat * schlick(&Vec3::new(0.04, 0.04, 0.04), wo[2])[0]
+    }
+
+    /// The relative index of refraction of the dielectric specular.
+    fn specularEta(&self) -> Float
+    {
+        let f0 = 0.08 * self.specular;
+        (1.0 + f0.sqrt()) / (1.0 - f0.sqrt()).max(1e-3)
+    }
+}
+
+/// A Disney style material with diffuse, specular, clearcoat, sheen
+/// and transmission lobes. Every parameter is a texture; scalar
+/// parameters use the luminance of theirs (see `texture::Grayscale`
+/// for packed textures, e.g. the blue channel for glTF metalness).
+///
+/// A lobe is chosen at random for every scatter, with probabilities
+/// from the parameters and the Fresnel reflectance at the normal.
+/// The scattered direction is weighted by `eval` over `pdf` of all the
+/// lobes, so sampled and directly evaluated light agree.
+pub struct Principled
+{
+    pub base_color: AnyTexture,
+    /// 0 for dielectric, 1 for metal.
+    pub metallic: AnyTexture,
+    /// Perceptual roughness of the specular and transmission lobes.
+    pub roughness: AnyTexture,
+    /// Reflectance of the dielectric specular at normal incidence,
+    /// where 1 is 8%. The default 0.5 is 4%, i.e. index of refraction
+    /// 1.5.
+    pub specular: AnyTexture,
+    /// Strength of a white, dielectric coat on top.
+    pub clearcoat: AnyTexture,
+    pub clearcoat_roughness: AnyTexture,
+    /// Strength of the soft retro-reflection of cloth at grazing
+    /// angles.
+    pub sheen: AnyTexture,
+    /// How much the sheen takes the hue of the base color.
+    pub sheen_tint: AnyTexture,
+    /// How much of the dielectric base is transmissive (glass-like)
+    /// instead of diffuse.
+    pub transmission: AnyTexture,
+    /// Index of refraction of transmission.
+    pub ior: Float,
+}
+
+fn scalar(x: Float) -> AnyTexture
+{
+    Arc::new(Constant::scalar(x))
+}
+
+impl Principled
+{
+    /// Construct a rough dielectric of `base_color`, with every other
+    /// lobe off.
+    pub fn new(base_color: AnyTexture) -> Self
+    {
+        Self {
+            base_color,
+            metallic: scalar(0.0),
+            roughness: scalar(0.5),
+            specular: scalar(0.5),
+            clearcoat: scalar(0.0),
+            clearcoat_roughness: scalar(0.03),
+            sheen: scalar(0.0),
+            sheen_tint: scalar(0.5),
+            transmission: scalar(0.0),
+            ior: 1.5,
+        }
+    }
+
+    pub fn withMetallic(mut self, metallic: AnyTexture) -> Self
+    {
+        self.metallic = metallic;
+        self
+    }
+
+    pub fn withRoughness(mut self, roughness: AnyTexture) -> Self
+    {
+        self.roughness = roughness;
+        self
+    }
+
+    pub fn withSpecular(mut self, specular: AnyTexture) -> Self
+    {
+        self.specular = specular;
+        self
+    }
+
+    pub fn withClearcoat(mut self, clearcoat: AnyTexture, roughness: AnyTexture) -> Self
+    {
+        self.clearcoat = clearcoat;
+        self.clearcoat_roughness = roughness;
+        self
+    }
+
+    pub fn withSheen(mut self, sheen: AnyTexture, tint: AnyTexture) -> Self
+    {
+        self.sheen = sheen;
+        self.sheen_tint = tint;
+        self
+    }
+
+    pub fn withTransmission(mut self, transmission: AnyTexture, ior: Float) -> Self
+    {
+        self.transmission = transmission;
+        self.ior = ior;
+        self
+    }
+
+    /// The color of the diffuse lobe from `wo` to `wi`, with sheen.
+    /// The sheen takes its share of the light from the diffuse lobe,
+    /// so the sum reflects no more than either.
+    fn diffuse(p: &Params, wo: &Vec3, wi: &Vec3) -> Color
+    {
+        let mut color = p.base_color;
+        if p.sheen > 0.0
+        {
+            let white = Vec3::new(1.0, 1.0, 1.0);
+            let lum = texture::luminance(&p.base_color);
+            let tint = if lum > 0.0 { p.base_color / lum } else { white };
+            let mut sheen_color = (1.0 - p.sheen_tint) * white + p.sheen_tint * tint;
+            for i in 0..3
+            {
+                sheen_color[i] = sheen_color[i].min(1.0);
+            }
+            let h = Vec3::unit(&(*wo + *wi));
+            let fh = p.sheen * (1.0 - vec3::dot(wi, &h).clamp(0.0, 1.0)).powi(5);
+            color = (1.0 - fh) * color + fh * sheen_color;
+        }
+        color
+    }
+
+    fn params(&self, hit: &Hit) -> Params
+    {
+        let get = |t: &AnyTexture| texture::luminance(&t.filtered(hit)).clamp(0.0, 1.0);
+        Params {
+            base_color: self.base_color.filtered(hit),
+            metallic: get(&self.metallic),
+            roughness: get(&self.roughness),
+            specular: get(&self.specular),
+            clearcoat: get(&self.clearcoat),
+            clearcoat_roughness: get(&self.clearcoat_roughness),
+            sheen: get(&self.sheen),
+            sheen_tint: get(&self.sheen_tint),
+            transmission: get(&self.transmission),
+        }
+    }
+
+    /// The transmission lobe, which also reflects.
+    fn glass(&self, p: &Params) -> Dielectric
+    {
+        Dielectric::new(self.ior).withRoughness(p.roughness)
+    }
+
+    /// `eval` in the shading frame, for `wo` and `wi` above the
+    /// surface: the lobes that `scatter` picks, weighted by the
+    /// light they reflect.
+    fn evalLocal(&self, p: &Params, wo: &Vec3, wi: &Vec3) -> Color
+    {
+        let white = Vec3::new(1.0, 1.0, 1.0);
+        let cos_m = vec3::dot(wo, &Vec3::unit(&(*wo + *wi)));
+        let glass = (1.0 - p.metallic) * p.transmission;
+        let dielectric = (1.0 - p.metallic) * (1.0 - p.transmission);
+        let eta = p.specularEta();
+
+        let specular = p.metallic * schlick(&p.base_color, cos_m) +
+            (glass * microfacet::fresnelDielectric(cos_m, self.ior) +
+             dielectric * microfacet::fresnelDielectric(cos_m, eta)) * white;
+        let diffuse = dielectric * (1.0 - microfacet::fresnelDielectric(wo[2], eta)) * wi[2] / PI;
+        let base = specular * microfacet::evalGlossy(wo, wi, p.roughness) +
+            diffuse * Self::diffuse(p, wo, wi);
+        let coat = p.coat(wo);
+        coat * microfacet::evalGlossy(wo, wi, p.clearcoat_roughness) * white + (1.0 - coat) * base
+    }
+
+    /// `pdf` in the shading frame, as `evalLocal`: the density of
+    /// each lobe times the probability that `scatter` picks it.
+    fn pdfLocal(&self, p: &Params, wo: &Vec3, wi: &Vec3) -> Float
+    {
+        let cos_m = vec3::dot(wo, &Vec3::unit(&(*wo + *wi)));
+        let glass = (1.0 - p.metallic) * p.transmission;
+        let dielectric = (1.0 - p.metallic) * (1.0 - p.transmission);
+        let specular = microfacet::fresnelDielectric(wo[2], p.specularEta());
+
+        // The glass reflects by the Fresnel term of the microfacet.
+        let glossy = p.metallic + glass * microfacet::fresnelDielectric(cos_m, self.ior) +
+            dielectric * specular;
+        let base = glossy * microfacet::pdfGlossy(wo, wi, p.roughness) +
+            dielectric * (1.0 - specular) * wi[2] / PI;
+        let coat = p.coat(wo);
+        coat * microfacet::pdfGlossy(wo, wi, p.clearcoat_roughness) + (1.0 - coat) * base
+    }
+}
+
+impl Material for Principled
+{
+    fn scatter(&self, r_in: &Ray, hit: &Hit) -> Option<(Ray, Vec3)>
+    {
+        let p = self.params(hit);
+        let transmission = (1.0 - p.metallic) * p.transmission;
+
+        // Inside a transmissive object, only the glass lobe exists.
+        if !hit.front_face && transmission > 0.0
+        {
+            return self.glass(&p).scatter(r_in, hit);
+        }
+
+        let frame = ShadingFrame::new(hit);
+        let wo = frame.toLocal(&-Vec3::unit(&r_in.dir));
+        if wo[2] <= 0.0
+        {
+            return None;
+        }
+        let white = Vec3::new(1.0, 1.0, 1.0);
+        // A smooth lobe reflects in a single direction, which `eval`
+        // and `pdf` leave out, so it is weighted by its reflectance
+        // alone.
+        let glossy = |roughness: Float, reflectance: Color| {
+            let (wi, _, _) = microfacet::sampleGlossy(&wo, roughness)?;
+            Some((wi, if microfacet::isSmoothGlossy(roughness) { Some(reflectance) } else { None }))
+        };
+
+        // Pick a lobe with the probabilities that `pdf` uses: the coat
+        // reflects first, and the rest of the light goes to the base.
+        let u = rand::random::<Float>();
+        let (wi, smooth) = if rand::random::<Float>() < p.coat(&wo)
+        {
+            glossy(p.clearcoat_roughness, white)?
+        }
+        else if u < p.metallic
+        {
+            glossy(p.roughness, schlick(&p.base_color, wo[2]))?
+        }
+        else if u < p.metallic + transmission
+        {
+            let (r, attenuation) = self.glass(&p).scatter(r_in, hit)?;
+            if vec3::dot(&r.dir, &hit.normal) < 0.0
+            {
+                // Transmission is not evaluated either. Tint the light
+                // entering the object.
+                return Some((r, attenuation * p.base_color));
+            }
+            let smooth = if microfacet::isSmoothGlossy(p.roughness) { Some(attenuation) } else { None };
+            (frame.toLocal(&Vec3::unit(&r.dir)), smooth)
+        }
+        // The dielectric base: a specular layer on top of a diffuse
+        // one.
+        else if rand::random::<Float>() < microfacet::fresnelDielectric(wo[2], p.specularEta())
+        {
+            glossy(p.roughness, white)?
+        }
+        else
+        {
+            (Vec3::randCosineHemisphere(), None)
+        };
+
+        let weight = match smooth
+        {
+            Some(reflectance) => reflectance,
+            None =>
+            {
+                let pdf = self.pdfLocal(&p, &wo, &wi);
+                if pdf <= 0.0
+                {
+                    return None;
+                }
+                self.evalLocal(&p, &wo, &wi) / pdf
+            },
+        };
+        Some((hit.spawnRay(frame.toWorld(&wi), r_in.time), weight))
+    }
+
+    /// Transmission and smooth lobes are not evaluated.
+    fn eval(&self, r_in: &Ray, hit: &Hit, wi: &Vec3) -> Color
+    {
+        let p = self.params(hit);
+        if !hit.front_face && p.transmission * (1.0 - p.metallic) > 0.0
+        {
+            return self.glass(&p).eval(r_in, hit, wi);
+        }
+        if !material::isReflection(r_in, hit, wi)
+        {
+            return Color::origin();
+        }
+        let frame = ShadingFrame::new(hit);
+        let wo = frame.toLocal(&-Vec3::unit(&r_in.dir));
+        let wi = frame.toLocal(wi);
+        if wo[2] <= 0.0 || wi[2] <= 0.0
+        {
+            return Color::origin();
+        }
+        self.evalLocal(&p, &wo, &wi)
+    }
+
+    fn pdf(&self, r_in: &Ray, hit: &Hit, wi: &Vec3) -> Float
+    {
+        let p = self.params(hit);
+        if !hit.front_face && p.transmission * (1.0 - p.metallic) > 0.0
+        {
+            return self.glass(&p).pdf(r_in, hit, wi);
+        }
+        if !material::isReflection(r_in, hit, wi)
+        {
+            return 0.0;
+        }
+        let frame = ShadingFrame::new(hit);
+        let wo = frame.toLocal(&-Vec3::unit(&r_in.dir));
+        let wi = frame.toLocal(wi);
+        if wo[2] <= 0.0 || wi[2] <= 0.0
+        {
+            return 0.0;
+        }
+        self.pdfLocal(&p, &wo, &wi)
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    /// A ray toward the origin from direction `wo`, hitting the plane
+    /// z = 0 there.
+    fn hit(wo: Vec3) -> (Ray, Hit)
+    {
+        let r = Ray::new(wo, -wo, 0.0);
+        let hit = Hit::new(&r, 1.0, Vec3::new(0.0, 0.0, 1.0), 0.5, 0.5,
+                           Vec3::new(1.0, 0.0, 0.0), Vec3::new(0.0, 1.0, 0.0), 0);
+        (r, hit)
+    }
+
+    /// The fraction of the light from `wo` that `material` reflects,
+    /// by integrating `eval` over the hemisphere.
+    fn albedo(material: &Principled, wo: Vec3) -> Color
+    {
+        let (r, hit) = hit(wo);
+        let n = 300;
+        let d_theta = 0.5 * PI / n as Float;
+        let d_phi = 2.0 * PI / n as Float;
+        let mut sum = Color::origin();
+        for i in 0..n
+        {
+            let theta = (i as Float + 0.5) * d_theta;
+            for j in 0..n
+            {
+                let phi = (j as Float + 0.5) * d_phi;
+                let wi = Vec3::new(theta.sin() * phi.cos(), theta.sin() * phi.sin(), theta.cos());
+                sum += material.eval(&r, &hit, &wi) * theta.sin() * d_theta * d_phi;
+            }
+        }
+        sum
+    }
+
+    fn white() -> Principled
+    {
+        Principled::new(Arc::new(Constant::new(Color::new(1.0, 1.0, 1.0))))
+    }
+
+    fn params(base_color: Color, sheen_tint: Float) -> Params
+    {
+        Params {
+            base_color, metallic: 0.0, roughness: 0.5, specular: 0.5,
+            clearcoat: 0.0, clearcoat_roughness: 0.03, sheen: 1.0, sheen_tint,
+            transmission: 0.0,
+        }
+    }
+
+    #[test]
+    fn sheenConservesEnergy()
+    {
+        let wo = Vec3::unit(&Vec3::new(1.0, 0.0, 0.05));
+        let wi = Vec3::unit(&Vec3::new(-1.0, 0.0, 0.05));
+        let bright = Principled::diffuse(&params(Color::new(1.0, 1.0, 1.0), 0.0), &wo, &wi);
+        for i in 0..3
+        {
+            assert!(bright[i] <= 1.0 + 1e-5, "{:?}", bright);
+        }
+        // A saturated tint does not add energy either.
+        let red = Principled::diffuse(&params(Color::new(0.9, 0.05, 0.05), 1.0), &wo, &wi);
+        for i in 0..3
+        {
+            assert!(red[i] <= 1.0 + 1e-5, "{:?}", red);
+        }
+        // Sheen brightens dark cloth at grazing angles, but not seen
+        // head on.
+        let dark = Color::new(0.1, 0.1, 0.1);
+        assert!(Principled::diffuse(&params(dark, 0.0), &wo, &wi)[0] > 0.5);
+        let up = Vec3::new(0.0, 0.0, 1.0);
+        assert!(Principled::diffuse(&params(dark, 0.0), &up, &up)[0] < 0.11);
+        // The lobe still conserves energy as a whole.
+        let velvet = white().withSheen(scalar(1.0), scalar(0.0));
+        for &cos in &[1.0, 0.5, 0.15]
+        {
+            let wo = Vec3::new((1.0 - cos * cos as Float).sqrt(), 0.0, cos);
+            assert!(albedo(&velvet, wo)[0] <= 1.0, "at cosine {}", cos);
+        }
+    }
+
+    #[test]
+    fn pdfIsNormalizedAndScatterWeightsAreBounded()
+    {
+        let materials = [
+            white(),
+            white().withMetallic(scalar(1.0)).withRoughness(scalar(0.3)),
+            white().withSheen(scalar(1.0), scalar(0.5)).withRoughness(scalar(0.8)),
+        ];
+        let wo = Vec3::unit(&Vec3::new(0.6, 0.2, 0.7));
+        let (r, hit) = hit(wo);
+        for material in &materials
+        {
+            let n = 300;
+            let d_theta = 0.5 * PI / n as Float;
+            let d_phi = 2.0 * PI / n as Float;
+            let mut total = 0.0;
+            for i in 0..n
+            {
+                let theta = (i as Float + 0.5) * d_theta;
+                for j in 0..n
+                {
+                    let phi = (j as Float + 0.5) * d_phi;
+                    let wi = Vec3::new(theta.sin() * phi.cos(), theta.sin() * phi.sin(),
+                                       theta.cos());
+                    total += material.pdf(&r, &hit, &wi) * theta.sin() * d_theta * d_phi;
+                }
+            }
+            assert!(total <= 1.01 && total > 0.8, "{}", total);
+
+            let samples = 20000;
+            let mut mean = Color::origin();
+            for _ in 0..samples
+            {
+                if let Some((_, weight)) = material.scatter(&r, &hit)
+                {
+                    mean += weight / samples as Float;
+                }
+            }
+            assert!(mean[0] <= 1.01, "{:?}", mean);
+        }
+    }
+
+    #[test]
+    fn scatterWeightsAreEvalOverPdf()
+    {
+        // Rough dielectric bases: plain, under a rough coat, and
+        // glass, which also reflects. Seen at a grazing angle, where
+        // the Fresnel terms are large.
+        let materials = [
+            white().withRoughness(scalar(0.4)).withSpecular(scalar(1.0)),
+            white().withRoughness(scalar(0.3)).withClearcoat(scalar(1.0), scalar(0.2)),
+            white().withRoughness(scalar(0.4)).withTransmission(scalar(0.7), 1.5),
+        ];
+        let wo = Vec3::unit(&Vec3::new(0.9, 0.2, 0.25));
+        let (r, hit) = hit(wo);
+        for material in &materials
+        {
+            let samples = 40000;
+            let mut mean = Color::origin();
+            for _ in 0..samples
+            {
+                let (scattered, weight) = match material.scatter(&r, &hit)
+                {
+                    Some(result) => result,
+                    None => continue,
+                };
+                let wi = Vec3::unit(&scattered.dir);
+                // Transmission is not evaluated.
+                if wi[2] < 0.0
+                {
+                    continue;
+                }
+                let expected = material.eval(&r, &hit, &wi) / material.pdf(&r, &hit, &wi);
+                assert!((weight - expected).norm() <= 1e-4 * expected.norm(),
+                        "{:?} != {:?}", weight, expected);
+                mean += weight / samples as Float;
+            }
+            // If `pdf` is the density of the samples, the weights
+            // average to the reflected light.
+            let expected = albedo(material, wo);
+            assert!((mean[0] - expected[0]).abs() < 0.015, "{} != {}", mean[0], expected[0]);
+        }
+    }
+}
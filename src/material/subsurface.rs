@@ -0,0 +1,136 @@
+use std::sync::Arc;
+
+use crate::config::Float;
+use crate::geometry::{Vec3, Color, Ray, Hit};
+use crate::medium::{AnyMedium, Homogeneous};
+use super::material::Material;
+use super::dielectric::Dielectric;
+
+/// Return the single scattering albedo that gives a semi-infinite
+/// slab the appearance of multiple scattering albedo `a` (Chiang et
+/// al. 2016).
+fn singleScatteringAlbedo(a: Float) -> Float
+{
+    let a = a.clamp(0.0, 0.999);
+    let x = 4.09712 + 4.20863 * a - (9.59217 + 41.6808 * a + 17.7126 * a * a).sqrt();
+    (1.0 - x * x).clamp(0.0, 1.0)
+}
+
+/// A translucent material (skin, wax, marble, milk), rendered by
+/// random walks in a medium inside of the surface. The surface is a
+/// dielectric. Objects of this material must be closed.
+pub struct Subsurface
+{
+    boundary: Dielectric,
+    medium: AnyMedium,
+    albedo: Color,
+    mean_free_path: Color,
+    g: Float,
+}
+
+impl Subsurface
+{
+    /// Construct a material that looks like color `albedo`, where
+    /// light goes an average of `mean_free_path` (per channel, in
+    /// world units) between scattering inside. The surface is smooth
+    /// with index of refraction 1.4.
+    pub fn new(albedo: Color, mean_free_path: Color) -> Self
+    {
+        let mut result = Self {
+            boundary: Dielectric::new(1.4),
+            medium: Arc::new(Homogeneous::new(Color::origin(), Color::origin())),
+            albedo,
+            mean_free_path,
+            g: 0.0,
+        };
+        result.buildMedium();
+        result
+    }
+
+    pub fn withIor(mut self, ior: Float) -> Self
+    {
+        self.boundary.ior = ior;
+        self
+    }
+
+    /// Set the perceptual roughness of the surface.
+    pub fn withRoughness(mut self, roughness: Float) -> Self
+    {
+        self.boundary = self.boundary.withRoughness(roughness);
+        self
+    }
+
+    /// Set the Henyey–Greenstein asymmetry `g` of the scattering
+    /// inside.
+    pub fn withPhase(mut self, g: Float) -> Self
+    {
+        self.g = g;
+        self.buildMedium();
+        self
+    }
+
+    fn buildMedium(&mut self)
+    {
+        let mut sigma_a = Color::origin();
+        let mut sigma_s = Color::origin();
+        for i in 0..3
+        {
+            let sigma_t = 1.0 / self.mean_free_path[i].max(1e-6);
+            let albedo = singleScatteringAlbedo(self.albedo[i]);
+            sigma_s[i] = albedo * sigma_t;
+            sigma_a[i] = sigma_t - sigma_s[i];
+        }
+        self.medium = Arc::new(Homogeneous::new(sigma_a, sigma_s).withPhase(self.g));
+    }
+}
+
+impl Material for Subsurface
+{
+    fn scatter(&self, r_in: &Ray, hit: &Hit) -> Option<(Ray, Vec3)>
+    {
+        self.boundary.scatter(r_in, hit)
+    }
+
+    fn interior(&self) -> Option<&AnyMedium>
+    {
+        Some(&self.medium)
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn singleScatteringAlbedoIsBelowAppearance()
+    {
+        assert!(singleScatteringAlbedo(0.0) < 1e-3);
+        let mut last = 0.0;
+        for i in 1..10
+        {
+            let a = i as Float / 10.0;
+            let single = singleScatteringAlbedo(a);
+            // Light that scatters many times must scatter each time
+            // with a higher albedo to look as bright.
+            assert!(single > last && single >= a, "{} for {}", single, a);
+            last = single;
+        }
+        assert!(singleScatteringAlbedo(0.999) > 0.999);
+    }
+
+    #[test]
+    fn mediumHasTheMeanFreePath()
+    {
+        let mean_free_path = Color::new(0.5, 1.0, 2.0);
+        let wax = Subsurface::new(Color::new(0.8, 0.5, 0.2), mean_free_path).withPhase(0.5);
+        let medium = wax.interior().unwrap();
+        for i in 0..3
+        {
+            let r = Ray::new(Vec3::origin(), Vec3::new(mean_free_path[i], 0.0, 0.0), 0.0);
+            let tr = medium.transmittance(&r, 1.0)[i];
+            assert!((tr - (-1.0 as Float).exp()).abs() < 1e-4, "{} in channel {}", tr, i);
+        }
+        assert!((medium.phase(&Vec3::origin()).g - 0.5).abs() < 1e-6);
+    }
+}
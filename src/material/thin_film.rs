@@ -0,0 +1,254 @@
+use std::ops;
+
+use crate::config::{Float, PI};
+use crate::geometry::{Vec3, Color, Hit};
+use crate::spectrum::{self, Wavelengths};
+use crate::texture::{self, AnyTexture};
+
+/// Just enough complex arithmetic for Fresnel equations with
+/// absorbing materials.
+#[derive(Clone, Copy, Debug)]
+struct Complex
+{
+    re: Float,
+    im: Float,
+}
+
+impl Complex
+{
+    const fn new(re: Float, im: Float) -> Self
+    {
+        Self { re, im }
+    }
+
+    const fn real(re: Float) -> Self
+    {
+        Self::new(re, 0.0)
+    }
+
+    fn normSquared(&self) -> Float
+    {
+        self.re * self.re + self.im * self.im
+    }
+
+    /// The principal square root.
+    fn sqrt(&self) -> Self
+    {
+        let r = self.normSquared().sqrt();
+        let re = (0.5 * (r + self.re)).max(0.0).sqrt();
+        let im = (0.5 * (r - self.re)).max(0.0).sqrt();
+        Self::new(re, if self.im < 0.0 { -im } else { im })
+    }
+
+    /// e^(i · self).
+    fn expI(&self) -> Self
+    {
+        let scale = (-self.im).exp();
+        Self::new(scale * self.re.cos(), scale * self.re.sin())
+    }
+}
+
+impl ops::Add for Complex
+{
+    type Output = Self;
+    fn add(self, rhs: Self) -> Self
+    {
+        Self::new(self.re + rhs.re, self.im + rhs.im)
+    }
+}
+
+impl ops::Sub for Complex
+{
+    type Output = Self;
+    fn sub(self, rhs: Self) -> Self
+    {
+        Self::new(self.re - rhs.re, self.im - rhs.im)
+    }
+}
+
+impl ops::Mul for Complex
+{
+    type Output = Self;
+    fn mul(self, rhs: Self) -> Self
+    {
+        Self::new(self.re * rhs.re - self.im * rhs.im, self.re * rhs.im + self.im * rhs.re)
+    }
+}
+
+impl ops::Div for Complex
+{
+    type Output = Self;
+    fn div(self, rhs: Self) -> Self
+    {
+        let d = rhs.normSquared();
+        Self::new((self.re * rhs.re + self.im * rhs.im) / d,
+                  (self.im * rhs.re - self.re * rhs.im) / d)
+    }
+}
+
+/// The indices of refraction around a thin film at one wavelength:
+/// on the side of the incident light, and of the substrate under the
+/// film, which may be absorbing.
+#[derive(Clone, Copy, Debug)]
+pub struct FilmInterface
+{
+    pub outside: Float,
+    pub substrate_eta: Float,
+    pub substrate_k: Float,
+}
+
+/// A thin transparent coating, e.g. soap, oil or a lens coating.
+/// Light reflected at its two faces interferes, so the reflectance
+/// depends on the wavelength, which gives iridescent colors.
+pub struct ThinFilm
+{
+    /// The thickness of the film in nm, as the luminance of a
+    /// texture.
+    thickness: AnyTexture,
+    ior: Float,
+}
+
+impl ThinFilm
+{
+    /// Construct a film of index of refraction `ior`, `thickness` nm
+    /// thick. Visible colors appear up to about 1000 nm.
+    pub fn new(thickness: AnyTexture, ior: Float) -> Self
+    {
+        Self { thickness, ior }
+    }
+
+    pub fn thicknessAt(&self, hit: &Hit) -> Float
+    {
+        texture::luminance(&self.thickness.filtered(hit)).max(0.0)
+    }
+
+    /// The reflectance at wavelength `lambda` (in nm) of a film
+    /// `thickness` nm thick, for unpolarized light at cosine `cos`
+    /// on the outside, by the Airy summation of the reflections
+    /// inside the film.
+    pub fn reflectance(&self, thickness: Float, cos: Float, interface: &FilmInterface,
+                       lambda: Float) -> Float
+    {
+        let n1 = Complex::real(interface.outside);
+        let n2 = Complex::real(self.ior);
+        let n3 = Complex::new(interface.substrate_eta, interface.substrate_k);
+        let cos1 = Complex::real(cos.clamp(0.0, 1.0));
+        let sin1 = interface.outside * (1.0 - cos.clamp(0.0, 1.0).powi(2)).sqrt();
+        // Snell's law, with n sin θ the same in all layers.
+        let cosIn = |n: Complex| {
+            let s = Complex::real(sin1) / n;
+            (Complex::real(1.0) - s * s).sqrt()
+        };
+        let cos2 = cosIn(n2);
+        let cos3 = cosIn(n3);
+
+        // The phase difference of one round trip in the film.
+        let delta = Complex::real(4.0 * PI * thickness / lambda) * n2 * cos2;
+        let phase = delta.expI();
+        let airy = |r12: Complex, r23: Complex| {
+            let r23 = r23 * phase;
+            ((r12 + r23) / (Complex::real(1.0) + r12 * r23)).normSquared()
+        };
+        let rs = airy((n1 * cos1 - n2 * cos2) / (n1 * cos1 + n2 * cos2),
+                      (n2 * cos2 - n3 * cos3) / (n2 * cos2 + n3 * cos3));
+        let rp = airy((n2 * cos1 - n1 * cos2) / (n2 * cos1 + n1 * cos2),
+                      (n3 * cos2 - n2 * cos3) / (n3 * cos2 + n2 * cos3));
+        (0.5 * (rs + rp)).clamp(0.0, 1.0)
+    }
+
+    /// The reflectance at `hit` for light at cosine `cos`, where
+    /// `interface` gives the indices of refraction at each
+    /// wavelength. In spectral mode, this is only for the hero
+    /// wavelength, so the secondary wavelengths of `wavelengths` are
+    /// terminated. Otherwise it is the RGB of the reflectance
+    /// spectrum.
+    pub fn reflectanceAt<F>(&self, hit: &Hit, cos: Float, interface: F,
+                            wavelengths: &mut Option<Wavelengths>) -> Color
+        where F: Fn(Float) -> FilmInterface
+    {
+        let thickness = self.thicknessAt(hit);
+        match wavelengths
+        {
+            Some(w) =>
+            {
+                w.terminateSecondary();
+                let r = self.reflectance(thickness, cos, &interface(w.hero()), w.hero());
+                Vec3::new(r, r, r)
+            },
+            None =>
+            {
+                // Out of gamut colors may have negative channels.
+                let rgb = spectrum::reflectanceToRgb(
+                    |lambda| self.reflectance(thickness, cos, &interface(lambda), lambda));
+                Vec3::new(rgb[0].clamp(0.0, 1.0), rgb[1].clamp(0.0, 1.0),
+                          rgb[2].clamp(0.0, 1.0))
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use std::sync::Arc;
+    use super::*;
+    use crate::geometry::Ray;
+    use crate::texture::Constant;
+    use crate::material::microfacet;
+
+    fn assertNear(a: Float, b: Float, tolerance: Float)
+    {
+        assert!((a - b).abs() <= tolerance, "{} is not near {}", a, b);
+    }
+
+    fn glass() -> FilmInterface
+    {
+        FilmInterface { outside: 1.0, substrate_eta: 1.5, substrate_k: 0.0 }
+    }
+
+    #[test]
+    fn filmWithoutThicknessIsFresnel()
+    {
+        let film = ThinFilm::new(Arc::new(Constant::scalar(0.0)), 1.33);
+        let gold = FilmInterface { outside: 1.0, substrate_eta: 0.2, substrate_k: 3.0 };
+        for &cos in &[1.0, 0.7, 0.3, 0.05]
+        {
+            assertNear(film.reflectance(0.0, cos, &glass(), 550.0),
+                       microfacet::fresnelDielectric(cos, 1.5), 1e-4);
+            assertNear(film.reflectance(0.0, cos, &gold, 550.0),
+                       microfacet::fresnelConductor(cos, 0.2, 3.0), 1e-4);
+            // A film like the air around it is no film at all.
+            let air = ThinFilm::new(Arc::new(Constant::scalar(0.0)), 1.0);
+            assertNear(air.reflectance(300.0, cos, &glass(), 550.0),
+                       microfacet::fresnelDielectric(cos, 1.5), 1e-4);
+        }
+    }
+
+    #[test]
+    fn quarterWaveCoatingCancelsReflection()
+    {
+        let n = (1.5 as Float).sqrt();
+        let film = ThinFilm::new(Arc::new(Constant::scalar(0.0)), n);
+        let lambda = 550.0;
+        assert!(film.reflectance(lambda / (4.0 * n), 1.0, &glass(), lambda) < 1e-4);
+        // Half a wave thick, the film is invisible.
+        assertNear(film.reflectance(lambda / (2.0 * n), 1.0, &glass(), lambda),
+                   microfacet::fresnelDielectric(1.0, 1.5), 1e-4);
+    }
+
+    #[test]
+    fn spectralReflectanceKeepsOnlyTheHero()
+    {
+        let film = ThinFilm::new(Arc::new(Constant::scalar(400.0)), 1.33);
+        let r = Ray::new(Vec3::new(0.0, 0.0, 1.0), Vec3::new(0.0, 0.0, -1.0), 0.0);
+        let hit = Hit::new(&r, 1.0, Vec3::new(0.0, 0.0, 1.0), 0.5, 0.5,
+                           Vec3::new(1.0, 0.0, 0.0), Vec3::new(0.0, 1.0, 0.0), 0);
+        let mut wavelengths = Some(Wavelengths { lambda: [480.0, 637.0, 793.0], active: 3 });
+        let f = film.reflectanceAt(&hit, 1.0, |_| glass(), &mut wavelengths);
+        assertNear(f[0], film.reflectance(400.0, 1.0, &glass(), 480.0), 1e-6);
+        assert_eq!(wavelengths.unwrap().active, 1);
+        // In RGB, an oil film on glass is colored.
+        let f = film.reflectanceAt(&hit, 1.0, |_| glass(), &mut None);
+        assert!((f[0] - f[1]).abs() > 0.01 || (f[1] - f[2]).abs() > 0.01);
+    }
+}
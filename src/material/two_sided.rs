@@ -0,0 +1,155 @@
+use crate::config::Float;
+use crate::geometry::{Vec3, Color, Ray, Hit};
+use crate::medium::AnyMedium;
+use super::material::{Material, AnyMaterial};
+
+/// A material for surfaces without an inside, e.g. leaves and paper.
+/// Back faces are shaded as front faces, either of the same material
+/// or of a different one.
+pub struct TwoSided
+{
+    front: AnyMaterial,
+    back: Option<AnyMaterial>,
+}
+
+impl TwoSided
+{
+    /// Shade both sides with `material`.
+    pub fn new(material: AnyMaterial) -> Self
+    {
+        Self { front: material, back: None }
+    }
+
+    /// Shade the back with `back` instead.
+    pub fn withBack(mut self, back: AnyMaterial) -> Self
+    {
+        self.back = Some(back);
+        self
+    }
+
+    /// Return the material of the side of `hit`, and the hit on its
+    /// front.
+    fn side(&self, hit: &Hit) -> (&AnyMaterial, Hit)
+    {
+        if hit.front_face
+        {
+            return (&self.front, hit.clone());
+        }
+        // Turn the surface around, so that the ray hits its front.
+        let mut flipped = hit.clone();
+        flipped.normal = -hit.normal;
+        flipped.front_face = true;
+        flipped.setShadingNormal(-hit.shading_normal);
+        (self.back(), flipped)
+    }
+
+    fn back(&self) -> &AnyMaterial
+    {
+        self.back.as_ref().unwrap_or(&self.front)
+    }
+}
+
+impl Material for TwoSided
+{
+    fn scatter(&self, r_in: &Ray, hit: &Hit) -> Option<(Ray, Vec3)>
+    {
+        let (material, hit) = self.side(hit);
+        material.scatter(r_in, &hit)
+    }
+
+    fn eval(&self, r_in: &Ray, hit: &Hit, wi: &Vec3) -> Color
+    {
+        let (material, hit) = self.side(hit);
+        material.eval(r_in, &hit, wi)
+    }
+
+    fn pdf(&self, r_in: &Ray, hit: &Hit, wi: &Vec3) -> Float
+    {
+        let (material, hit) = self.side(hit);
+        material.pdf(r_in, &hit, wi)
+    }
+
+    fn emitted(&self, r_in: &Ray, hit: &Hit) -> Color
+    {
+        let (material, hit) = self.side(hit);
+        material.emitted(r_in, &hit)
+    }
+
+    /// The medium inside is that of the front, or else of the back.
+    fn interior(&self) -> Option<&AnyMedium>
+    {
+        self.front.interior().or_else(|| self.back().interior())
+    }
+
+    /// Invisible if both sides are.
+    fn isInvisible(&self) -> bool
+    {
+        self.front.isInvisible() && self.back().isInvisible()
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use std::sync::Arc;
+    use super::*;
+    use crate::config::PI;
+    use crate::geometry::vec3;
+    use crate::texture::Constant;
+    use crate::medium::Homogeneous;
+    use crate::material::{Lambertian, DiffuseLight, MediumBoundary};
+
+    fn lambertian(x: Float) -> AnyMaterial
+    {
+        Arc::new(Lambertian { albedo: Arc::new(Constant::new(Color::new(x, x, x))) })
+    }
+
+    /// A hit on the back of the plane z = 0, from below.
+    fn backHit() -> (Ray, Hit)
+    {
+        let r = Ray::new(Vec3::new(0.0, 0.0, -1.0), Vec3::new(0.0, 0.0, 1.0), 0.0);
+        let hit = Hit::new(&r, 1.0, Vec3::new(0.0, 0.0, 1.0), 0.5, 0.5,
+                           Vec3::new(1.0, 0.0, 0.0), Vec3::new(0.0, 1.0, 0.0), 0);
+        (r, hit)
+    }
+
+    #[test]
+    fn backFacesAreShadedAsFronts()
+    {
+        let (r, hit) = backHit();
+        assert!(!hit.front_face);
+        let paper = TwoSided::new(lambertian(0.8));
+        for _ in 0..100
+        {
+            let (scattered, _) = paper.scatter(&r, &hit).unwrap();
+            assert!(vec3::dot(&scattered.dir, &hit.normal) < 0.0);
+        }
+        let down = Vec3::new(0.0, 0.0, -1.0);
+        assert!((paper.eval(&r, &hit, &down)[0] - 0.8 / PI).abs() < 1e-4);
+        assert!(paper.pdf(&r, &hit, &down) > 0.0);
+
+        let card = TwoSided::new(lambertian(0.8)).withBack(lambertian(0.2));
+        assert!((card.eval(&r, &hit, &down)[0] - 0.2 / PI).abs() < 1e-4);
+    }
+
+    #[test]
+    fn bothSidesEmitAndKeepTheirMedium()
+    {
+        let (r, hit) = backHit();
+        let glow = |x: Float| -> AnyMaterial {
+            Arc::new(DiffuseLight::new(Arc::new(Constant::new(Color::new(x, x, x)))))
+        };
+        // A light emits from its front only, unless two-sided.
+        assert_eq!(glow(2.0).emitted(&r, &hit)[0], 0.0);
+        assert_eq!(TwoSided::new(glow(2.0)).emitted(&r, &hit)[0], 2.0);
+        let sign = TwoSided::new(glow(2.0)).withBack(glow(3.0));
+        assert_eq!(sign.emitted(&r, &hit)[0], 3.0);
+        assert!(sign.interior().is_none());
+        assert!(!sign.isInvisible());
+
+        let medium = Arc::new(Homogeneous::new(Color::new(1.0, 1.0, 1.0), Color::origin()));
+        let boundary = TwoSided::new(Arc::new(MediumBoundary::new(medium)));
+        assert!(boundary.interior().is_some());
+        assert!(boundary.isInvisible());
+    }
+}
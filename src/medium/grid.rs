@@ -0,0 +1,187 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::config::Float;
+use crate::geometry::{Vec3, BBox};
+
+/// A 3D grid of scalars (e.g. density or temperature from a
+/// simulation), stretched over a bounding box. The values are at the
+/// centers of the voxels, and are trilinearly interpolated between.
+/// Outside of the bounding box, the value is 0.
+pub struct VoxelGrid
+{
+    size: [usize; 3],
+    /// x changes the fastest, then y, then z.
+    data: Vec<Float>,
+    bbox: BBox,
+    max: Float,
+}
+
+impl VoxelGrid
+{
+    /// Construct a grid of `nx` × `ny` × `nz` voxels over `bbox`.
+    /// Panic if a size is 0, or if `data` is not of that size.
+    pub fn new(nx: usize, ny: usize, nz: usize, data: Vec<Float>, bbox: BBox) -> Self
+    {
+        if nx == 0 || ny == 0 || nz == 0
+        {
+            panic!("Voxel grid has no voxels");
+        }
+        if data.len() != nx * ny * nz
+        {
+            panic!("Voxel grid data does not match its size");
+        }
+        let max = data.iter().cloned().fold(0.0, Float::max);
+        Self { size: [nx, ny, nz], data, bbox, max }
+    }
+
+    /// Load a grid from a raw voxel file, and place it in `bbox`.
+    ///
+    /// The file is the 4 bytes “LVOX”, followed by the number of
+    /// voxels in x, y and z as little-endian u32, followed by the
+    /// values as little-endian f32, x changing the fastest.
+    pub fn load<P: AsRef<Path>>(path: P, bbox: BBox) -> io::Result<Self>
+    {
+        let bytes = fs::read(path)?;
+        let invalid = |msg| io::Error::new(io::ErrorKind::InvalidData, msg);
+        if bytes.len() < 16 || &bytes[0..4] != b"LVOX"
+        {
+            return Err(invalid("Not a raw voxel file"));
+        }
+        let word = |i: usize| {
+            [bytes[i], bytes[i + 1], bytes[i + 2], bytes[i + 3]]
+        };
+        let nx = u32::from_le_bytes(word(4)) as usize;
+        let ny = u32::from_le_bytes(word(8)) as usize;
+        let nz = u32::from_le_bytes(word(12)) as usize;
+        if nx == 0 || ny == 0 || nz == 0
+        {
+            return Err(invalid("Raw voxel file has no voxels"));
+        }
+        // The sizes come from the file, so they may overflow.
+        let count = nx.checked_mul(ny).and_then(|n| n.checked_mul(nz));
+        let expected = count.and_then(|n| n.checked_mul(4)).and_then(|n| n.checked_add(16));
+        let count = match (count, expected)
+        {
+            (Some(count), Some(expected)) if bytes.len() == expected => count,
+            _ => return Err(invalid("Wrong size of raw voxel file")),
+        };
+        let data = (0..count).map(|i| f32::from_le_bytes(word(16 + i * 4)) as Float)
+            .collect();
+        Ok(Self::new(nx, ny, nz, data, bbox))
+    }
+
+    pub fn bbox(&self) -> &BBox
+    {
+        &self.bbox
+    }
+
+    /// The largest value in the grid.
+    pub fn max(&self) -> Float
+    {
+        self.max
+    }
+
+    fn voxel(&self, x: usize, y: usize, z: usize) -> Float
+    {
+        self.data[(z * self.size[1] + y) * self.size[0] + x]
+    }
+
+    /// The interpolated value at `p`.
+    pub fn lookup(&self, p: &Vec3) -> Float
+    {
+        let mut index = [0; 3];
+        let mut frac = [0.0; 3];
+        for i in 0..3
+        {
+            let extent = self.bbox.higher[i] - self.bbox.lower[i];
+            let x = (p[i] - self.bbox.lower[i]) / extent;
+            if !(0.0..=1.0).contains(&x)
+            {
+                return 0.0;
+            }
+            // Coordinates of the voxel centers are integers.
+            let g = (x * self.size[i] as Float - 0.5)
+                .clamp(0.0, (self.size[i] - 1) as Float);
+            index[i] = (g.floor() as usize).min(self.size[i].saturating_sub(2));
+            frac[i] = if self.size[i] > 1 { g - index[i] as Float } else { 0.0 };
+        }
+        let next = |i: usize| if self.size[i] > 1 { index[i] + 1 } else { index[i] };
+        let lerp = |a: Float, b: Float, t: Float| a + (b - a) * t;
+        let (x0, y0, z0) = (index[0], index[1], index[2]);
+        let (x1, y1, z1) = (next(0), next(1), next(2));
+        let c00 = lerp(self.voxel(x0, y0, z0), self.voxel(x1, y0, z0), frac[0]);
+        let c10 = lerp(self.voxel(x0, y1, z0), self.voxel(x1, y1, z0), frac[0]);
+        let c01 = lerp(self.voxel(x0, y0, z1), self.voxel(x1, y0, z1), frac[0]);
+        let c11 = lerp(self.voxel(x0, y1, z1), self.voxel(x1, y1, z1), frac[0]);
+        lerp(lerp(c00, c10, frac[1]), lerp(c01, c11, frac[1]), frac[2])
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    fn unitBox() -> BBox
+    {
+        BBox { lower: Vec3::new(0.0, 0.0, 0.0), higher: Vec3::new(1.0, 1.0, 1.0) }
+    }
+
+    /// Write a raw voxel file with header sizes `size` and `values`,
+    /// and return its path.
+    fn writeRaw(name: &str, size: [u32; 3], values: &[f32]) -> std::path::PathBuf
+    {
+        let mut bytes = b"LVOX".to_vec();
+        for n in &size
+        {
+            bytes.extend_from_slice(&n.to_le_bytes());
+        }
+        for v in values
+        {
+            bytes.extend_from_slice(&v.to_le_bytes());
+        }
+        let path = std::env::temp_dir().join(name);
+        fs::write(&path, bytes).unwrap();
+        path
+    }
+
+    #[test]
+    fn lookupInterpolatesBetweenVoxelCenters()
+    {
+        let grid = VoxelGrid::new(2, 1, 1, vec![1.0, 3.0], unitBox());
+        assert_eq!(grid.max(), 3.0);
+        // Constant up to the outer centers, linear between.
+        assert_eq!(grid.lookup(&Vec3::new(0.1, 0.5, 0.5)), 1.0);
+        assert!((grid.lookup(&Vec3::new(0.5, 0.2, 0.9)) - 2.0).abs() < 1e-5);
+        assert_eq!(grid.lookup(&Vec3::new(0.9, 0.5, 0.5)), 3.0);
+        assert_eq!(grid.lookup(&Vec3::new(1.5, 0.5, 0.5)), 0.0);
+    }
+
+    #[test]
+    fn loadsRawFilesAndRejectsBadHeaders()
+    {
+        let path = writeRaw("lorentz_grid_ok.lvox", [2, 1, 1], &[1.0, 3.0]);
+        let grid = VoxelGrid::load(&path, unitBox()).unwrap();
+        assert!((grid.lookup(&Vec3::new(0.5, 0.5, 0.5)) - 2.0).abs() < 1e-5);
+        fs::remove_file(&path).unwrap();
+
+        let empty = writeRaw("lorentz_grid_empty.lvox", [0, 4, 4], &[]);
+        let huge = writeRaw("lorentz_grid_huge.lvox", [u32::MAX, u32::MAX, u32::MAX], &[1.0]);
+        let short = writeRaw("lorentz_grid_short.lvox", [2, 2, 1], &[1.0, 3.0]);
+        for path in &[empty, huge, short]
+        {
+            let e = VoxelGrid::load(path, unitBox()).err().unwrap();
+            assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+            fs::remove_file(path).unwrap();
+        }
+    }
+
+    #[test]
+    #[should_panic]
+    fn gridWithoutVoxelsPanics()
+    {
+        VoxelGrid::new(0, 1, 1, vec![], unitBox());
+    }
+}
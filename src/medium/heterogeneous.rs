@@ -0,0 +1,143 @@
+use std::sync::Arc;
+
+use crate::rand;
+use crate::config::Float;
+use crate::geometry::{Vec3, Color, Ray, AxisBox};
+use crate::spectrum;
+use super::volume::{Medium, MediumEvent, HenyeyGreenstein};
+use super::grid::VoxelGrid;
+
+/// A medium with density from a voxel grid, e.g. smoke or fire from
+/// a simulation. The medium is inside the bounding box of the grid;
+/// bound it with `boundary()`.
+///
+/// Free flights are sampled by delta tracking, and transmittance is
+/// estimated by ratio tracking, against the largest density in the
+/// grid. Both are unbiased.
+pub struct Heterogeneous
+{
+    density: Arc<VoxelGrid>,
+    /// The extinction coefficient at density 1, per unit distance.
+    sigma_t: Float,
+    /// The fraction of extinction that is scattering, per channel.
+    albedo: Color,
+    phase: HenyeyGreenstein,
+    /// Emitted radiance per unit of absorption, from a grid times a
+    /// color.
+    emission: Option<(Arc<VoxelGrid>, Color)>,
+}
+
+impl Heterogeneous
+{
+    pub fn new(density: Arc<VoxelGrid>, sigma_t: Float, albedo: Color) -> Self
+    {
+        Self { density, sigma_t, albedo,
+               phase: HenyeyGreenstein::new(0.0), emission: None }
+    }
+
+    /// Set the Henyey–Greenstein asymmetry `g` of the phase function.
+    pub fn withPhase(mut self, g: Float) -> Self
+    {
+        self.phase = HenyeyGreenstein::new(g);
+        self
+    }
+
+    /// Make the medium glow (e.g. fire) with radiance `grid` × `color`
+    /// where it absorbs light. `grid` is usually temperature, and
+    /// should cover the same box as the density.
+    pub fn withEmission(mut self, grid: Arc<VoxelGrid>, color: Color) -> Self
+    {
+        self.emission = Some((grid, color));
+        self
+    }
+
+    /// Return a box around the medium, for a `MediumBoundary`
+    /// material at index `material`.
+    pub fn boundary(&self, material: usize) -> AxisBox
+    {
+        let bbox = self.density.bbox();
+        AxisBox { lower: bbox.lower, higher: bbox.higher, material }
+    }
+
+    fn majorant(&self) -> Float
+    {
+        self.sigma_t * self.density.max()
+    }
+
+    /// Return the ray time after a random step from `t`, with
+    /// exponential distribution by the majorant.
+    fn step(&self, r: &Ray, t: Float) -> Float
+    {
+        t - (1.0 - rand::random::<Float>()).ln() / (self.majorant() * r.dir.norm())
+    }
+}
+
+impl Medium for Heterogeneous
+{
+    fn sample(&self, r: &Ray, t_max: Float, _: &Color) -> MediumEvent
+    {
+        let pass = MediumEvent::Pass { weight: Vec3::new(1.0, 1.0, 1.0) };
+        if self.majorant() <= 0.0
+        {
+            return pass;
+        }
+        let (mut t, t_exit) = match self.density.bbox().clip(r, 0.0, t_max)
+        {
+            Some(range) => range,
+            None => return pass,
+        };
+        loop
+        {
+            t = self.step(r, t);
+            if t >= t_exit
+            {
+                return pass;
+            }
+            let p = r.at(t);
+            let sigma_t = self.sigma_t * self.density.lookup(&p);
+            if rand::random::<Float>() * self.majorant() < sigma_t
+            {
+                let along = |c: &Color| match &r.wavelengths
+                {
+                    Some(w) => spectrum::upsample(c, w),
+                    None => *c,
+                };
+                let albedo = along(&self.albedo);
+                let emitted = match &self.emission
+                {
+                    Some((grid, color)) =>
+                        (Vec3::new(1.0, 1.0, 1.0) - albedo) * along(color) * grid.lookup(&p),
+                    None => Color::origin(),
+                };
+                return MediumEvent::Scatter { t, weight: albedo, emitted };
+            }
+        }
+    }
+
+    fn transmittance(&self, r: &Ray, t_max: Float) -> Color
+    {
+        let mut tr = 1.0;
+        if self.majorant() > 0.0
+        {
+            if let Some((mut t, t_exit)) = self.density.bbox().clip(r, 0.0, t_max)
+            {
+                loop
+                {
+                    t = self.step(r, t);
+                    if t >= t_exit || tr <= 0.0
+                    {
+                        break;
+                    }
+                    let sigma_t = self.sigma_t * self.density.lookup(&r.at(t));
+                    tr *= 1.0 - sigma_t / self.majorant();
+                }
+            }
+        }
+        Vec3::new(tr, tr, tr)
+    }
+
+    fn phase(&self, _: &Vec3) -> HenyeyGreenstein
+    {
+        self.phase
+    }
+}
@@ -0,0 +1,11 @@
+//! Participating media: fog, smoke, and the insides of translucent
+//! objects.
+
+mod volume;
+pub use self::volume::*;
+
+mod grid;
+pub use self::grid::*;
+
+mod heterogeneous;
+pub use self::heterogeneous::*;
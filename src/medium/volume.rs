@@ -0,0 +1,288 @@
+use std::sync::Arc;
+
+use crate::rand;
+use crate::config::{Float, PI};
+use crate::geometry::vec3;
+use crate::geometry::{Vec3, Color, Ray};
+use crate::spectrum;
+
+/// The Henyey–Greenstein phase function, which describes how much
+/// light is scattered to each direction in a medium.
+#[derive(Clone, Copy, Debug)]
+pub struct HenyeyGreenstein
+{
+    /// The mean cosine of the scattering angle, in (-1, 1). Positive
+    /// scatters forward, negative scatters backward, and 0 is
+    /// isotropic.
+    pub g: Float,
+}
+
+impl HenyeyGreenstein
+{
+    pub fn new(g: Float) -> Self
+    {
+        Self { g: g.clamp(-0.99, 0.99) }
+    }
+
+    /// The density of scattering to an angle with cosine `cos` from
+    /// the direction of travel, per solid angle.
+    pub fn eval(&self, cos: Float) -> Float
+    {
+        let g = self.g;
+        let denomi = 1.0 + g * g - 2.0 * g * cos;
+        (1.0 - g * g) / (4.0 * PI * denomi * denomi.sqrt())
+    }
+
+    /// Sample a scattered direction (unit) for light traveling in
+    /// direction `dir`. Its density is exactly `eval`.
+    pub fn sample(&self, dir: &Vec3) -> Vec3
+    {
+        let g = self.g;
+        let u = rand::random::<Float>();
+        let cos = if g.abs() < 1e-3
+        {
+            1.0 - 2.0 * u
+        }
+        else
+        {
+            let s = (1.0 - g * g) / (1.0 - g + 2.0 * g * u);
+            (1.0 + g * g - s * s) / (2.0 * g)
+        };
+        let sin = (1.0 - cos * cos).max(0.0).sqrt();
+        let phi = 2.0 * PI * rand::random::<Float>();
+        let w = Vec3::unit(dir);
+        let (t, b) = vec3::basis(&w);
+        w * cos + (t * phi.cos() + b * phi.sin()) * sin
+    }
+}
+
+/// What happens to light traveling through a medium.
+pub enum MediumEvent
+{
+    /// The light is scattered at ray time `t`. `weight` is the
+    /// fraction of it that survives to scatter, over the probability
+    /// of this event. `emitted` is the radiance emitted by the
+    /// medium, over the same probability.
+    Scatter { t: Float, weight: Color, emitted: Color },
+    /// The light goes through. `weight` is the fraction that goes
+    /// through, over the probability of this event.
+    Pass { weight: Color },
+}
+
+/// In spectral mode (when the ray has wavelengths), a medium
+/// evaluates its coefficients at the wavelengths of the ray, and
+/// returns weights, emission and transmittance per wavelength.
+pub trait Medium
+{
+    /// Sample where light along ray `r` between ray time 0 and `t_max`
+    /// is scattered, if at all. `throughput` is the throughput of the
+    /// path so far; colored media use it to choose which channel to
+    /// sample by.
+    fn sample(&self, r: &Ray, t_max: Float, throughput: &Color) -> MediumEvent;
+
+    /// Return the fraction of light that goes through along ray `r`
+    /// between ray time 0 and `t_max`.
+    fn transmittance(&self, r: &Ray, t_max: Float) -> Color;
+
+    /// The phase function at `p`.
+    fn phase(&self, p: &Vec3) -> HenyeyGreenstein;
+}
+
+pub type AnyMedium = Arc<dyn Medium + Send + Sync>;
+
+fn expColor(c: &Color) -> Color
+{
+    Vec3::new(c[0].exp(), c[1].exp(), c[2].exp())
+}
+
+/// A medium with the same density everywhere.
+pub struct Homogeneous
+{
+    /// Absorption coefficient per unit distance.
+    pub sigma_a: Color,
+    /// Scattering coefficient per unit distance.
+    pub sigma_s: Color,
+    pub phase: HenyeyGreenstein,
+}
+
+impl Homogeneous
+{
+    /// Construct an isotropic medium.
+    pub fn new(sigma_a: Color, sigma_s: Color) -> Self
+    {
+        Self { sigma_a, sigma_s, phase: HenyeyGreenstein::new(0.0) }
+    }
+
+    /// Set the Henyey–Greenstein asymmetry `g` of the phase function.
+    pub fn withPhase(mut self, g: Float) -> Self
+    {
+        self.phase = HenyeyGreenstein::new(g);
+        self
+    }
+
+    /// The absorption and the scattering coefficients along `r`.
+    fn coefficients(&self, r: &Ray) -> (Color, Color)
+    {
+        match &r.wavelengths
+        {
+            Some(w) => (spectrum::upsample(&self.sigma_a, w), spectrum::upsample(&self.sigma_s, w)),
+            None => (self.sigma_a, self.sigma_s),
+        }
+    }
+}
+
+impl Medium for Homogeneous
+{
+    fn sample(&self, r: &Ray, t_max: Float, throughput: &Color) -> MediumEvent
+    {
+        let (sigma_a, sigma_s) = self.coefficients(r);
+        let sigma_t = sigma_a + sigma_s;
+        let speed = r.dir.norm();
+        let max_distance = t_max * speed;
+        // Sample the distance by the extinction of a channel chosen by
+        // the throughput. The probability is that of the mixture of
+        // all the channels, so colored media do not make extreme
+        // weights, even after many scattering events (Chiang et al.
+        // 2016).
+        let total = throughput[0] + throughput[1] + throughput[2];
+        let channel_prob = if total > 1e-20 { *throughput / total }
+                           else { Vec3::new(1.0, 1.0, 1.0) / 3.0 };
+        let u = rand::random::<Float>();
+        let channel = if u < channel_prob[0] { 0 }
+                      else if u < channel_prob[0] + channel_prob[1] { 1 }
+                      else { 2 };
+        let sigma = sigma_t[channel];
+        let distance = if sigma > 0.0
+        {
+            -(1.0 - rand::random::<Float>()).ln() / sigma
+        }
+        else
+        {
+            Float::INFINITY
+        };
+        if distance < max_distance
+        {
+            let tr = expColor(&(-distance * sigma_t));
+            let pdf = vec3::dot(&channel_prob, &(tr * sigma_t));
+            if pdf <= 0.0
+            {
+                return MediumEvent::Pass { weight: Color::origin() };
+            }
+            return MediumEvent::Scatter { t: distance / speed, weight: tr * sigma_s / pdf,
+                                          emitted: Color::origin() };
+        }
+        let tr = expColor(&(-max_distance * sigma_t));
+        let probability = vec3::dot(&channel_prob, &tr);
+        if probability <= 0.0
+        {
+            return MediumEvent::Pass { weight: Color::origin() };
+        }
+        MediumEvent::Pass { weight: tr / probability }
+    }
+
+    fn transmittance(&self, r: &Ray, t_max: Float) -> Color
+    {
+        let (sigma_a, sigma_s) = self.coefficients(r);
+        expColor(&(-(t_max * r.dir.norm()) * (sigma_a + sigma_s)))
+    }
+
+    fn phase(&self, _: &Vec3) -> HenyeyGreenstein
+    {
+        self.phase
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use crate::spectrum::Wavelengths;
+
+    fn assertNear(a: Float, b: Float, tolerance: Float)
+    {
+        assert!((a - b).abs() <= tolerance, "{} is not near {}", a, b);
+    }
+
+    #[test]
+    fn phaseIsNormalizedWithMeanCosineG()
+    {
+        for &g in &[-0.5, 0.0, 0.3, 0.8]
+        {
+            let phase = HenyeyGreenstein::new(g);
+            // Integrate over the cosine; the azimuth gives 2π.
+            let n = 20000;
+            let (mut total, mut mean) = (0.0, 0.0);
+            for i in 0..n
+            {
+                let cos = -1.0 + 2.0 * (i as Float + 0.5) / n as Float;
+                let p = phase.eval(cos) * 2.0 * PI * 2.0 / n as Float;
+                total += p;
+                mean += p * cos;
+            }
+            assertNear(total, 1.0, 1e-3);
+            assertNear(mean, g, 1e-3);
+
+            let dir = Vec3::unit(&Vec3::new(1.0, 2.0, -0.5));
+            let samples = 20000;
+            let sampled: Float = (0..samples)
+                .map(|_| vec3::dot(&phase.sample(&dir), &dir)).sum::<Float>() / samples as Float;
+            assertNear(sampled, g, 0.02);
+        }
+    }
+
+    #[test]
+    fn colorMediumPassesByTransmittance()
+    {
+        let fog = Homogeneous::new(Color::new(0.1, 0.5, 1.0), Color::new(0.4, 0.3, 0.2));
+        let r = Ray::new(Vec3::origin(), Vec3::new(0.0, 0.0, 2.0), 0.0);
+        let tr = fog.transmittance(&r, 0.5);
+        assertNear(tr[0], (-0.5 as Float).exp(), 1e-5);
+
+        // The weights of passing estimate the transmittance, whatever
+        // channel the throughput favors.
+        let throughput = Color::new(1.0, 0.5, 0.1);
+        let samples = 50000;
+        let mut passed = Color::origin();
+        let mut scattered = 0;
+        for _ in 0..samples
+        {
+            match fog.sample(&r, 0.5, &throughput)
+            {
+                MediumEvent::Pass { weight } => passed += weight / samples as Float,
+                MediumEvent::Scatter { t, .. } =>
+                {
+                    assert!(t > 0.0 && t < 0.5);
+                    scattered += 1;
+                },
+            }
+        }
+        for i in 0..3
+        {
+            assertNear(passed[i], tr[i], 0.02);
+        }
+        assert!(scattered > 0);
+    }
+
+    #[test]
+    fn spectralMediumAbsorbsByWavelength()
+    {
+        // Absorbs red only.
+        let ink = Homogeneous::new(Color::new(4.0, 0.0, 0.0), Color::origin());
+        let mut r = Ray::new(Vec3::origin(), Vec3::new(1.0, 0.0, 0.0), 0.0);
+        let w = Wavelengths { lambda: [650.0, 450.0, 550.0], active: 3 };
+        r.wavelengths = Some(w);
+        let tr = ink.transmittance(&r, 1.0);
+        let expected = spectrum::upsample(&Color::new(4.0, 0.0, 0.0), &w);
+        for i in 0..3
+        {
+            assertNear(tr[i], (-expected[i]).exp(), 1e-5);
+        }
+        assert!(tr[0] < 0.1 && tr[1] > 0.9);
+        // A red throughput samples the red wavelength.
+        match ink.sample(&r, 1.0, &Color::new(1.0, 0.0, 0.0))
+        {
+            MediumEvent::Pass { weight } => assertNear(weight[0], 1.0, 1e-4),
+            MediumEvent::Scatter { weight, .. } => assertNear(weight[0], 0.0, 1e-6),
+        }
+    }
+}
@@ -1,3 +1,5 @@
+#![allow(clippy::redundant_field_names)]
+
 use std::sync::Arc;
 
 use crate::config::Float;
@@ -36,11 +38,37 @@ fn buildPrimitives() -> PrimitiveList
         for j in 0..20
         {
             let z = -5.0 + 0.5 * (j as Float);
-            stuff.push(Arc::new(geometry::Sphere {
-                center: Vec3::new(x, -0.4, z),
-                radius: 0.1,
-                material: (i * 20 + j) % 17,
-            }));
+            let center = Vec3::new(x, -0.4, z);
+            let material = (i * 20 + j) % 17;
+            // Some of the spheres bounce or roll while the shutter is
+            // open.
+            if (i + j) % 7 == 0
+            {
+                stuff.push(Arc::new(geometry::MovingSphere {
+                    center: geometry::Keyframes::new(vec![
+                        (0.0, center), (0.5, center + Vec3::new(0.0, 0.2, 0.0)),
+                        (1.0, center)]),
+                    radius: 0.1,
+                    material: material,
+                }));
+            }
+            else if (i * j) % 11 == 1
+            {
+                stuff.push(Arc::new(geometry::MovingSphere {
+                    center: geometry::Keyframes::linear(
+                        0.0, center, 1.0, center + Vec3::new(0.15, 0.0, 0.0)),
+                    radius: 0.1,
+                    material: material,
+                }));
+            }
+            else
+            {
+                stuff.push(Arc::new(geometry::Sphere {
+                    center: center,
+                    radius: 0.1,
+                    material: material,
+                }));
+            }
         }
     }
 
@@ -84,8 +112,12 @@ pub fn buildScene(width: u32, height: u32) -> Scene
         camera: Camera::new(
             camera_pos, camera_lookat, Vec3::new(0.0, 1.0, 0.0), 40.0,
             width as Float / height as Float,
-            0.06, (camera_lookat - camera_pos).norm() - 0.5,),
+            0.06, (camera_lookat - camera_pos).norm() - 0.5,)
+            .withShutter(0.0, 1.0),
         primitives: buildPrimitives(),
         materials: mats,
+        lights: Vec::new(),
+        fog: None,
+        spectral: false,
     }
 }
@@ -0,0 +1,506 @@
+use std::path::Path;
+use std::sync::Arc;
+
+use crate::config::{Float, PI};
+use crate::geometry::{Vec3, Color, Mat4};
+use crate::geometry;
+use crate::geometry::BoundedPrimitive;
+use crate::geometry::PrimitiveList;
+use crate::light;
+use crate::material;
+use crate::medium;
+use crate::texture;
+use crate::scene::Camera;
+use crate::scene::Scene;
+
+/// The height of the floor.
+const FLOOR: Float = -0.5;
+
+/// A unit sphere at the origin, to be placed by instances.
+fn unitSphere(material: usize) -> Arc<dyn BoundedPrimitive + Sync + Send>
+{
+    Arc::new(geometry::Sphere {
+        center: Vec3::new(0.0, 0.0, 0.0),
+        radius: 1.0,
+        material,
+    })
+}
+
+/// A smooth shaded icosahedron with vertices on the sphere at
+/// `center` with `radius`.
+fn icosahedronMesh(center: Vec3, radius: Float, material: usize) -> Arc<geometry::TriangleMesh>
+{
+    let g = (1.0 + (5.0 as Float).sqrt()) * 0.5;
+    let positions: Vec<Vec3> = [
+        (-1.0, g, 0.0), (1.0, g, 0.0), (-1.0, -g, 0.0), (1.0, -g, 0.0),
+        (0.0, -1.0, g), (0.0, 1.0, g), (0.0, -1.0, -g), (0.0, 1.0, -g),
+        (g, 0.0, -1.0), (g, 0.0, 1.0), (-g, 0.0, -1.0), (-g, 0.0, 1.0),
+    ].iter().map(|&(x, y, z)| Vec3::unit(&Vec3::new(x, y, z))).collect();
+    let normals = positions.clone();
+    let positions = positions.iter().map(|&p| center + p * radius).collect();
+    let indices = vec![
+        [0, 11, 5], [0, 5, 1], [0, 1, 7], [0, 7, 10], [0, 10, 11],
+        [1, 5, 9], [5, 11, 4], [11, 10, 2], [10, 7, 6], [7, 1, 8],
+        [3, 9, 4], [3, 4, 2], [3, 2, 6], [3, 6, 8], [3, 8, 9],
+        [4, 9, 5], [2, 4, 11], [6, 2, 10], [8, 6, 7], [9, 8, 1],
+    ];
+    Arc::new(geometry::TriangleMesh::new(positions, indices, material).withNormals(normals))
+}
+
+/// A smooth shaded icosahedron with vertices on the unit sphere.
+fn icosahedron(material: usize) -> Vec<Arc<dyn BoundedPrimitive + Sync + Send>>
+{
+    icosahedronMesh(Vec3::origin(), 1.0, material).triangles()
+}
+
+/// A glowing bulb over the rusty can.
+fn bulb() -> geometry::Sphere
+{
+    geometry::Sphere { center: Vec3::new(-1.35, FLOOR + 0.6, -0.6), radius: 0.05, material: 22 }
+}
+
+/// A cool light panel on the wall.
+fn panel() -> geometry::Quad
+{
+    geometry::Quad {
+        origin: Vec3::new(-2.6, 0.9, -2.48),
+        edge_u: Vec3::new(0.8, 0.0, 0.0),
+        edge_v: Vec3::new(0.0, 0.3, 0.0),
+        material: 23,
+    }
+}
+
+/// A light in the ceiling, out of view.
+fn downlight() -> geometry::Disk
+{
+    geometry::Disk::new(Vec3::new(0.0, 2.4, -0.5), Vec3::new(0.0, -1.0, 0.0), 0.3, 25)
+}
+
+/// A glowing crystal on the floor.
+fn crystal() -> Arc<geometry::TriangleMesh>
+{
+    icosahedronMesh(Vec3::new(0.4, FLOOR + 0.07, 0.55), 0.07, 24)
+}
+
+/// Build the instanced objects around `center`: a ring of squashed
+/// and tilted instances of spheres in 4 materials, and gems scattered
+/// around it.
+fn buildInstances(center: Vec3) -> geometry::TwoLevelBvh
+{
+    let mut accel = geometry::TwoLevelBvh::new();
+    let spheres: Vec<usize> = [1, 6, 7, 8].iter()
+        .map(|&material| accel.addObject(vec![unitSphere(material)])).collect();
+    let spindle = accel.addObject(vec![unitSphere(14)]);
+    let gem = accel.addObject(icosahedron(0));
+
+    let count = 8;
+    for i in 0..count
+    {
+        let angle = 2.0 * PI * i as Float / count as Float;
+        let offset = Vec3::new(angle.cos(), 0.0, angle.sin()) * 0.6;
+        let transform = Mat4::translation(&(center + offset)) *
+            Mat4::rotation(&Vec3::new(0.0, 1.0, 0.0), -angle) *
+            Mat4::rotation(&Vec3::new(1.0, 0.0, 0.0), 0.3) *
+            Mat4::scaling(&Vec3::new(0.08, 0.2, 0.12));
+        accel.addInstance(spheres[i % spheres.len()], transform);
+    }
+    // A long ellipsoid in the middle, pointing up and away.
+    accel.addInstance(spindle, Mat4::translation(&(center + Vec3::new(0.0, 0.1, 0.0))) *
+                      geometry::alignZ(&Vec3::new(0.3, 1.0, -0.3)) *
+                      Mat4::scaling(&Vec3::new(0.1, 0.1, 0.35)));
+
+    let count = 24;
+    for i in 0..count
+    {
+        let angle = 2.0 * PI * (i as Float + 0.5) / count as Float;
+        let radius = 1.0 + 0.3 * (i * 7 % 5) as Float / 5.0;
+        let offset = Vec3::new(angle.cos(), 0.0, angle.sin()) * radius;
+        let spin = Mat4::rotation(&Vec3::new(i as Float, 1.0, 2.0), angle * 3.0);
+        let index = accel.addInstance(gem, Mat4::translation(&(center + offset)) * spin *
+                                      Mat4::scaling(&Vec3::new(0.06, 0.06, 0.06)));
+        // Rest the gem on the floor.
+        let lift = FLOOR - accel.instance(index).bbox().lower[1];
+        let transform = Mat4::translation(&Vec3::new(0.0, lift, 0.0)) *
+            *accel.instance(index).transform();
+        accel.moveInstance(index, transform);
+    }
+    accel.build();
+    accel
+}
+
+/// Return a small sRGB test card: color bars over a gray ramp, with
+/// a white border.
+fn testCard() -> texture::Image
+{
+    let (width, height) = (64, 48);
+    let bars = [(0.75, 0.75, 0.75), (0.75, 0.75, 0.0), (0.0, 0.75, 0.75), (0.0, 0.75, 0.0),
+                (0.75, 0.0, 0.75), (0.75, 0.0, 0.0), (0.0, 0.0, 0.75), (0.1, 0.1, 0.1)];
+    let mut data = Vec::with_capacity(width * height);
+    for y in 0..height
+    {
+        for x in 0..width
+        {
+            let (r, g, b) = if x < 2 || y < 2 || x >= width - 2 || y >= height - 2
+            {
+                (1.0, 1.0, 1.0)
+            }
+            else if y < height * 2 / 3
+            {
+                bars[x * bars.len() / width]
+            }
+            else
+            {
+                let gray = x as Float / (width - 1) as Float;
+                (gray, gray, gray)
+            };
+            data.push(Color::new(texture::srgbToLinear(r), texture::srgbToLinear(g),
+                                 texture::srgbToLinear(b)));
+        }
+    }
+    texture::Image::new(data, width as u32, height as u32)
+}
+
+/// Return a checker image of 2x2 squares, light and dark.
+fn checkerImage() -> texture::Image
+{
+    let size = 64;
+    let data = (0..size * size).map(|i| {
+        if (i % size < size / 2) == (i / size < size / 2) { Color::new(0.7, 0.7, 0.7) }
+        else { Color::new(0.2, 0.2, 0.2) }
+    }).collect();
+    texture::Image::new(data, size as u32, size as u32)
+}
+
+/// Return a tangent space normal map of a round dimple, in linear
+/// colors.
+fn dimpleNormalMap() -> texture::Image
+{
+    let size = 32;
+    let mut data = Vec::with_capacity(size * size);
+    for y in 0..size
+    {
+        for x in 0..size
+        {
+            // The offset from the center in surface coordinates; v
+            // goes up, rows go down.
+            let du = (x as Float + 0.5) / size as Float - 0.5;
+            let dv = 0.5 - (y as Float + 0.5) / size as Float;
+            let r2 = du * du + dv * dv;
+            // The slope of a spherical bowl of radius 0.5, tilting the
+            // normal toward the center.
+            let n = if r2 < 0.2 { Vec3::new(-du, -dv, (0.25 - r2).max(0.0).sqrt()) }
+                    else { Vec3::new(0.0, 0.0, 1.0) };
+            let n = Vec3::unit(&n);
+            data.push(Color::new(0.5 * n[0] + 0.5, 0.5 * n[1] + 0.5, 0.5 * n[2] + 0.5));
+        }
+    }
+    texture::Image::new(data, size as u32, size as u32)
+}
+
+/// The box of the flame over the cone.
+fn flameBox() -> geometry::BBox
+{
+    geometry::BBox {
+        lower: Vec3::new(1.58, FLOOR + 0.5, -1.92),
+        higher: Vec3::new(1.82, FLOOR + 1.0, -1.68),
+    }
+}
+
+/// Return voxel grids of the density and the temperature of a candle
+/// flame: a teardrop, hottest at the bottom.
+fn flameGrids() -> (medium::VoxelGrid, medium::VoxelGrid)
+{
+    let (nx, ny, nz) = (16, 32, 16);
+    let mut density = Vec::with_capacity(nx * ny * nz);
+    let mut temperature = Vec::with_capacity(nx * ny * nz);
+    for z in 0..nz
+    {
+        for y in 0..ny
+        {
+            for x in 0..nx
+            {
+                let coord = |i: usize, n: usize| (i as Float + 0.5) / n as Float;
+                let (u, v, w) = (coord(x, nx) - 0.5, coord(y, ny), coord(z, nz) - 0.5);
+                // Round at the bottom, pointed at the top.
+                let radius = 0.45 * (4.0 * v).min(1.0).sqrt() * (1.0 - v).powf(0.8);
+                let d = (1.0 - (u * u + w * w).sqrt() / radius.max(1e-3)).clamp(0.0, 1.0);
+                density.push(d);
+                temperature.push(d * (1.0 - v));
+            }
+        }
+    }
+    (medium::VoxelGrid::new(nx, ny, nz, density, flameBox()),
+     medium::VoxelGrid::new(nx, ny, nz, temperature, flameBox()))
+}
+
+/// Return the image at `path` through `cache`, or the test card
+/// without a path.
+fn loadPicture(path: Option<&Path>, cache: &texture::ImageCache) -> Arc<texture::Image>
+{
+    match path
+    {
+        Some(path) => cache.load(path, texture::ColorSpace::Srgb).unwrap_or_else(
+            |e| panic!("Failed to load {}: {}", path.display(), e)),
+        None => Arc::new(testCard()),
+    }
+}
+
+/// Add a still life of the analytic shapes on both sides of the
+/// instances, in front of a wall.
+fn addShapes(stuff: &mut Vec<Arc<dyn BoundedPrimitive + Sync + Send>>, picture_aspect: Float)
+{
+    let up = Vec3::new(0.0, 1.0, 0.0);
+    stuff.push(Arc::new(geometry::Quad {
+        origin: Vec3::new(-4.0, FLOOR, -2.5),
+        edge_u: Vec3::new(8.0, 0.0, 0.0),
+        edge_v: Vec3::new(0.0, 2.5, 0.0),
+        material: 3,
+    }));
+    // A picture on the wall.
+    let picture_height = 0.8;
+    let picture_width = picture_height * picture_aspect;
+    stuff.push(Arc::new(geometry::Quad {
+        origin: Vec3::new(-0.5 * picture_width, 0.2, -2.49),
+        edge_u: Vec3::new(picture_width, 0.0, 0.0),
+        edge_v: Vec3::new(0.0, picture_height, 0.0),
+        material: 4,
+    }));
+    stuff.push(Arc::new(geometry::AxisBox {
+        lower: Vec3::new(-2.0, FLOOR, -1.7),
+        higher: Vec3::new(-1.5, FLOOR + 0.45, -1.2),
+        material: 5,
+    }));
+    stuff.push(Arc::new(geometry::Cylinder::new(
+        Vec3::new(-1.35, FLOOR, -0.6), up, 0.15, 0.4, true, 10)));
+    // A side table: an open cylinder with a disk on top.
+    stuff.push(Arc::new(geometry::Cylinder::new(
+        Vec3::new(1.3, FLOOR, 0.2), up, 0.06, 0.3, false, 12)));
+    stuff.push(Arc::new(geometry::Disk::new(
+        Vec3::new(1.3, FLOOR + 0.3, 0.2), up, 0.25, 11)));
+    stuff.push(Arc::new(geometry::Cone::new(
+        Vec3::new(1.7, FLOOR, -1.8), up, 0.22, 0.55, true, 9)));
+    stuff.push(Arc::new(geometry::Torus::new(
+        Vec3::new(1.55, FLOOR + 0.27, -0.75), Vec3::new(0.4, 0.0, 1.0), 0.2, 0.07, 13)));
+    // A lace screen, with holes where cellular noise is dark.
+    let lace = texture::NoiseTexture::new(
+        9, texture::Cellular::F1, Arc::new(texture::Constant::scalar(0.0)),
+        Arc::new(texture::Constant::scalar(1.0))).withScale(14.0);
+    stuff.push(Arc::new(geometry::AlphaMasked::new(Arc::new(geometry::Quad {
+        origin: Vec3::new(-1.6, FLOOR, 0.5),
+        edge_u: Vec3::new(0.4, 0.0, -0.8),
+        edge_v: Vec3::new(0.0, 0.9, 0.0),
+        material: 17,
+    }), Arc::new(lace)).withThreshold(0.6)));
+    // A puff of smoke above the table.
+    stuff.push(Arc::new(geometry::Sphere {
+        center: Vec3::new(1.3, FLOOR + 0.5, 0.2), radius: 0.16, material: 18 }));
+    // A wax egg.
+    stuff.push(Arc::new(geometry::Sphere {
+        center: Vec3::new(0.05, FLOOR + 0.12, 0.6), radius: 0.12, material: 20 }));
+    // A soap bubble.
+    stuff.push(Arc::new(geometry::Sphere {
+        center: Vec3::new(-0.45, FLOOR + 0.6, 0.25), radius: 0.14, material: 21 }));
+    // Marbles of clear and of frosted glass in front.
+    stuff.push(Arc::new(geometry::Sphere {
+        center: Vec3::new(-0.75, FLOOR + 0.18, 0.45), radius: 0.18, material: 15 }));
+    stuff.push(Arc::new(geometry::Sphere {
+        center: Vec3::new(0.7, FLOOR + 0.15, 0.5), radius: 0.15, material: 16 }));
+}
+
+/// Build the primitives, with the `instances`, and the surfaces of
+/// the area lights and of the flame in `glowing`.
+fn buildPrimitives(picture_aspect: Float, instances: Arc<geometry::TwoLevelBvh>,
+                   glowing: Vec<Arc<dyn BoundedPrimitive + Sync + Send>>) -> PrimitiveList
+{
+    let mut stuff: Vec<Arc<dyn BoundedPrimitive + Sync + Send>> = vec![instances];
+    addShapes(&mut stuff, picture_aspect);
+    stuff.extend(glowing);
+
+    PrimitiveList::new(stuff, vec![
+        Arc::new(geometry::InfinitePlane {
+            origin: Vec3::new(0.0, FLOOR, 0.0),
+            normal: Vec3::new(0.0, 1.0, 0.0),
+            material: 2,
+        }),])
+}
+
+/// A scene with the primitives, materials and lights beyond spheres
+/// and planes. The image at `picture` hangs on the wall, and covers a
+/// box. The checkered floor is filtered by `floor_filter`, e.g.
+/// `Nearest` to compare against point sampling. Also return the
+/// instances, whose BVHs tell how they were built.
+pub fn buildScene(width: u32, height: u32, picture: Option<&Path>, floor_filter: texture::Filter)
+    -> (Scene, Arc<geometry::TwoLevelBvh>)
+{
+    // The picture and the box use the same image file, which the
+    // cache loads once.
+    let cache = texture::ImageCache::new();
+    let image = loadPicture(picture, &cache);
+    let picture_aspect = image.width() as Float / image.height() as Float;
+
+    let camera_pos = Vec3::new(0.0, 0.8, 2.5);
+    let camera_lookat = Vec3::new(0.0, -0.2, -1.0);
+
+    let constant = |r, g, b| -> texture::AnyTexture {
+        Arc::new(texture::Constant::new(Color::new(r, g, b)))
+    };
+    let scalar = |x| -> texture::AnyTexture { Arc::new(texture::Constant::scalar(x)) };
+    // Plaster with faint cracks where cellular noise has cell borders.
+    let plaster: texture::AnyTexture = Arc::new(texture::NoiseTexture::new(
+        2, texture::Fbm, constant(0.45, 0.45, 0.5), constant(0.6, 0.6, 0.62))
+        .withScale(8.0).withOctaves(4).withBasis(texture::NoiseBasis::Simplex));
+    let cells = texture::Grayscale::new(Arc::new(texture::NoiseTexture::new(
+        8, texture::Cellular::F2MinusF1, constant(0.0, 0.0, 0.0), constant(1.0, 0.5, 0.0))
+        .withScale(3.0)), texture::Channel::Red);
+    let cracks: texture::AnyTexture = Arc::new(texture::Clamp::new(
+        Arc::new(texture::Multiply::new(Arc::new(cells), scalar(20.0))), 0.7, 1.0));
+    let picture_tiles = texture::UVTransform::new(
+        Arc::new(texture::ImageTexture::new(loadPicture(picture, &cache))
+                 .withWrap(texture::WrapMode::Mirror)))
+        .withScale(2.0, 2.0).withRotation(PI / 12.0).withOffset(0.25, 0.0);
+
+    let mut texs: Vec<Arc<dyn texture::Texture + Sync + Send>> = vec![
+        Arc::new(texture::NoiseTexture::new(
+            1, texture::Marble::new(),
+            constant(0.8, 0.8, 0.78), constant(0.15, 0.15, 0.2))
+                 .withScale(6.0).withOctaves(6)),
+        Arc::new(texture::ImageTexture::new(Arc::new(checkerImage()))
+                 .withFilter(floor_filter)),
+        Arc::new(texture::Multiply::new(plaster, cracks.clone())),
+        Arc::new(texture::ImageTexture::new(image.clone()).withWrap(texture::WrapMode::Clamp)),
+        Arc::new(picture_tiles),
+        Arc::new(texture::NoiseTexture::new(
+            3, texture::Wood::new(),
+            constant(0.6, 0.4, 0.2), constant(0.3, 0.15, 0.05)).withScale(4.0)),
+        Arc::new(texture::NoiseTexture::new(
+            4, texture::Cellular::F1, constant(0.1, 0.2, 0.5), constant(0.6, 0.8, 0.9))
+                 .withScale(20.0)),
+        // Scales.
+        Arc::new(texture::NoiseTexture::new(
+            5, texture::Cellular::F2MinusF1, constant(0.05, 0.1, 0.05),
+            constant(0.3, 0.6, 0.2)).withScale(25.0).withOctaves(2)),
+        Arc::new(texture::NoiseTexture::new(
+            6, texture::Turbulence, constant(0.7, 0.2, 0.05), constant(0.95, 0.8, 0.3))
+                 .withScale(5.0).withOctaves(6)),
+    ];
+    // Rust, in patches on steel where a noise is above a threshold.
+    let rust_noise: texture::AnyTexture = Arc::new(texture::NoiseTexture::new(
+        7, texture::Fbm, constant(0.0, 0.0, 0.0), constant(1.0, 1.0, 1.0))
+        .withScale(6.0).withOctaves(5));
+    let rust = texture::ColorRamp::new(Arc::new(texture::Invert::new(rust_noise.clone())), vec![
+        (0.3, Color::new(0.15, 0.05, 0.02)), (0.5, Color::new(0.5, 0.2, 0.05)),
+        (0.7, Color::new(0.35, 0.15, 0.05))]).withChannel(texture::Channel::Average);
+    let rust_cover: texture::AnyTexture = Arc::new(texture::Clamp::new(Arc::new(
+        texture::Multiply::new(Arc::new(texture::Add::new(rust_noise, scalar(-0.55))),
+                               scalar(6.0))), 0.0, 1.0));
+    texs.push(Arc::new(rust));
+    // The table has no useful surface coordinates: the picture on top,
+    // and the floor pattern on the sides.
+    texs.push(Arc::new(texture::Triplanar::perAxis(
+        texs[1].clone(), Arc::new(texture::ImageTexture::new(image)), texs[1].clone())
+                       .withScale(0.5)));
+    texs.push(Arc::new(texture::Triplanar::new(texs[1].clone()).withScale(0.1)
+                       .withSharpness(8.0)));
+
+    // A candle flame on the cone, glowing where it absorbs.
+    let (density, temperature) = flameGrids();
+    let flame = Arc::new(medium::Heterogeneous::new(Arc::new(density), 60.0,
+                                                    Color::new(0.1, 0.1, 0.1))
+                         .withEmission(Arc::new(temperature), Color::new(12.0, 5.0, 1.2)));
+    let flame_box = flame.boundary(19);
+
+    let mats: Vec<Arc<dyn material::Material + Sync + Send>> = vec![
+        Arc::new(material::Conductor::new(material::GOLD, 0.2)), // Gems
+        Arc::new(material::Lambertian { albedo: texs[0].clone() }),
+        Arc::new(material::Lambertian { albedo: texs[1].clone() }), // Floor
+        // The cracks are also carved into the wall.
+        Arc::new(material::BumpMapped::new(
+            Arc::new(material::Lambertian { albedo: texs[2].clone() }), cracks, 0.02)),
+        Arc::new(material::Lambertian { albedo: texs[3].clone() }), // Picture
+        Arc::new(material::Lambertian { albedo: texs[4].clone() }),
+        // Varnished wood.
+        Arc::new(material::Layered::new(
+            Arc::new(material::Lambertian { albedo: texs[5].clone() }), 1.5)
+                 .withColor(Color::new(0.95, 0.85, 0.6))),
+        // Blue velvet.
+        Arc::new(material::Principled::new(texs[6].clone())
+                 .withRoughness(scalar(0.9)).withSheen(scalar(1.0), scalar(0.3))),
+        Arc::new(material::Lambertian { albedo: texs[7].clone() }),
+        // Lacquered under a clear coat.
+        Arc::new(material::Principled::new(texs[8].clone())
+                 .withClearcoat(scalar(1.0), scalar(0.1))),
+        Arc::new(material::MixMaterial::new(
+            Arc::new(material::Metal { albedo: Vec3::new(0.55, 0.55, 0.57), roughness: 0.3 }),
+            Arc::new(material::Lambertian { albedo: texs[9].clone() }), rust_cover)),
+        Arc::new(material::Lambertian { albedo: texs[10].clone() }), // Table
+        Arc::new(material::Lambertian { albedo: texs[11].clone() }),
+        // Hammered metal, tinted by heat: an oxide film of uneven
+        // thickness.
+        Arc::new(material::NormalMapped::new(
+            Arc::new(material::Conductor::new(material::SILVER, 0.15).withThinFilm(
+                Arc::new(texture::NoiseTexture::new(
+                    11, texture::Fbm, scalar(100.0), scalar(450.0)).withScale(4.0)), 2.2)),
+            Arc::new(texture::UVTransform::new(
+                Arc::new(texture::ImageTexture::new(Arc::new(dimpleNormalMap()))))
+                     .withScale(16.0, 4.0))).withStrength(0.7)),
+        // Copper brushed along the spindle.
+        Arc::new(material::Conductor::new(material::COPPER, 0.3).withAnisotropy(0.6, 0.1)),
+        // Green crown glass, which disperses light, and frosted glass.
+        Arc::new(material::Dielectric::new(1.5).withDispersion(material::Dispersion::BK7)
+                 .withAbsorption(Color::new(0.4, 0.8, 0.5), 0.2)),
+        Arc::new(material::Dielectric::new(1.5).withCauchy(1.5046, 0.0042).withRoughness(0.4)),
+        // The lace is red on one side and cream on the other.
+        Arc::new(material::TwoSided::new(
+            Arc::new(material::Lambertian { albedo: constant(0.6, 0.05, 0.05) }))
+                 .withBack(Arc::new(material::Lambertian { albedo: constant(0.9, 0.85, 0.7) }))),
+        Arc::new(material::MediumBoundary::new(Arc::new(medium::Homogeneous::new(
+            Color::new(0.5, 0.5, 0.5), Color::new(12.0, 12.0, 12.0)).withPhase(0.3)))),
+        Arc::new(material::MediumBoundary::new(flame)),
+        Arc::new(material::Subsurface::new(Color::new(0.9, 0.75, 0.5),
+                                           Color::new(0.04, 0.02, 0.01)).withRoughness(0.3)),
+        // Soap swirls between 200 and 800 nm thick.
+        Arc::new(material::Dielectric::new(1.0).withThinWalls().withThinFilm(
+            Arc::new(texture::NoiseTexture::new(
+                10, texture::Fbm, constant(200.0, 200.0, 200.0), constant(800.0, 800.0, 800.0))
+                     .withScale(8.0).withOctaves(3)), 1.33)),
+        Arc::new(material::DiffuseLight::new(constant(6.0, 4.5, 2.5))), // Bulb
+        Arc::new(material::DiffuseLight::new(constant(1.2, 1.5, 1.8))), // Panel
+        Arc::new(material::DiffuseLight::new(constant(3.0, 0.6, 2.4))), // Crystal
+        Arc::new(material::DiffuseLight::new(constant(1.5, 1.5, 1.4))), // Ceiling
+    ];
+
+    // A spot on the picture, the glow of the candle, the sun, slightly
+    // soft, and the glowing shapes.
+    let mut lights: Vec<light::AnyLight> = vec![
+        Arc::new(light::SpotLight::new(
+            Vec3::new(0.0, 1.8, -0.8), Vec3::new(0.0, -1.2, -1.7), Color::new(3.0, 3.0, 2.8),
+            25.0).withFalloff(15.0)),
+        Arc::new(light::PointLight::new(Vec3::new(1.7, FLOOR + 0.62, -1.8), Color::new(0.15, 0.08, 0.02))),
+        Arc::new(light::DirectionalLight::new(
+            Vec3::new(-0.5, -1.0, -0.4), Color::new(1.2, 1.1, 1.0))
+                 .withAngularDiameter(2.0)),
+    ];
+    let mut glowing: Vec<Arc<dyn BoundedPrimitive + Sync + Send>> = vec![
+        Arc::new(flame_box),
+        light::AreaLight::add(Arc::new(bulb()), &mats, &mut lights),
+        light::AreaLight::add(Arc::new(panel()), &mats, &mut lights),
+        light::AreaLight::add(Arc::new(downlight()), &mats, &mut lights),
+    ];
+    glowing.extend(light::AreaLight::addMesh(&crystal(), &mats, &mut lights));
+
+    let instances = Arc::new(buildInstances(Vec3::new(0.0, -0.3, -1.0)));
+    let scene = Scene {
+        width, height,
+        camera: Camera::new(
+            camera_pos, camera_lookat, Vec3::new(0.0, 1.0, 0.0), 40.0,
+            width as Float / height as Float,
+            0.0, (camera_lookat - camera_pos).norm()),
+        primitives: buildPrimitives(picture_aspect, instances.clone(), glowing),
+        materials: mats,
+        lights,
+        fog: None,
+        // For the dispersion of the glass.
+        spectral: true,
+    };
+    (scene, instances)
+}
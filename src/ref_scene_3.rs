@@ -0,0 +1,134 @@
+use std::path::Path;
+use std::sync::Arc;
+
+use crate::config::Float;
+use crate::geometry::{Vec3, Color};
+use crate::geometry;
+use crate::geometry::BoundedPrimitive;
+use crate::geometry::PrimitiveList;
+use crate::light;
+use crate::material;
+use crate::texture;
+use crate::scene::Camera;
+use crate::scene::Scene;
+
+/// A photo studio: a dim backdrop, darker toward the floor, with a
+/// large softbox on the left and a small one on the right. Top of the
+/// image is up.
+fn studioImage() -> texture::Image
+{
+    let (width, height) = (256, 128);
+    let mut data = Vec::with_capacity((width * height) as usize);
+    for y in 0..height
+    {
+        let fy = (y as Float + 0.5) / height as Float;
+        for x in 0..width
+        {
+            let fx = (x as Float + 0.5) / width as Float;
+            let softbox = |x0: Float, x1: Float, y0: Float, y1: Float|
+                x0 <= fx && fx < x1 && y0 <= fy && fy < y1;
+            data.push(if softbox(0.3, 0.4, 0.2, 0.35)
+            {
+                Color::new(12.0, 12.0, 11.0)
+            }
+            else if softbox(0.75, 0.78, 0.3, 0.36)
+            {
+                Color::new(20.0, 22.0, 25.0)
+            }
+            else
+            {
+                Color::new(0.3, 0.3, 0.32) * (1.0 - fy)
+            });
+        }
+    }
+    texture::Image::new(data, width, height)
+}
+
+/// Return the environment: the image file at `path`, e.g. an `.hdr`
+/// or `.exr` panorama, or the studio.
+fn loadEnvironment(path: Option<&Path>) -> light::EnvironmentLight
+{
+    match path
+    {
+        Some(path) => light::EnvironmentLight::load(path).unwrap_or_else(
+            |e| panic!("Failed to load {}: {}", path.display(), e)),
+        None => light::EnvironmentLight::new(Arc::new(studioImage()))
+            .withRotation(30.0).withScale(1.5),
+    }
+}
+
+fn buildPrimitives() -> PrimitiveList
+{
+    let stuff: Vec<Arc<dyn BoundedPrimitive + Sync + Send>> = vec![
+        Arc::new(geometry::Sphere { center: Vec3::new(-1.1, 0.0, -1.0), radius: 0.5, material: 1 }),
+        Arc::new(geometry::Sphere { center: Vec3::new(0.0, 0.0, -1.0), radius: 0.5, material: 2 }),
+        Arc::new(geometry::Sphere { center: Vec3::new(1.1, 0.0, -1.0), radius: 0.5, material: 3 }),
+        Arc::new(geometry::Sphere { center: Vec3::new(-0.5, -0.3, 0.0), radius: 0.2, material: 4 }),
+        Arc::new(geometry::Sphere { center: Vec3::new(0.5, -0.3, 0.0), radius: 0.2, material: 5 }),
+    ];
+
+    PrimitiveList::new(stuff, vec![
+        Arc::new(geometry::InfinitePlane {
+            origin: Vec3::new(0.0, -0.5, 0.0),
+            normal: Vec3::new(0.0, 1.0, 0.0),
+            material: 0,
+        }),])
+}
+
+/// Spheres of a few materials on a floor, lit only by their
+/// surroundings: the image file at `environment` or a studio.
+pub fn buildScene(width: u32, height: u32, environment: Option<&Path>) -> Scene
+{
+    buildSceneLitBy(width, height, vec![Arc::new(loadEnvironment(environment))])
+}
+
+/// The spheres outdoors, on a late summer afternoon. The light is
+/// brightened a little for the low sun.
+pub fn buildSkyScene(width: u32, height: u32) -> Scene
+{
+    let sky = light::PhysicalSky::at(172, 17.5, 48.0).withTurbidity(4.0)
+        .withGroundAlbedo(Color::new(0.2, 0.25, 0.15)).withScale(1.3);
+    buildSceneLitBy(width, height, sky.lights())
+}
+
+fn buildSceneLitBy(width: u32, height: u32, lights: Vec<light::AnyLight>) -> Scene
+{
+    let camera_pos = Vec3::new(0.0, 0.6, 2.5);
+    let camera_lookat = Vec3::new(0.0, -0.1, -0.8);
+
+    let constant = |r, g, b| -> texture::AnyTexture {
+        Arc::new(texture::Constant::new(Color::new(r, g, b)))
+    };
+    let scalar = |x| -> texture::AnyTexture { Arc::new(texture::Constant::scalar(x)) };
+
+    let mats: Vec<Arc<dyn material::Material + Sync + Send>> = vec![
+        Arc::new(material::Lambertian { albedo: Arc::new(texture::Checker::new(
+            constant(0.6, 0.6, 0.6), constant(0.3, 0.3, 0.3))) }), // Floor
+        Arc::new(material::Conductor::new(material::ALUMINIUM, 0.05)),
+        // Fused silica.
+        Arc::new(material::Dielectric::new(1.5).withSellmeier(
+            [0.6961663, 0.4079426, 0.8974794], [0.004679148, 0.01351206, 97.934])),
+        // Metallic red paint under a clear coat.
+        Arc::new(material::Principled::new(constant(0.6, 0.05, 0.05))
+                 .withMetallic(scalar(0.5)).withRoughness(scalar(0.35))
+                 .withClearcoat(scalar(1.0), scalar(0.05))),
+        Arc::new(material::Conductor::new(material::GOLD, 0.3)),
+        // Frosted, mostly clear plastic.
+        Arc::new(material::Principled::new(constant(0.8, 0.85, 0.9))
+                 .withRoughness(scalar(0.3)).withSpecular(scalar(0.6))
+                 .withTransmission(scalar(0.9), 1.45)),
+    ];
+
+    Scene {
+        width, height,
+        camera: Camera::new(
+            camera_pos, camera_lookat, Vec3::new(0.0, 1.0, 0.0), 40.0,
+            width as Float / height as Float,
+            0.0, (camera_lookat - camera_pos).norm()),
+        primitives: buildPrimitives(),
+        materials: mats,
+        lights,
+        fog: None,
+        spectral: false,
+    }
+}
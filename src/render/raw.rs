@@ -1,3 +1,5 @@
+#![allow(clippy::redundant_field_names)]
+
 use std::ops::{Index, IndexMut};
 
 use crate::geometry::Color;
@@ -128,6 +130,8 @@ impl RawImageView
 
     const fn xy2Index(&self, x: u32, y: u32) -> usize
     {
+        debug_assert!(x + self.offset_x < self.orig_width &&
+                      y + self.offset_y < self.orig_height);
         ((y + self.offset_y) * self.orig_width + x + self.offset_x) as usize
     }
 
@@ -1,3 +1,5 @@
+#![allow(clippy::redundant_field_names, clippy::manual_is_multiple_of)]
+
 use super::{RawImage, RawImageView};
 
 pub struct TiledCanvas<'a>
@@ -3,43 +3,312 @@ use rayon;
 
 use crate::geometry::{Vec3, Color};
 use crate::config::Float;
-use crate::geometry::Ray;
+use crate::geometry::vec3;
+use crate::geometry::{Ray, Hit};
+use crate::medium::{AnyMedium, MediumEvent};
+use crate::light::{LightSample, LightBvh};
 use crate::scene::Scene;
+use crate::spectrum::{self, Wavelengths};
 use crate::geometry::Primitive;
-use super::raw::{RawImage, RawImageView};
-use super::tile::TiledCanvas;
+use super::{RawImage, RawImageView, TiledCanvas};
 
-use crate::ref_scene_1;
+/// The maximum number of bounces of a path off surfaces.
+const MAX_DEPTH: u32 = 32;
 
-fn renderRay(r: &Ray, scene: &Scene, count: u32) -> Color
+/// The maximum number of scattering events of a path in media,
+/// including hitting the boundaries of media. Dense media (e.g.
+/// subsurface scattering) need many.
+const MAX_VOLUME_EVENTS: u32 = 1024;
+
+/// How far rays go.
+const FAR: Float = 1000.0;
+
+/// How far rays that escape the scene go through the medium they are
+/// in, in world units, before they reach the background.
+const FOG_DISTANCE: Float = 100.0;
+
+fn background(r: &Ray) -> Color
+{
+    let t = (Vec3::unit(&r.dir)[1] + 1.0) * 0.5;
+    (1.0 - t) * Vec3::new(1.0, 1.0, 1.0) + t * Vec3::new(0.5, 0.7, 1.0)
+}
+
+/// Pick one of the lights of `scene` with `bvh`, and sample the
+/// light from it arriving at `p`. The radiance is over the
+/// probability of picking the light.
+fn sampleLight(scene: &Scene, bvh: &LightBvh, p: &Vec3) -> Option<LightSample>
+{
+    let (i, pmf) = bvh.sample(p)?;
+    let mut sample = scene.lights[i].sample(p)?;
+    sample.radiance /= pmf;
+    sample.pdf *= pmf;
+    Some(sample)
+}
+
+/// Return the probability density per solid angle of `sampleLight`
+/// at `p` choosing unit direction `dir`, which hits the surface at
+/// `hit`. It is 0 unless the surface is that of a light.
+fn lightPdf(scene: &Scene, bvh: &LightBvh, p: &Vec3, dir: &Vec3, hit: &Hit) -> Float
 {
-    if count > 32
+    match hit.light
     {
-        return Color::origin();
+        Some(i) => scene.lights[i].pdf(p, dir) * bvh.pmf(p, i),
+        None => 0.0,
     }
+}
 
-    // Set min hit distance to some small number to address the
-    // surface acne problem.
-    if let Some(hit) = scene.primitives.intersect(r, 0.0001, 1000.0)
+/// Return the radiance that ray `r` sees escaping `scene`, from the
+/// lights around it, or from the background if there are none. If
+/// the ray was scattered at `last_scatter`, lights could have been
+/// sampled there instead.
+fn escaped(scene: &Scene, bvh: &LightBvh, r: &Ray,
+           last_scatter: Option<(Vec3, Float)>) -> Color
+{
+    let dir = Vec3::unit(&r.dir);
+    let mut result = Color::origin();
+    let mut environment = false;
+    for (i, light) in scene.lights.iter().enumerate()
     {
-        if let Some((scattered, att)) = scene.materials[hit.material].scatter(r, &hit)
+        if let Some(l) = light.environment(&dir)
         {
-            return att * renderRay(&scattered, scene, count+1);
+            environment = true;
+            let weight = match last_scatter
+            {
+                Some((p, pdf)) =>
+                    misWeight(pdf, light.pdf(&p, &dir) * bvh.pmf(&p, i)),
+                None => 1.0,
+            };
+            result += l * weight;
         }
-        else
+    }
+    if environment { result } else { background(r) }
+}
+
+/// The weight of a sample from a strategy with density `pdf`, when
+/// another strategy with density `other_pdf` could also have taken
+/// it (the power heuristic of multiple importance sampling).
+fn misWeight(pdf: Float, other_pdf: Float) -> Float
+{
+    if pdf.is_infinite()
+    {
+        return 1.0;
+    }
+    let a = pdf * pdf;
+    let b = other_pdf * other_pdf;
+    if a + b > 0.0 { a / (a + b) } else { 0.0 }
+}
+
+/// Return the fraction of light that travels along `shadow` (with a
+/// unit direction) for `distance`, starting in `medium`. Surfaces
+/// block the light, except invisible ones that bound media. The
+/// light itself may be a surface, so the shadow ray stops just short
+/// of it. In spectral mode, this is per wavelength of `shadow`.
+fn visibility(scene: &Scene, shadow: &Ray, distance: Float,
+              medium: Option<&AnyMedium>) -> Color
+{
+    let mut ray = shadow.clone();
+    let mut medium = medium;
+    let mut remaining = distance * (1.0 - 1e-3);
+    let mut transmittance = Color::new(1.0, 1.0, 1.0);
+    for _ in 0..MAX_VOLUME_EVENTS
+    {
+        let hit = scene.primitives.intersect(&ray, 0.0001, remaining.min(FAR));
+        if let Some(m) = medium
+        {
+            let t = hit.as_ref().map_or(remaining.min(FOG_DISTANCE), |h| h.t);
+            transmittance *= m.transmittance(&ray, t);
+        }
+        let hit = match hit
+        {
+            Some(hit) => hit,
+            None => return transmittance,
+        };
+        let material = &scene.materials[hit.material];
+        if !material.isInvisible()
         {
             return Color::origin();
         }
+        medium = if vec3::dot(&ray.dir, &hit.normal) < 0.0
+        {
+            material.interior()
+        }
+        else
+        {
+            scene.fog.as_ref()
+        };
+        remaining -= hit.t;
+        let wavelengths = ray.wavelengths;
+        ray = hit.spawnRay(ray.dir, ray.time);
+        ray.wavelengths = wavelengths;
     }
+    Color::origin()
+}
 
-    // Background
-    let t = (Vec3::unit(&r.dir)[1] + 1.0) * 0.5;
-    let c = (1.0 - t) * Vec3::new(1.0, 1.0, 1.0) + t * Vec3::new(0.5, 0.7, 1.0);
-    c
+fn renderRay(r: &Ray, scene: &Scene, bvh: &LightBvh) -> Color
+{
+    // In spectral mode, colors along the path are the values at the
+    // sampled wavelengths, and are only converted to RGB at the end.
+    let mut wavelengths = if scene.spectral
+    {
+        Some(Wavelengths::sample(rand::random::<Float>()))
+    }
+    else
+    {
+        None
+    };
+    let toPath = |c: Color, w: &Option<Wavelengths>| match w
+    {
+        Some(w) => spectrum::upsample(&c, w),
+        None => c,
+    };
+    let toRgb = |c: Color, w: &Option<Wavelengths>| match w
+    {
+        Some(w) => spectrum::spectrumToRgb(&c, w),
+        None => c,
+    };
+
+    let mut ray = r.clone();
+    let mut throughput = Color::new(1.0, 1.0, 1.0);
+    let mut radiance = Color::origin();
+    // The medium the ray is currently traveling in.
+    let mut medium: Option<&AnyMedium> = scene.fog.as_ref();
+    // Where the ray was last scattered, and the density of its
+    // direction, if lights could have been sampled there instead.
+    let mut last_scatter: Option<(Vec3, Float)> = None;
+
+    let mut depth = 0;
+    let mut volume_events = 0;
+    while depth < MAX_DEPTH && volume_events < MAX_VOLUME_EVENTS
+    {
+        ray.wavelengths = wavelengths;
+        // Set min hit distance to some small number to address the
+        // surface acne problem.
+        let hit = scene.primitives.intersect(&ray, 0.0001, FAR);
+        if let Some(m) = medium
+        {
+            let t_max = hit.as_ref().map_or(FOG_DISTANCE / ray.dir.norm(), |h| h.t);
+            match m.sample(&ray, t_max, &throughput)
+            {
+                MediumEvent::Scatter { t, weight, emitted } =>
+                {
+                    // Media already work per wavelength.
+                    radiance += throughput * emitted;
+                    throughput *= weight;
+                    let p = ray.at(t);
+                    let phase = m.phase(&p);
+                    let dir_in = Vec3::unit(&ray.dir);
+                    if let Some(light) = sampleLight(scene, bvh, &p)
+                    {
+                        let f = phase.eval(vec3::dot(&dir_in, &light.dir));
+                        let mut shadow = Ray::new(p, light.dir, ray.time);
+                        shadow.wavelengths = wavelengths;
+                        let v = visibility(scene, &shadow, light.distance, medium);
+                        radiance += throughput * (f * misWeight(light.pdf, f)) *
+                            toPath(light.radiance, &wavelengths) * v;
+                    }
+                    let dir = phase.sample(&dir_in);
+                    last_scatter = Some((p, phase.eval(vec3::dot(&dir_in, &dir))));
+                    ray = Ray::new(p, dir, ray.time);
+                    volume_events += 1;
+                    continue;
+                },
+                MediumEvent::Pass { weight } => throughput *= weight,
+            }
+        }
+
+        let hit = match hit
+        {
+            Some(hit) => hit,
+            None =>
+            {
+                let escaped = escaped(scene, bvh, &ray, last_scatter);
+                radiance += throughput * toPath(escaped, &wavelengths);
+                return toRgb(radiance, &wavelengths);
+            },
+        };
+        let material = &scene.materials[hit.material];
+
+        let emitted = material.emitted(&ray, &hit);
+        if emitted.normSquared() > 0.0
+        {
+            // The light may also have been sampled directly at the
+            // last scatter.
+            let weight = match last_scatter
+            {
+                Some((p, pdf)) =>
+                    misWeight(pdf, lightPdf(scene, bvh, &p, &Vec3::unit(&ray.dir), &hit)),
+                None => 1.0,
+            };
+            radiance += throughput * toPath(emitted, &wavelengths) * weight;
+        }
+
+        // Light arriving straight from a light source.
+        if let Some(light) = sampleLight(scene, bvh, &hit.p)
+        {
+            let f = material.eval(&ray, &hit, &light.dir);
+            if f.normSquared() > 0.0
+            {
+                let weight = misWeight(light.pdf, material.pdf(&ray, &hit, &light.dir));
+                // The shadow ray may go through the surface into its
+                // medium.
+                let shadow_medium = match material.interior()
+                {
+                    Some(interior) if vec3::dot(&light.dir, &hit.normal) < 0.0 =>
+                        Some(interior),
+                    Some(_) => scene.fog.as_ref(),
+                    None => medium,
+                };
+                let mut shadow = hit.spawnRay(light.dir, ray.time);
+                shadow.wavelengths = wavelengths;
+                let v = visibility(scene, &shadow, light.distance, shadow_medium);
+                radiance += throughput * toPath(f * weight, &wavelengths) *
+                    toPath(light.radiance, &wavelengths) * v;
+            }
+        }
+
+        let (scattered, att) = match material.scatter(&ray, &hit)
+        {
+            Some(result) => result,
+            None => return toRgb(radiance, &wavelengths),
+        };
+
+        // Going through a surface with a medium inside, the ray
+        // enters or leaves the medium.
+        if let Some(interior) = material.interior()
+        {
+            let incoming = vec3::dot(&ray.dir, &hit.normal);
+            let outgoing = vec3::dot(&scattered.dir, &hit.normal);
+            if incoming * outgoing > 0.0
+            {
+                medium = if outgoing < 0.0 { Some(interior) } else { scene.fog.as_ref() };
+            }
+            volume_events += 1;
+        }
+        else
+        {
+            depth += 1;
+        }
+        // Invisible surfaces leave the direction as it was.
+        if !material.isInvisible()
+        {
+            let pdf = material.pdf(&ray, &hit, &Vec3::unit(&scattered.dir));
+            last_scatter = if pdf > 0.0 { Some((hit.p, pdf)) } else { None };
+        }
+        throughput *= toPath(att, &wavelengths);
+        if scattered.wavelengths.is_some()
+        {
+            wavelengths = scattered.wavelengths;
+        }
+        ray = scattered;
+    }
+    toRgb(radiance, &wavelengths)
 }
 
-fn renderTile(scene: &Scene, ns: u32, tile: &mut RawImageView)
+fn renderTile(scene: &Scene, bvh: &LightBvh, ns: u32, tile: &mut RawImageView)
 {
+    // With many samples per pixel, each sample only needs to cover
+    // part of the pixel.
+    let footprint = (1.0 / (ns as Float).sqrt()).max(0.125);
     for dy in 0..tile.height
     {
         for dx in 0..tile.width
@@ -54,8 +323,10 @@ fn renderTile(scene: &Scene, ns: u32, tile: &mut RawImageView)
                 let v: Float = ((scene.height - y - 1) as Float + rand::random::<Float>()) /
                     scene.height as Float;
 
-                let r = scene.camera.ray(u, v);
-                col += renderRay(&r, &scene, 0);
+                let r = scene.camera.rayWithDifferentials(
+                    u, v, footprint / scene.width as Float,
+                    footprint / scene.height as Float);
+                col += renderRay(&r, scene, bvh);
             }
             col /= ns as Float;
             tile.set(dx, dy, col);
@@ -63,11 +334,12 @@ fn renderTile(scene: &Scene, ns: u32, tile: &mut RawImageView)
     }
 }
 
-pub fn render() -> RawImage
+pub fn render(scene: Scene) -> RawImage
 {
-    let width = 800;
-    let height = 500;
-    let scene = Arc::new(ref_scene_1::buildScene(width, height));
+    let width = scene.width;
+    let height = scene.height;
+    let scene = Arc::new(scene);
+    let bvh = Arc::new(LightBvh::new(&scene.lights));
 
     // Signal to noise ratio, in some arbitrary scale.
     let snr_index: u32 = 10;
@@ -79,15 +351,14 @@ pub fn render() -> RawImage
     let mut canvas = TiledCanvas::new(&mut img, 64);
     let tiles = canvas.tiles();
     pool.scope(|s| {
-        let mut i = 0;
-        for mut tile in tiles
+        for (i, mut tile) in tiles.into_iter().enumerate()
         {
             let scene = scene.clone();
+            let bvh = bvh.clone();
             s.spawn(move |_| {
                 println!("Rendering tile {:?}...", i);
-                renderTile(&scene, ns, &mut tile);
+                renderTile(&scene, &bvh, ns, &mut tile);
             });
-            i += 1;
         }
     });
     img
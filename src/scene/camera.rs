@@ -1,7 +1,10 @@
+#![allow(clippy::redundant_field_names)]
+
+use crate::rand;
 use crate::geometry::vec3;
 use crate::geometry::Vec3;
 use crate::config::{Float, PI};
-use crate::geometry::Ray;
+use crate::geometry::{Ray, RayDifferential};
 
 pub struct Camera
 {
@@ -13,6 +16,11 @@ pub struct Camera
     u: Vec3,
     v: Vec3,
     _w: Vec3,
+    /// The time when the shutter opens.
+    pub shutter_open: Float,
+    /// The time when the shutter closes. Rays are shot at random
+    /// times between `shutter_open` and `shutter_close`.
+    pub shutter_close: Float,
 }
 
 impl Camera
@@ -40,16 +48,47 @@ impl Camera
             origin: look_from,
             lens_radius: aperture * 0.5,
             u: u, v: v, _w: w,
+            shutter_open: 0.0,
+            shutter_close: 0.0,
         }
     }
 
+    /// Return the camera with the shutter open between time `open`
+    /// and `close`. By default the shutter is only open at time 0,
+    /// i.e. no motion blur.
+    pub fn withShutter(mut self, open: Float, close: Float) -> Self
+    {
+        self.shutter_open = open;
+        self.shutter_close = close;
+        self
+    }
+
     pub fn ray(&self, s: Float, t: Float) -> Ray
     {
         let rd = self.lens_radius * Vec3::randInUnitDisk();
         let offset = self.u * rd[0] + self.v * rd[1];
-        Ray {
-            origin: self.origin + offset,
-            dir: self.lower_left + s * self.hori + t * self.vert
-                - self.origin - offset }
+        let time = self.shutter_open + rand::random::<Float>() *
+            (self.shutter_close - self.shutter_open);
+        Ray::new(self.origin + offset,
+                 self.lower_left + s * self.hori + t * self.vert
+                 - self.origin - offset,
+                 time)
+    }
+
+    /// Same as `ray`, but also with the differential rays at `s` +
+    /// `ds` and `t` + `dt`, which go through the same point on the
+    /// lens.
+    pub fn rayWithDifferentials(&self, s: Float, t: Float, ds: Float, dt: Float) ->
+        Ray
+    {
+        let mut r = self.ray(s, t);
+        let film = r.origin + r.dir;
+        r.differentials = Some(RayDifferential {
+            rx_origin: r.origin,
+            rx_dir: film + ds * self.hori - r.origin,
+            ry_origin: r.origin,
+            ry_dir: film + dt * self.vert - r.origin,
+        });
+        r
     }
 }
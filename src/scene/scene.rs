@@ -3,6 +3,8 @@ use std::sync::Arc;
 use super::camera::Camera;
 use crate::geometry::PrimitiveList;
 use crate::material;
+use crate::medium::AnyMedium;
+use crate::light::AnyLight;
 
 pub struct Scene
 {
@@ -11,4 +13,11 @@ pub struct Scene
     pub camera: Camera,
     pub primitives: PrimitiveList,
     pub materials: Vec<Arc<dyn material::Material + Send + Sync>>,
+    /// The lights sampled directly with shadow rays.
+    pub lights: Vec<AnyLight>,
+    /// The medium outside of all objects, e.g. atmospheric fog.
+    pub fog: Option<AnyMedium>,
+    /// Whether to render with sampled wavelengths instead of RGB.
+    /// Needed for dispersion; slower to converge.
+    pub spectral: bool,
 }
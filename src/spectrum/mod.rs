@@ -0,0 +1,5 @@
+//! Spectral rendering: wavelength sampling, RGB to spectrum
+//! upsampling, and conversion back to RGB at the film.
+
+mod wavelengths;
+pub use self::wavelengths::*;
@@ -0,0 +1,192 @@
+use std::sync::OnceLock;
+
+use crate::config::Float;
+use crate::geometry::{Vec3, Color, Mat4};
+
+/// The range of wavelengths that are rendered, in nm.
+pub const LAMBDA_MIN: Float = 360.0;
+pub const LAMBDA_MAX: Float = 830.0;
+
+/// The wavelengths (in nm) carried by a path in spectral mode. The
+/// first one is the hero wavelength; the others are evenly spaced
+/// from it, wrapping around the visible range. In spectral mode, a
+/// `Color` along the path holds the values at these 3 wavelengths
+/// instead of RGB.
+#[derive(Clone, Copy, Debug)]
+pub struct Wavelengths
+{
+    pub lambda: [Float; 3],
+    /// How many of the wavelengths are still carried. Wavelength
+    /// dependent refraction keeps only the hero.
+    pub active: usize,
+}
+
+impl Wavelengths
+{
+    /// Sample the wavelengths from a uniform random number `u`.
+    pub fn sample(u: Float) -> Self
+    {
+        let range = LAMBDA_MAX - LAMBDA_MIN;
+        let hero = u * range;
+        let at = |i: usize| LAMBDA_MIN + (hero + range * i as Float / 3.0) % range;
+        Self { lambda: [at(0), at(1), at(2)], active: 3 }
+    }
+
+    pub fn hero(&self) -> Float
+    {
+        self.lambda[0]
+    }
+
+    /// Keep only the hero wavelength, e.g. after the path is split
+    /// by dispersion.
+    pub fn terminateSecondary(&mut self)
+    {
+        self.active = 1;
+    }
+
+    /// The probability density of each wavelength.
+    pub fn pdf() -> Float
+    {
+        1.0 / (LAMBDA_MAX - LAMBDA_MIN)
+    }
+}
+
+/// A piece of Gaussian with different widths on the two sides.
+fn lobe(lambda: Float, mean: Float, sigma_low: Float, sigma_high: Float) -> Float
+{
+    let t = (lambda - mean) / if lambda < mean { sigma_low } else { sigma_high };
+    (-0.5 * t * t).exp()
+}
+
+/// The CIE 1931 color matching functions at `lambda` (in nm), by the
+/// multi-lobe fit of Wyman, Sloan and Shirley (2013).
+pub fn cieXyz(lambda: Float) -> Vec3
+{
+    let x = 1.056 * lobe(lambda, 599.8, 37.9, 31.0) + 0.362 * lobe(lambda, 442.0, 16.0, 26.7)
+        - 0.065 * lobe(lambda, 501.1, 20.4, 26.2);
+    let y = 0.821 * lobe(lambda, 568.8, 46.9, 40.5) + 0.286 * lobe(lambda, 530.9, 16.3, 31.1);
+    let z = 1.217 * lobe(lambda, 437.0, 11.8, 36.0) + 0.681 * lobe(lambda, 459.0, 26.0, 13.8);
+    Vec3::new(x, y, z)
+}
+
+/// Convert CIE XYZ to linear sRGB.
+pub fn xyzToLinearSrgb(xyz: &Vec3) -> Color
+{
+    Vec3::new(3.2406 * xyz[0] - 1.5372 * xyz[1] - 0.4986 * xyz[2],
+              -0.9689 * xyz[0] + 1.8758 * xyz[1] + 0.0415 * xyz[2],
+              0.0557 * xyz[0] - 0.2040 * xyz[1] + 1.0570 * xyz[2])
+}
+
+fn smoothstep(x: Float, low: Float, high: Float) -> Float
+{
+    let t = ((x - low) / (high - low)).clamp(0.0, 1.0);
+    t * t * (3.0 - 2.0 * t)
+}
+
+/// The smooth spectra that RGB colors are made of, at `lambda`. They
+/// sum to 1 everywhere.
+fn basisSpectra(lambda: Float) -> Vec3
+{
+    let blue = 1.0 - smoothstep(lambda, 470.0, 520.0);
+    let red = smoothstep(lambda, 570.0, 620.0);
+    Vec3::new(red, 1.0 - red - blue, blue)
+}
+
+/// The linear map from RGB to spectra.
+struct Conversion
+{
+    /// Maps RGB to the weights of the basis spectra. It is the
+    /// inverse of the RGB of the basis spectra.
+    to_spectrum: Mat4,
+    /// The RGB of a constant spectrum of 1, before normalization.
+    white: Vec3,
+}
+
+/// Return the RGB of light of wavelength `lambda`, before
+/// normalization.
+fn rawRgb(lambda: Float) -> Color
+{
+    xyzToLinearSrgb(&cieXyz(lambda))
+}
+
+fn conversion() -> &'static Conversion
+{
+    static CONVERSION: OnceLock<Conversion> = OnceLock::new();
+    CONVERSION.get_or_init(|| {
+        let mut white = Color::origin();
+        let mut columns = [Color::origin(); 3];
+        let mut lambda = LAMBDA_MIN + 0.5;
+        while lambda < LAMBDA_MAX
+        {
+            let rgb = rawRgb(lambda);
+            let basis = basisSpectra(lambda);
+            white += rgb;
+            for (j, column) in columns.iter_mut().enumerate()
+            {
+                *column += rgb * basis[j];
+            }
+            lambda += 1.0;
+        }
+        // Normalize so that a constant spectrum is white.
+        let mut to_rgb = Mat4::identity();
+        for (j, column) in columns.iter().enumerate()
+        {
+            for i in 0..3
+            {
+                to_rgb.data[i][j] = column[i] / white[i];
+            }
+        }
+        Conversion {
+            to_spectrum: to_rgb.inverse().expect("Spectral basis is degenerate"),
+            white,
+        }
+    })
+}
+
+/// Return the value at `lambda` of a smooth spectrum that has color
+/// `c`, i.e. converting it back gives `c` exactly. Saturated colors
+/// may need negative values, which are clipped.
+pub fn rgbToSpectrum(c: &Color, lambda: Float) -> Float
+{
+    let weights = conversion().to_spectrum.vector(c);
+    crate::geometry::vec3::dot(&weights, &basisSpectra(lambda)).max(0.0)
+}
+
+/// Return the values of the spectrum of `c` at wavelengths `w`.
+pub fn upsample(c: &Color, w: &Wavelengths) -> Color
+{
+    Vec3::new(rgbToSpectrum(c, w.lambda[0]), rgbToSpectrum(c, w.lambda[1]),
+              rgbToSpectrum(c, w.lambda[2]))
+}
+
+/// Estimate the RGB of a spectrum from its values at wavelengths
+/// `w`.
+pub fn spectrumToRgb(values: &Color, w: &Wavelengths) -> Color
+{
+    let white = conversion().white;
+    let mut rgb = Color::origin();
+    for i in 0..w.active
+    {
+        rgb += values[i] * rawRgb(w.lambda[i]);
+    }
+    rgb / (w.active as Float * Wavelengths::pdf()) / white
+}
+
+
+/// The number of wavelengths `reflectanceToRgb` evaluates at.
+const REFLECTANCE_SAMPLES: usize = 48;
+
+/// Return the RGB of a reflectance spectrum, given as a function of
+/// the wavelength in nm. This is for spectra with features that RGB
+/// cannot follow, e.g. interference, in RGB mode.
+pub fn reflectanceToRgb<F: Fn(Float) -> Float>(reflectance: F) -> Color
+{
+    let step = (LAMBDA_MAX - LAMBDA_MIN) / REFLECTANCE_SAMPLES as Float;
+    let mut rgb = Color::origin();
+    for i in 0..REFLECTANCE_SAMPLES
+    {
+        let lambda = LAMBDA_MIN + (i as Float + 0.5) * step;
+        rgb += reflectance(lambda) * rawRgb(lambda);
+    }
+    rgb * step / conversion().white
+}
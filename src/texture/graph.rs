@@ -0,0 +1,559 @@
+// Textures that compute their color from other textures. Together
+// they form a small shading graph, e.g. a noise through a color ramp,
+// mixed with an image by another noise.
+
+use crate::config::Float;
+use crate::geometry::{Vec3, Color, Hit};
+use super::texture::{Texture, AnyTexture};
+
+/// Return the relative luminance of linear sRGB color `c`.
+pub fn luminance(c: &Color) -> Float
+{
+    0.2126 * c[0] + 0.7152 * c[1] + 0.0722 * c[2]
+}
+
+/// How to turn a color into a scalar.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Channel
+{
+    Red,
+    Green,
+    Blue,
+    /// The average of the 3 channels.
+    Average,
+    Luminance,
+}
+
+impl Channel
+{
+    pub fn of(self, c: &Color) -> Float
+    {
+        match self
+        {
+            Channel::Red => c[0],
+            Channel::Green => c[1],
+            Channel::Blue => c[2],
+            Channel::Average => (c[0] + c[1] + c[2]) / 3.0,
+            Channel::Luminance => luminance(c),
+        }
+    }
+}
+
+/// Interpolate between `a` and `b` by `factor`. This is done per
+/// channel, so a gray factor is a plain lerp.
+pub struct Mix
+{
+    a: AnyTexture,
+    b: AnyTexture,
+    factor: AnyTexture,
+}
+
+impl Mix
+{
+    /// Show `a` where `factor` is 0, and `b` where it is 1.
+    pub fn new(a: AnyTexture, b: AnyTexture, factor: AnyTexture) -> Self
+    {
+        Self { a, b, factor }
+    }
+}
+
+fn lerp(a: Color, b: Color, t: Color) -> Color
+{
+    (Vec3::new(1.0, 1.0, 1.0) - t) * a + t * b
+}
+
+impl Texture for Mix
+{
+    fn value(&self, u: Float, v: Float, p: &Vec3) -> Color
+    {
+        lerp(self.a.value(u, v, p), self.b.value(u, v, p), self.factor.value(u, v, p))
+    }
+
+    fn filtered(&self, hit: &Hit) -> Color
+    {
+        lerp(self.a.filtered(hit), self.b.filtered(hit), self.factor.filtered(hit))
+    }
+}
+
+/// The sum of two textures.
+pub struct Add
+{
+    a: AnyTexture,
+    b: AnyTexture,
+}
+
+impl Add
+{
+    pub fn new(a: AnyTexture, b: AnyTexture) -> Self
+    {
+        Self { a, b }
+    }
+}
+
+impl Texture for Add
+{
+    fn value(&self, u: Float, v: Float, p: &Vec3) -> Color
+    {
+        self.a.value(u, v, p) + self.b.value(u, v, p)
+    }
+
+    fn filtered(&self, hit: &Hit) -> Color
+    {
+        self.a.filtered(hit) + self.b.filtered(hit)
+    }
+}
+
+/// The per channel product of two textures.
+pub struct Multiply
+{
+    a: AnyTexture,
+    b: AnyTexture,
+}
+
+impl Multiply
+{
+    pub fn new(a: AnyTexture, b: AnyTexture) -> Self
+    {
+        Self { a, b }
+    }
+}
+
+impl Texture for Multiply
+{
+    fn value(&self, u: Float, v: Float, p: &Vec3) -> Color
+    {
+        self.a.value(u, v, p) * self.b.value(u, v, p)
+    }
+
+    fn filtered(&self, hit: &Hit) -> Color
+    {
+        self.a.filtered(hit) * self.b.filtered(hit)
+    }
+}
+
+/// 1 minus a texture, per channel.
+pub struct Invert
+{
+    input: AnyTexture,
+}
+
+impl Invert
+{
+    pub fn new(input: AnyTexture) -> Self
+    {
+        Self { input }
+    }
+}
+
+impl Texture for Invert
+{
+    fn value(&self, u: Float, v: Float, p: &Vec3) -> Color
+    {
+        Vec3::new(1.0, 1.0, 1.0) - self.input.value(u, v, p)
+    }
+
+    fn filtered(&self, hit: &Hit) -> Color
+    {
+        Vec3::new(1.0, 1.0, 1.0) - self.input.filtered(hit)
+    }
+}
+
+/// A texture with every channel clamped to [`low`, `high`].
+pub struct Clamp
+{
+    input: AnyTexture,
+    low: Float,
+    high: Float,
+}
+
+impl Clamp
+{
+    pub fn new(input: AnyTexture, low: Float, high: Float) -> Self
+    {
+        Self { input, low, high }
+    }
+
+    fn clamp(&self, c: Color) -> Color
+    {
+        Vec3::new(c[0].clamp(self.low, self.high), c[1].clamp(self.low, self.high),
+                  c[2].clamp(self.low, self.high))
+    }
+}
+
+impl Texture for Clamp
+{
+    fn value(&self, u: Float, v: Float, p: &Vec3) -> Color
+    {
+        self.clamp(self.input.value(u, v, p))
+    }
+
+    fn filtered(&self, hit: &Hit) -> Color
+    {
+        self.clamp(self.input.filtered(hit))
+    }
+}
+
+/// A gray texture from one channel of another texture. Use this to
+/// feed a colored texture as a scalar factor.
+pub struct Grayscale
+{
+    input: AnyTexture,
+    channel: Channel,
+}
+
+impl Grayscale
+{
+    pub fn new(input: AnyTexture, channel: Channel) -> Self
+    {
+        Self { input, channel }
+    }
+}
+
+impl Texture for Grayscale
+{
+    fn value(&self, u: Float, v: Float, p: &Vec3) -> Color
+    {
+        let x = self.channel.of(&self.input.value(u, v, p));
+        Vec3::new(x, x, x)
+    }
+
+    fn filtered(&self, hit: &Hit) -> Color
+    {
+        let x = self.channel.of(&self.input.filtered(hit));
+        Vec3::new(x, x, x)
+    }
+}
+
+/// Map a scalar texture to colors, by linearly interpolating between
+/// a list of color stops.
+pub struct ColorRamp
+{
+    input: AnyTexture,
+    channel: Channel,
+    /// (position, color), sorted by position.
+    stops: Vec<(Float, Color)>,
+}
+
+impl ColorRamp
+{
+    /// Construct a ramp from the luminance of `input`. Outside of the
+    /// stops the ramp is the color of the nearest stop. Panic if
+    /// there is no stop.
+    pub fn new(input: AnyTexture, mut stops: Vec<(Float, Color)>) -> Self
+    {
+        if stops.is_empty()
+        {
+            panic!("Color ramp without stops");
+        }
+        stops.sort_by(|a, b| a.0.total_cmp(&b.0));
+        Self { input, channel: Channel::Luminance, stops }
+    }
+
+    /// Set which channel of `input` to use as the scalar.
+    pub fn withChannel(mut self, channel: Channel) -> Self
+    {
+        self.channel = channel;
+        self
+    }
+
+    fn map(&self, c: &Color) -> Color
+    {
+        let x = self.channel.of(c);
+        let i = self.stops.partition_point(|stop| stop.0 <= x);
+        if i == 0
+        {
+            return self.stops[0].1;
+        }
+        if i == self.stops.len()
+        {
+            return self.stops[i - 1].1;
+        }
+        let (x0, c0) = self.stops[i - 1];
+        let (x1, c1) = self.stops[i];
+        let t = (x - x0) / (x1 - x0);
+        (1.0 - t) * c0 + t * c1
+    }
+}
+
+impl Texture for ColorRamp
+{
+    fn value(&self, u: Float, v: Float, p: &Vec3) -> Color
+    {
+        self.map(&self.input.value(u, v, p))
+    }
+
+    fn filtered(&self, hit: &Hit) -> Color
+    {
+        self.map(&self.input.filtered(hit))
+    }
+}
+
+/// A texture with its surface coordinates scaled, then rotated, then
+/// offset. I.e. the input texture at (u', v') = R · S · (u, v) + o.
+pub struct UVTransform
+{
+    input: AnyTexture,
+    scale: (Float, Float),
+    /// Counterclockwise, in radians.
+    rotation: Float,
+    offset: (Float, Float),
+}
+
+impl UVTransform
+{
+    /// Construct an identity transform.
+    pub fn new(input: AnyTexture) -> Self
+    {
+        Self { input, scale: (1.0, 1.0), rotation: 0.0, offset: (0.0, 0.0) }
+    }
+
+    /// Scale the coordinates. Larger makes the input repeat more.
+    pub fn withScale(mut self, su: Float, sv: Float) -> Self
+    {
+        self.scale = (su, sv);
+        self
+    }
+
+    /// Rotate the coordinates counterclockwise by `angle` (in
+    /// radians).
+    pub fn withRotation(mut self, angle: Float) -> Self
+    {
+        self.rotation = angle;
+        self
+    }
+
+    pub fn withOffset(mut self, du: Float, dv: Float) -> Self
+    {
+        self.offset = (du, dv);
+        self
+    }
+
+    /// Apply the linear part (scale and rotation) to (`u`, `v`).
+    fn apply(&self, u: Float, v: Float) -> (Float, Float)
+    {
+        let (s, c) = self.rotation.sin_cos();
+        let (u, v) = (u * self.scale.0, v * self.scale.1);
+        (c * u - s * v, s * u + c * v)
+    }
+}
+
+impl Texture for UVTransform
+{
+    fn value(&self, u: Float, v: Float, p: &Vec3) -> Color
+    {
+        let (u1, v1) = self.apply(u, v);
+        self.input.value(u1 + self.offset.0, v1 + self.offset.1, p)
+    }
+
+    fn filtered(&self, hit: &Hit) -> Color
+    {
+        let mut h = hit.clone();
+        let (u1, v1) = self.apply(hit.u, hit.v);
+        h.u = u1 + self.offset.0;
+        h.v = v1 + self.offset.1;
+        // The differentials go through the linear part only.
+        let (dudx, dvdx) = self.apply(hit.dudx, hit.dvdx);
+        let (dudy, dvdy) = self.apply(hit.dudy, hit.dvdy);
+        h.dudx = dudx;
+        h.dvdx = dvdx;
+        h.dudy = dudy;
+        h.dvdy = dvdy;
+        self.input.filtered(&h)
+    }
+}
+
+/// Project textures onto the 3 axis planes in world space, and blend
+/// them by the shading normal. This textures surfaces without good
+/// surface coordinates.
+pub struct Triplanar
+{
+    /// The texture on the planes facing x, y, and z.
+    inputs: [AnyTexture; 3],
+    /// World units per unit of surface coordinates.
+    scale: Float,
+    /// Larger makes the transitions between planes narrower.
+    sharpness: Float,
+}
+
+impl Triplanar
+{
+    /// Construct with the same `input` on all 3 planes, repeating
+    /// every world unit.
+    pub fn new(input: AnyTexture) -> Self
+    {
+        Self::perAxis(input.clone(), input.clone(), input)
+    }
+
+    /// Construct with different textures on the planes facing x, y
+    /// and z.
+    pub fn perAxis(x: AnyTexture, y: AnyTexture, z: AnyTexture) -> Self
+    {
+        Self { inputs: [x, y, z], scale: 1.0, sharpness: 4.0 }
+    }
+
+    /// Set the world size of a unit of the projected coordinates.
+    pub fn withScale(mut self, scale: Float) -> Self
+    {
+        self.scale = scale;
+        self
+    }
+
+    pub fn withSharpness(mut self, sharpness: Float) -> Self
+    {
+        self.sharpness = sharpness;
+        self
+    }
+
+    /// Return the (u, v) axes of the plane facing `axis`.
+    fn planeAxes(axis: usize) -> (usize, usize)
+    {
+        match axis
+        {
+            0 => (2, 1),
+            1 => (0, 2),
+            _ => (0, 1),
+        }
+    }
+
+    fn weights(&self, n: &Vec3) -> [Float; 3]
+    {
+        let mut w = [n[0].abs().powf(self.sharpness), n[1].abs().powf(self.sharpness),
+                     n[2].abs().powf(self.sharpness)];
+        let sum = w[0] + w[1] + w[2];
+        for x in w.iter_mut()
+        {
+            *x /= sum;
+        }
+        w
+    }
+}
+
+impl Texture for Triplanar
+{
+    /// Without a normal, the 3 projections are blended equally.
+    fn value(&self, _: Float, _: Float, p: &Vec3) -> Color
+    {
+        let mut c = Color::origin();
+        for (axis, input) in self.inputs.iter().enumerate()
+        {
+            let (a, b) = Self::planeAxes(axis);
+            c += input.value(p[a] / self.scale, p[b] / self.scale, p) / 3.0;
+        }
+        c
+    }
+
+    fn filtered(&self, hit: &Hit) -> Color
+    {
+        let w = self.weights(&hit.shading_normal);
+        let dpdx = hit.dpdu * hit.dudx + hit.dpdv * hit.dvdx;
+        let dpdy = hit.dpdu * hit.dudy + hit.dpdv * hit.dvdy;
+        let mut c = Color::origin();
+        for (axis, input) in self.inputs.iter().enumerate()
+        {
+            if w[axis] < 1e-4
+            {
+                continue;
+            }
+            let (a, b) = Self::planeAxes(axis);
+            let mut h = hit.clone();
+            h.u = hit.p[a] / self.scale;
+            h.v = hit.p[b] / self.scale;
+            h.dudx = dpdx[a] / self.scale;
+            h.dvdx = dpdx[b] / self.scale;
+            h.dudy = dpdy[a] / self.scale;
+            h.dvdy = dpdy[b] / self.scale;
+            c += w[axis] * input.filtered(&h);
+        }
+        c
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use std::sync::Arc;
+    use crate::config::PI;
+    use crate::texture::Constant;
+
+    /// A texture that shows its surface coordinates as red and green.
+    struct ShowUV;
+
+    impl Texture for ShowUV
+    {
+        fn value(&self, u: Float, v: Float, _: &Vec3) -> Color
+        {
+            Color::new(u, v, 0.0)
+        }
+    }
+
+    fn constant(r: Float, g: Float, b: Float) -> AnyTexture
+    {
+        Arc::new(Constant::new(Color::new(r, g, b)))
+    }
+
+    fn at(tex: &dyn Texture) -> Color
+    {
+        tex.value(0.0, 0.0, &Vec3::origin())
+    }
+
+    fn assertNear(a: &Color, b: &Color)
+    {
+        assert!((*a - *b).norm() < 1e-5, "{:?} != {:?}", a, b);
+    }
+
+    #[test]
+    fn arithmeticNodes()
+    {
+        let a = constant(0.2, 0.5, 2.0);
+        let b = constant(0.5, 0.5, 0.5);
+        assertNear(&at(&Add::new(a.clone(), b.clone())), &Color::new(0.7, 1.0, 2.5));
+        assertNear(&at(&Multiply::new(a.clone(), b.clone())), &Color::new(0.1, 0.25, 1.0));
+        assertNear(&at(&Invert::new(a.clone())), &Color::new(0.8, 0.5, -1.0));
+        assertNear(&at(&Clamp::new(a.clone(), 0.3, 1.0)), &Color::new(0.3, 0.5, 1.0));
+        assertNear(&at(&Grayscale::new(a.clone(), Channel::Blue)), &Color::new(2.0, 2.0, 2.0));
+        // The factor mixes per channel.
+        assertNear(&at(&Mix::new(a, b, constant(0.0, 1.0, 0.5))), &Color::new(0.2, 0.5, 1.25));
+        assert!((luminance(&Color::new(1.0, 1.0, 1.0)) - 1.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn colorRampInterpolatesBetweenStops()
+    {
+        let stops = vec![(1.0, Color::new(0.0, 0.0, 1.0)), (0.0, Color::new(1.0, 0.0, 0.0))];
+        let ramp = |x| ColorRamp::new(constant(x, 0.0, 0.0), stops.clone())
+            .withChannel(Channel::Red);
+        assertNear(&at(&ramp(0.25)), &Color::new(0.75, 0.0, 0.25));
+        assertNear(&at(&ramp(-1.0)), &Color::new(1.0, 0.0, 0.0));
+        assertNear(&at(&ramp(3.0)), &Color::new(0.0, 0.0, 1.0));
+        // A NaN stop does not panic.
+        ColorRamp::new(constant(0.0, 0.0, 0.0), vec![(Float::NAN, Color::origin()),
+                                                     (0.0, Color::origin())]);
+    }
+
+    #[test]
+    fn uvTransformScalesRotatesThenOffsets()
+    {
+        let tex = UVTransform::new(Arc::new(ShowUV)).withScale(2.0, 1.0)
+            .withRotation(PI * 0.5).withOffset(0.5, 0.0);
+        // (1, 0) → (2, 0) → (0, 2) → (0.5, 2).
+        assertNear(&tex.value(1.0, 0.0, &Vec3::origin()), &Color::new(0.5, 2.0, 0.0));
+    }
+
+    #[test]
+    fn triplanarProjectsByNormal()
+    {
+        let tex = Triplanar::perAxis(constant(1.0, 0.0, 0.0), Arc::new(ShowUV),
+                                     constant(0.0, 0.0, 1.0)).withScale(2.0);
+        let w = tex.weights(&Vec3::unit(&Vec3::new(1.0, 2.0, 3.0)));
+        assert!((w.iter().sum::<Float>() - 1.0).abs() < 1e-5);
+        assert!(w[2] > w[1] && w[1] > w[0]);
+
+        // Facing up, the y plane is projected from x and z.
+        let r = crate::geometry::Ray::new(Vec3::new(1.0, 1.0, 3.0),
+                                          Vec3::new(0.0, -1.0, 0.0), 0.0);
+        let hit = Hit::new(&r, 1.0, Vec3::new(0.0, 1.0, 0.0), 0.0, 0.0,
+                           Vec3::new(1.0, 0.0, 0.0), Vec3::new(0.0, 0.0, 1.0), 0);
+        assertNear(&tex.filtered(&hit), &Color::new(0.5, 1.5, 0.0));
+    }
+}
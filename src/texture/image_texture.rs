@@ -0,0 +1,677 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use image::{DynamicImage, ImageError, ImageResult};
+use image::error::{DecodingError, ImageFormatHint};
+use image::codecs::hdr::HdrDecoder;
+
+use crate::config::Float;
+use crate::geometry::{Vec3, Color, Hit};
+use super::texture::Texture;
+
+/// How the pixels in an image file are encoded. This only matters
+/// for integer formats. Float formats (HDR) are always linear.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ColorSpace
+{
+    /// Colors, as in most PNG and JPEG files.
+    Srgb,
+    /// Non-color data, e.g. normal maps and roughness maps.
+    Linear,
+}
+
+/// What to do with texture coordinates outside of [0, 1].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WrapMode
+{
+    /// Tile the image.
+    Repeat,
+    /// Extend the edge pixels.
+    Clamp,
+    /// Tile the image, flipping every other tile.
+    Mirror,
+}
+
+/// How to get a color from the pixels around a texture coordinate.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Filter
+{
+    /// The nearest pixel. This is point sampling, which ignores the
+    /// footprint of the ray, and is mostly useful to validate the
+    /// other filters.
+    Nearest,
+    /// Linear interpolation between the 4 nearest pixels, ignoring
+    /// the footprint of the ray.
+    Bilinear,
+    /// Bilinear filtering on the two mipmap levels closest to the
+    /// size of the footprint of the ray, linearly interpolated.
+    /// Isotropic, so grazing angles come out blurry.
+    Trilinear,
+    /// Elliptically weighted average over the footprint of the ray,
+    /// on the mipmap levels that match the minor axis of the
+    /// footprint. Sharpest at grazing angles, but slower.
+    Ewa,
+}
+
+/// EWA filtering clamps the eccentricity of the footprint to this,
+/// to limit the number of pixels in the filter.
+const MAX_ANISOTROPY: Float = 8.0;
+/// Sharpness of the Gaussian in EWA filtering.
+const EWA_ALPHA: Float = 2.0;
+
+/// Convert a sRGB encoded channel in [0, 1] to linear.
+pub fn srgbToLinear(c: Float) -> Float
+{
+    if c <= 0.04045
+    {
+        c / 12.92
+    }
+    else
+    {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+/// One level of the mipmap of an `Image`.
+struct Level
+{
+    data: Vec<Color>,           // x is inner loop, top row first.
+    width: u32,
+    height: u32,
+}
+
+impl Level
+{
+    fn texel(&self, x: i64, y: i64, wrap: WrapMode) -> Color
+    {
+        let x = wrapIndex(x, self.width as i64, wrap);
+        let y = wrapIndex(y, self.height as i64, wrap);
+        self.data[(y * self.width as i64 + x) as usize]
+    }
+
+    /// Return the half-sized level (rounded down). Every new pixel is
+    /// the average of the pixels it covers, which are 2x2 pixels for
+    /// even sizes, and up to 3x3 partially covered pixels for odd
+    /// sizes, so no row or column is dropped.
+    fn downsample(&self) -> Level
+    {
+        let width = std::cmp::max(self.width / 2, 1);
+        let height = std::cmp::max(self.height / 2, 1);
+        let taps_x: Vec<_> = (0..width).map(|x| downsampleTaps(x, self.width, width))
+            .collect();
+        let mut data = Vec::with_capacity((width * height) as usize);
+        for y in 0..height
+        {
+            let taps_y = downsampleTaps(y, self.height, height);
+            for taps in taps_x.iter()
+            {
+                let mut sum = Color::origin();
+                for &(ty, wy) in taps_y.iter()
+                {
+                    for &(tx, wx) in taps.iter()
+                    {
+                        sum += self.texel(tx, ty, WrapMode::Clamp) * (wx * wy);
+                    }
+                }
+                data.push(sum);
+            }
+        }
+        Level { data, width, height }
+    }
+
+    /// Return the pixel at texture coordinate (`u`, `v`).
+    fn nearest(&self, u: Float, v: Float, wrap: WrapMode) -> Color
+    {
+        let x = u * self.width as Float;
+        let y = (1.0 - v) * self.height as Float;
+        self.texel(x.floor() as i64, y.floor() as i64, wrap)
+    }
+
+    /// Bilinear interpolation at texture coordinate (`u`, `v`).
+    fn bilinear(&self, u: Float, v: Float, wrap: WrapMode) -> Color
+    {
+        // Position in pixel units, where pixel centers are at
+        // integers.
+        let x = u * self.width as Float - 0.5;
+        let y = (1.0 - v) * self.height as Float - 0.5;
+        let x0 = x.floor();
+        let y0 = y.floor();
+        let fx = x - x0;
+        let fy = y - y0;
+        let (x0, y0) = (x0 as i64, y0 as i64);
+        (1.0 - fy) * ((1.0 - fx) * self.texel(x0, y0, wrap) +
+                      fx * self.texel(x0 + 1, y0, wrap)) +
+            fy * ((1.0 - fx) * self.texel(x0, y0 + 1, wrap) +
+                  fx * self.texel(x0 + 1, y0 + 1, wrap))
+    }
+
+    /// Gaussian weighted average over the ellipse at texture
+    /// coordinate (`u`, `v`) with axes `axis0` and `axis1` (in
+    /// texture coordinate units).
+    fn ewa(&self, u: Float, v: Float, axis0: (Float, Float), axis1: (Float, Float),
+           wrap: WrapMode) -> Color
+    {
+        let w = self.width as Float;
+        let h = self.height as Float;
+        // Everything in pixel units from here on. y goes down.
+        let s = u * w - 0.5;
+        let t = (1.0 - v) * h - 0.5;
+        let (d0s, d0t) = (axis0.0 * w, -axis0.1 * h);
+        let (d1s, d1t) = (axis1.0 * w, -axis1.1 * h);
+
+        // The implicit ellipse a s² + b s t + c t² = 1, padded by a
+        // pixel so that it never falls between pixels.
+        let mut a = d0t * d0t + d1t * d1t + 1.0;
+        let mut b = -2.0 * (d0s * d0t + d1s * d1t);
+        let mut c = d0s * d0s + d1s * d1s + 1.0;
+        let f_inverse = 1.0 / (a * c - b * b * 0.25);
+        a *= f_inverse;
+        b *= f_inverse;
+        c *= f_inverse;
+
+        // The bbox of the ellipse.
+        let det = -b * b + 4.0 * a * c;
+        let det_inverse = 1.0 / det;
+        let s_extent = 2.0 * det_inverse * (det * c).sqrt();
+        let t_extent = 2.0 * det_inverse * (det * a).sqrt();
+        // This only happens on the smallest level, when the footprint
+        // is larger than the whole image.
+        if s_extent > w || t_extent > h
+        {
+            return self.bilinear(u, v, wrap);
+        }
+        let s0 = (s - s_extent).ceil() as i64;
+        let s1 = (s + s_extent).floor() as i64;
+        let t0 = (t - t_extent).ceil() as i64;
+        let t1 = (t + t_extent).floor() as i64;
+
+        let mut sum = Color::origin();
+        let mut weight_sum: Float = 0.0;
+        let edge = (-EWA_ALPHA).exp();
+        for it in t0..=t1
+        {
+            let tt = it as Float - t;
+            for is in s0..=s1
+            {
+                let ss = is as Float - s;
+                let r2 = a * ss * ss + b * ss * tt + c * tt * tt;
+                if r2 < 1.0
+                {
+                    let weight = (-EWA_ALPHA * r2).exp() - edge;
+                    sum += self.texel(is, it, wrap) * weight;
+                    weight_sum += weight;
+                }
+            }
+        }
+        if weight_sum > 0.0
+        {
+            sum / weight_sum
+        }
+        else
+        {
+            self.bilinear(u, v, wrap)
+        }
+    }
+}
+
+/// An image in linear float RGB with its mipmap, ready to be sampled.
+pub struct Image
+{
+    /// The mipmap levels, from the full image to 1x1.
+    levels: Vec<Level>,
+}
+
+impl Image
+{
+    /// Construct from linear pixels. `data` has `width` × `height`
+    /// pixels, x being the inner loop, top row first.
+    pub fn new(data: Vec<Color>, width: u32, height: u32) -> Self
+    {
+        assert!(width > 0 && height > 0, "Empty {}x{} image", width, height);
+        assert_eq!(data.len(), (width * height) as usize);
+        let mut levels = vec![Level { data, width, height }];
+        loop
+        {
+            let last = &levels[levels.len() - 1];
+            if last.width == 1 && last.height == 1
+            {
+                break;
+            }
+            let next = last.downsample();
+            levels.push(next);
+        }
+        Self { levels }
+    }
+
+    /// Load an image file. Radiance HDR and OpenEXR files are read as
+    /// float data; of an EXR file, only the RGB channels of the first
+    /// layer are used. Other formats supported by the `image` crate
+    /// are read as integers, and converted from `space` to linear.
+    pub fn load(path: &Path, space: ColorSpace) -> ImageResult<Self>
+    {
+        let ext = path.extension().and_then(|e| e.to_str())
+            .map(|e| e.to_ascii_lowercase());
+        match ext.as_deref()
+        {
+            Some("hdr") =>
+            {
+                let decoder = HdrDecoder::new(BufReader::new(File::open(path)?))?;
+                let meta = decoder.metadata();
+                let data = decoder.read_image_hdr()?.into_iter()
+                    .map(|p| Color::new(p[0], p[1], p[2])).collect();
+                Self::decoded(path, data, meta.width, meta.height)
+            },
+            Some("exr") => Self::loadExr(path),
+            _ =>
+            {
+                let decode = |c: Float| -> Float {
+                    match space
+                    {
+                        ColorSpace::Srgb => srgbToLinear(c),
+                        ColorSpace::Linear => c,
+                    }
+                };
+                let img = image::open(path)?;
+                let (width, height, data) = match img
+                {
+                    DynamicImage::ImageLuma16(_) | DynamicImage::ImageLumaA16(_) |
+                    DynamicImage::ImageRgb16(_) | DynamicImage::ImageRgba16(_) =>
+                    {
+                        let img = img.into_rgb16();
+                        let scale = 1.0 / 65535.0;
+                        (img.width(), img.height(), img.pixels().map(
+                            |p| Color::new(decode(p[0] as Float * scale),
+                                           decode(p[1] as Float * scale),
+                                           decode(p[2] as Float * scale)))
+                         .collect())
+                    },
+                    _ =>
+                    {
+                        let img = img.into_rgb8();
+                        let scale = 1.0 / 255.0;
+                        (img.width(), img.height(), img.pixels().map(
+                            |p| Color::new(decode(p[0] as Float * scale),
+                                           decode(p[1] as Float * scale),
+                                           decode(p[2] as Float * scale)))
+                         .collect())
+                    },
+                };
+                Self::decoded(path, data, width, height)
+            },
+        }
+    }
+
+    /// Load the RGB channels of the first layer of an OpenEXR file.
+    fn loadExr(path: &Path) -> ImageResult<Self>
+    {
+        let img = exr::prelude::read_first_rgba_layer_from_file(
+            path,
+            |size, _| (size.width(), vec![Color::origin(); size.area()]),
+            |(width, data), pos, (r, g, b, _): (f32, f32, f32, f32)| {
+                data[pos.y() * *width + pos.x()] = Color::new(r, g, b);
+            }).map_err(|e| match e
+            {
+                exr::error::Error::Io(e) => ImageError::IoError(e),
+                e => ImageError::Decoding(DecodingError::new(
+                    ImageFormatHint::Name("OpenEXR".to_owned()), e)),
+            })?;
+        let size = img.layer_data.size;
+        let (_, data) = img.layer_data.channel_data.pixels;
+        Self::decoded(path, data, size.width() as u32, size.height() as u32)
+    }
+
+    /// Construct from the pixels decoded from the file at `path`,
+    /// which may have none.
+    fn decoded(path: &Path, data: Vec<Color>, width: u32, height: u32) -> ImageResult<Self>
+    {
+        if width == 0 || height == 0
+        {
+            return Err(ImageError::Decoding(DecodingError::new(
+                ImageFormatHint::from(path), "the image has no pixels")));
+        }
+        Ok(Self::new(data, width, height))
+    }
+
+    pub fn width(&self) -> u32
+    {
+        self.levels[0].width
+    }
+
+    pub fn height(&self) -> u32
+    {
+        self.levels[0].height
+    }
+
+    /// Return the pixel at (`x`, `y`), where y = 0 is the top row.
+    /// Coordinates outside of the image are wrapped by `wrap`.
+    pub fn texel(&self, x: i64, y: i64, wrap: WrapMode) -> Color
+    {
+        self.levels[0].texel(x, y, wrap)
+    }
+
+    /// Sample the image at texture coordinate (`u`, `v`), where v = 0
+    /// is the bottom of the image, without a footprint. Trilinear and
+    /// EWA filtering fall back to bilinear.
+    pub fn sample(&self, u: Float, v: Float, wrap: WrapMode, filter: Filter) -> Color
+    {
+        match filter
+        {
+            Filter::Nearest => self.levels[0].nearest(u, v, wrap),
+            _ => self.levels[0].bilinear(u, v, wrap),
+        }
+    }
+
+    /// Sample the image at texture coordinate (`u`, `v`), filtered
+    /// over a footprint given by how (u, v) changes across a pixel
+    /// in x and y.
+    // The footprint is the two screen space derivatives of both
+    // coordinates, which only ever travel together from `Hit`.
+    #[allow(clippy::too_many_arguments)]
+    pub fn sampleFootprint(&self, u: Float, v: Float, dudx: Float, dvdx: Float,
+                           dudy: Float, dvdy: Float, wrap: WrapMode, filter: Filter) ->
+        Color
+    {
+        let w = self.width() as Float;
+        let h = self.height() as Float;
+        match filter
+        {
+            Filter::Nearest | Filter::Bilinear => self.sample(u, v, wrap, filter),
+            Filter::Trilinear =>
+            {
+                // Width of the footprint in pixels of the full image.
+                let width = 2.0 * (dudx.abs() * w).max(dvdx.abs() * h)
+                    .max(dudy.abs() * w).max(dvdy.abs() * h);
+                self.lerpLevels(width, |level| level.bilinear(u, v, wrap))
+            },
+            Filter::Ewa =>
+            {
+                let mut axis0 = (dudx, dvdx);
+                let mut axis1 = (dudy, dvdy);
+                let len2 = |a: (Float, Float)| (a.0 * w).powi(2) + (a.1 * h).powi(2);
+                if len2(axis0) < len2(axis1)
+                {
+                    std::mem::swap(&mut axis0, &mut axis1);
+                }
+                let longer = len2(axis0).sqrt();
+                let mut shorter = len2(axis1).sqrt();
+                if shorter == 0.0
+                {
+                    return self.sample(u, v, wrap, Filter::Bilinear);
+                }
+                // Clamp the eccentricity by widening the minor axis.
+                if shorter * MAX_ANISOTROPY < longer
+                {
+                    let scale = longer / (shorter * MAX_ANISOTROPY);
+                    axis1 = (axis1.0 * scale, axis1.1 * scale);
+                    shorter *= scale;
+                }
+                self.lerpLevels(shorter, |level| level.ewa(u, v, axis0, axis1, wrap))
+            },
+        }
+    }
+
+    /// Pick the two levels where a footprint of `width` pixels (of
+    /// the full image) is about 1 pixel, sample both with `f`, and
+    /// interpolate.
+    fn lerpLevels<F>(&self, width: Float, f: F) -> Color
+        where F: Fn(&Level) -> Color
+    {
+        let max_level = (self.levels.len() - 1) as Float;
+        let level = width.max(1e-8).log2().max(0.0).min(max_level);
+        let level0 = level.floor();
+        let frac = level - level0;
+        let level0 = level0 as usize;
+        if frac == 0.0
+        {
+            f(&self.levels[level0])
+        }
+        else
+        {
+            (1.0 - frac) * f(&self.levels[level0]) + frac * f(&self.levels[level0 + 1])
+        }
+    }
+}
+
+/// Return the pixels of a row of `size` pixels that are covered by
+/// pixel `i` of the row scaled to `new_size` pixels, and the weight
+/// of each by how much of it is covered. The weights sum to 1.
+fn downsampleTaps(i: u32, size: u32, new_size: u32) -> Vec<(i64, Float)>
+{
+    let scale = size as Float / new_size as Float;
+    let start = i as Float * scale;
+    let end = start + scale;
+    let mut taps = Vec::with_capacity(3);
+    let mut x = start.floor() as i64;
+    while (x as Float) < end && x < size as i64
+    {
+        let covered = end.min(x as Float + 1.0) - start.max(x as Float);
+        if covered > 0.0
+        {
+            taps.push((x, covered / scale));
+        }
+        x += 1;
+    }
+    taps
+}
+
+fn wrapIndex(i: i64, size: i64, wrap: WrapMode) -> i64
+{
+    match wrap
+    {
+        WrapMode::Repeat => i.rem_euclid(size),
+        WrapMode::Clamp => i.clamp(0, size - 1),
+        WrapMode::Mirror =>
+        {
+            let period = i.rem_euclid(2 * size);
+            if period < size { period } else { 2 * size - 1 - period }
+        },
+    }
+}
+
+/// A cache of loaded images, so that an image file used by many
+/// textures is only loaded once. It is meant to be shared (e.g. in
+/// an `Arc`) by everything that builds a scene.
+pub struct ImageCache
+{
+    images: Mutex<HashMap<(PathBuf, ColorSpace), Arc<Image>>>,
+}
+
+impl ImageCache
+{
+    pub fn new() -> Self
+    {
+        Self { images: Mutex::new(HashMap::new()) }
+    }
+
+    /// Return the image at `path` decoded from `space`, loading it if
+    /// it is not in the cache yet.
+    pub fn load<P: AsRef<Path>>(&self, path: P, space: ColorSpace) ->
+        ImageResult<Arc<Image>>
+    {
+        let key = (path.as_ref().to_path_buf(), space);
+        if let Some(img) = self.images.lock().unwrap().get(&key)
+        {
+            return Ok(img.clone());
+        }
+        // Do not hold the lock while loading, so that other images
+        // can be loaded in parallel. In the worst case an image is
+        // loaded twice.
+        let img = Arc::new(Image::load(&key.0, space)?);
+        Ok(self.images.lock().unwrap().entry(key).or_insert(img).clone())
+    }
+}
+
+impl Default for ImageCache
+{
+    fn default() -> Self
+    {
+        Self::new()
+    }
+}
+
+/// A texture from an image.
+pub struct ImageTexture
+{
+    image: Arc<Image>,
+    pub wrap: WrapMode,
+    pub filter: Filter,
+}
+
+impl ImageTexture
+{
+    /// Construct with repeat wrapping and trilinear filtering.
+    pub fn new(image: Arc<Image>) -> Self
+    {
+        Self { image, wrap: WrapMode::Repeat, filter: Filter::Trilinear }
+    }
+
+    pub fn withWrap(mut self, wrap: WrapMode) -> Self
+    {
+        self.wrap = wrap;
+        self
+    }
+
+    pub fn withFilter(mut self, filter: Filter) -> Self
+    {
+        self.filter = filter;
+        self
+    }
+}
+
+impl Texture for ImageTexture
+{
+    fn value(&self, u: Float, v: Float, _: &Vec3) -> Color
+    {
+        self.image.sample(u, v, self.wrap, self.filter)
+    }
+
+    fn filtered(&self, hit: &Hit) -> Color
+    {
+        self.image.sampleFootprint(hit.u, hit.v, hit.dudx, hit.dvdx, hit.dudy,
+                                   hit.dvdy, self.wrap, self.filter)
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    fn assertNear(a: &Color, b: &Color)
+    {
+        assert!((*a - *b).norm() < 1e-4, "{:?} != {:?}", a, b);
+    }
+
+    /// A 2x2 image with a different gray in every pixel.
+    fn quad() -> Image
+    {
+        Image::new(vec![Color::new(0.0, 0.0, 0.0), Color::new(1.0, 1.0, 1.0),
+                        Color::new(2.0, 2.0, 2.0), Color::new(3.0, 3.0, 3.0)], 2, 2)
+    }
+
+    #[test]
+    fn wrapModesMapOutsideIndices()
+    {
+        assert_eq!(wrapIndex(-1, 4, WrapMode::Repeat), 3);
+        assert_eq!(wrapIndex(5, 4, WrapMode::Repeat), 1);
+        assert_eq!(wrapIndex(-1, 4, WrapMode::Clamp), 0);
+        assert_eq!(wrapIndex(9, 4, WrapMode::Clamp), 3);
+        assert_eq!(wrapIndex(-1, 4, WrapMode::Mirror), 0);
+        assert_eq!(wrapIndex(5, 4, WrapMode::Mirror), 2);
+    }
+
+    #[test]
+    fn srgbToLinearMatchesKnownValues()
+    {
+        assert_eq!(srgbToLinear(0.0), 0.0);
+        assert!((srgbToLinear(1.0) - 1.0).abs() < 1e-6);
+        assert!((srgbToLinear(0.5) - 0.214).abs() < 1e-3);
+        assert!((srgbToLinear(0.04) - 0.04 / 12.92).abs() < 1e-7);
+    }
+
+    #[test]
+    fn bilinearInterpolatesBetweenPixelCenters()
+    {
+        let img = quad();
+        // v = 1 is the top row.
+        assertNear(&img.sample(0.25, 0.75, WrapMode::Clamp, Filter::Bilinear),
+                   &Color::new(0.0, 0.0, 0.0));
+        assertNear(&img.sample(0.5, 0.5, WrapMode::Clamp, Filter::Bilinear),
+                   &Color::new(1.5, 1.5, 1.5));
+        assertNear(&img.sample(0.9, 0.1, WrapMode::Clamp, Filter::Nearest),
+                   &Color::new(3.0, 3.0, 3.0));
+    }
+
+    #[test]
+    fn downsampleKeepsOddRowsAndColumns()
+    {
+        let row = |values: &[Float]| values.iter().map(|&c| Color::new(c, c, c)).collect();
+        let img = Image::new(row(&[1.0, 2.0, 3.0, 4.0, 5.0]), 5, 1);
+        assert_eq!(img.levels.len(), 3);
+        let half = &img.levels[1];
+        assert_eq!((half.width, half.height), (2, 1));
+        assertNear(&half.data[0], &Color::new(1.8, 1.8, 1.8));
+        assertNear(&half.data[1], &Color::new(4.2, 4.2, 4.2));
+        // The average of the image is kept down to the last level.
+        assertNear(&img.levels[2].data[0], &Color::new(3.0, 3.0, 3.0));
+
+        let img = Image::new(row(&[0.0, 3.0, 6.0, 1.0, 4.0, 7.0, 2.0, 5.0, 8.0]), 3, 3);
+        assertNear(&img.levels[1].data[0], &Color::new(4.0, 4.0, 4.0));
+    }
+
+    #[test]
+    fn wideFootprintAveragesTheImage()
+    {
+        let img = quad();
+        for &filter in [Filter::Trilinear, Filter::Ewa].iter()
+        {
+            assertNear(&img.sampleFootprint(0.3, 0.3, 4.0, 0.0, 0.0, 4.0,
+                                            WrapMode::Repeat, filter),
+                       &Color::new(1.5, 1.5, 1.5));
+        }
+        // Point sampling ignores the footprint.
+        assertNear(&img.sampleFootprint(0.3, 0.3, 4.0, 0.0, 0.0, 4.0,
+                                        WrapMode::Repeat, Filter::Nearest),
+                   &Color::new(2.0, 2.0, 2.0));
+    }
+
+    #[test]
+    fn exrRoundTrip()
+    {
+        let path = std::env::temp_dir().join(
+            format!("lorentz-test-{}.exr", std::process::id()));
+        exr::prelude::write_rgb_file(&path, 3, 2, |x, y| (x as f32, y as f32, 0.5f32))
+            .unwrap();
+
+        let cache = ImageCache::new();
+        let img = cache.load(&path, ColorSpace::Srgb).unwrap();
+        // Float data is not decoded from sRGB.
+        assert_eq!((img.width(), img.height()), (3, 2));
+        assertNear(&img.texel(2, 1, WrapMode::Clamp), &Color::new(2.0, 1.0, 0.5));
+        // The second load comes from the cache.
+        assert!(Arc::ptr_eq(&img, &cache.load(&path, ColorSpace::Srgb).unwrap()));
+        std::fs::remove_file(&path).unwrap();
+        assert!(Image::load(&path, ColorSpace::Srgb).is_err());
+    }
+
+    #[test]
+    fn emptyFilesAreErrors()
+    {
+        let path = std::env::temp_dir().join(
+            format!("lorentz-test-empty-{}.hdr", std::process::id()));
+        std::fs::write(&path, "#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n-Y 0 +X 4\n").unwrap();
+        let result = Image::load(&path, ColorSpace::Linear);
+        std::fs::remove_file(&path).unwrap();
+        assert!(result.is_err());
+    }
+
+    #[test]
+    #[should_panic]
+    fn emptyImagesPanic()
+    {
+        Image::new(Vec::new(), 0, 4);
+    }
+}
@@ -1,2 +1,12 @@
+#[allow(clippy::module_inception)]
 mod texture;
 pub use self::texture::*;
+
+mod image_texture;
+pub use self::image_texture::*;
+
+mod noise;
+pub use self::noise::*;
+
+mod graph;
+pub use self::graph::*;
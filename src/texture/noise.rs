@@ -0,0 +1,531 @@
+use rand::SeedableRng;
+use rand::seq::SliceRandom;
+use rand::rngs::StdRng;
+
+use crate::config::Float;
+use crate::geometry::{Vec3, Color, Hit};
+use super::texture::{Texture, AnyTexture};
+
+/// A permutation table for gradient noise, generated from a seed.
+/// This provides Perlin noise, simplex noise, and their fractal
+/// sums.
+pub struct Perlin
+{
+    perm: [usize; 512],
+}
+
+fn fade(t: Float) -> Float
+{
+    t * t * t * (t * (t * 6.0 - 15.0) + 10.0)
+}
+
+fn lerp(t: Float, a: Float, b: Float) -> Float
+{
+    a + t * (b - a)
+}
+
+/// Dot product of (`x`, `y`, `z`) with one of 12 gradient directions
+/// (the edges of a cube, with 4 repeated) chosen by `hash`.
+fn grad(hash: usize, x: Float, y: Float, z: Float) -> Float
+{
+    let h = hash & 15;
+    let u = if h < 8 { x } else { y };
+    let v = if h < 4 { y } else if h == 12 || h == 14 { x } else { z };
+    (if h & 1 == 0 { u } else { -u }) + (if h & 2 == 0 { v } else { -v })
+}
+
+impl Perlin
+{
+    pub fn new(seed: u64) -> Self
+    {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let mut p: Vec<usize> = (0..256).collect();
+        p.shuffle(&mut rng);
+        let mut perm = [0; 512];
+        for i in 0..512
+        {
+            perm[i] = p[i & 255];
+        }
+        Self { perm }
+    }
+
+    fn hash(&self, x: i64, y: i64, z: i64) -> usize
+    {
+        let p = &self.perm;
+        p[p[p[(x & 255) as usize] + (y & 255) as usize] + (z & 255) as usize]
+    }
+
+    /// Perlin’s improved gradient noise at `p`, roughly in [-1, 1].
+    pub fn noise(&self, p: &Vec3) -> Float
+    {
+        let (xf, yf, zf) = (p[0].floor(), p[1].floor(), p[2].floor());
+        let (x, y, z) = (p[0] - xf, p[1] - yf, p[2] - zf);
+        let (xi, yi, zi) = (xf as i64, yf as i64, zf as i64);
+        let (u, v, w) = (fade(x), fade(y), fade(z));
+        let g = |dx: i64, dy: i64, dz: i64| {
+            grad(self.hash(xi + dx, yi + dy, zi + dz),
+                 x - dx as Float, y - dy as Float, z - dz as Float)
+        };
+        lerp(w, lerp(v, lerp(u, g(0, 0, 0), g(1, 0, 0)),
+                     lerp(u, g(0, 1, 0), g(1, 1, 0))),
+             lerp(v, lerp(u, g(0, 0, 1), g(1, 0, 1)),
+                  lerp(u, g(0, 1, 1), g(1, 1, 1))))
+    }
+
+    /// Simplex noise at `p`, roughly in [-1, 1]. It has fewer
+    /// directional artifacts than `noise`, and is cheaper.
+    pub fn simplex(&self, p: &Vec3) -> Float
+    {
+        const F3: Float = 1.0 / 3.0;
+        const G3: Float = 1.0 / 6.0;
+
+        // Find the simplex cell.
+        let s = (p[0] + p[1] + p[2]) * F3;
+        let i = (p[0] + s).floor();
+        let j = (p[1] + s).floor();
+        let k = (p[2] + s).floor();
+        let t = (i + j + k) * G3;
+        let x0 = p[0] - (i - t);
+        let y0 = p[1] - (j - t);
+        let z0 = p[2] - (k - t);
+
+        // Which of the 6 tetrahedra in the cell we are in.
+        let (o1, o2) = if x0 >= y0
+        {
+            if y0 >= z0 { ((1, 0, 0), (1, 1, 0)) }
+            else if x0 >= z0 { ((1, 0, 0), (1, 0, 1)) }
+            else { ((0, 0, 1), (1, 0, 1)) }
+        }
+        else if y0 < z0 { ((0, 0, 1), (0, 1, 1)) }
+        else if x0 < z0 { ((0, 1, 0), (0, 1, 1)) }
+        else { ((0, 1, 0), (1, 1, 0)) };
+
+        let (i, j, k) = (i as i64, j as i64, k as i64);
+        let corner = |di: i64, dj: i64, dk: i64, scale: Float| -> Float {
+            let x = x0 - di as Float + scale * G3;
+            let y = y0 - dj as Float + scale * G3;
+            let z = z0 - dk as Float + scale * G3;
+            let t = 0.6 - x * x - y * y - z * z;
+            if t < 0.0
+            {
+                0.0
+            }
+            else
+            {
+                let t2 = t * t;
+                t2 * t2 * grad(self.hash(i + di, j + dj, k + dk), x, y, z)
+            }
+        };
+        32.0 * (corner(0, 0, 0, 0.0) + corner(o1.0, o1.1, o1.2, 1.0) +
+                corner(o2.0, o2.1, o2.2, 2.0) + corner(1, 1, 1, 3.0))
+    }
+
+    /// Sum of `octaves` layers of noise `basis`, each with double the
+    /// frequency and half the amplitude of the previous. The result
+    /// is normalized to roughly [-1, 1].
+    pub fn fbm<F>(&self, p: &Vec3, octaves: u32, basis: F) -> Float
+        where F: Fn(&Self, &Vec3) -> Float
+    {
+        let mut sum = 0.0;
+        let mut amplitude = 1.0;
+        let mut total = 0.0;
+        let mut q = *p;
+        for _ in 0..octaves
+        {
+            sum += amplitude * basis(self, &q);
+            total += amplitude;
+            amplitude *= 0.5;
+            q *= 2.0;
+        }
+        if total > 0.0 { sum / total } else { 0.0 }
+    }
+
+    /// Like `fbm`, but summing the absolute values of the layers,
+    /// which gives creases where the noise crosses 0. The result is
+    /// roughly in [0, 1].
+    pub fn turbulence<F>(&self, p: &Vec3, octaves: u32, basis: F) -> Float
+        where F: Fn(&Self, &Vec3) -> Float
+    {
+        self.fbm(p, octaves, |s, q| basis(s, q).abs())
+    }
+}
+
+/// Which gradient noise to use as the basis of the noise textures.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NoiseBasis
+{
+    Perlin,
+    Simplex,
+}
+
+impl NoiseBasis
+{
+    fn eval(self, perlin: &Perlin, p: &Vec3) -> Float
+    {
+        match self
+        {
+            NoiseBasis::Perlin => perlin.noise(p),
+            NoiseBasis::Simplex => perlin.simplex(p),
+        }
+    }
+}
+
+/// The noise shared by all the noise textures, with the frequency
+/// and the number of layers.
+pub struct NoiseParams
+{
+    perlin: Perlin,
+    seed: u64,
+    pub basis: NoiseBasis,
+    /// The frequency of the noise. Larger is finer.
+    pub scale: Float,
+    /// The number of fractal layers.
+    pub octaves: u32,
+}
+
+impl NoiseParams
+{
+    pub fn new(seed: u64) -> Self
+    {
+        Self { perlin: Perlin::new(seed), seed, basis: NoiseBasis::Perlin,
+               scale: 1.0, octaves: 1 }
+    }
+
+    /// Fractal noise at `p` (before scaling), roughly in [-1, 1].
+    pub fn fbm(&self, p: &Vec3) -> Float
+    {
+        let basis = self.basis;
+        self.perlin.fbm(&(*p * self.scale), self.octaves, |s, q| basis.eval(s, q))
+    }
+
+    /// Turbulence at `p` (before scaling), roughly in [0, 1].
+    pub fn turbulence(&self, p: &Vec3) -> Float
+    {
+        let basis = self.basis;
+        self.perlin.turbulence(&(*p * self.scale), self.octaves,
+                               |s, q| basis.eval(s, q))
+    }
+}
+
+/// A pattern computed from noise.
+pub trait NoisePattern
+{
+    /// Return the pattern at `p`, in [0, 1].
+    fn factor(&self, noise: &NoiseParams, p: &Vec3) -> Float;
+}
+
+/// A texture that blends between two textures by a noise pattern,
+/// e.g. `NoiseTexture::new(seed, Marble::new(), white, black)`.
+pub struct NoiseTexture<P>
+{
+    pub params: NoiseParams,
+    pub pattern: P,
+    low: AnyTexture,
+    high: AnyTexture,
+}
+
+impl<P: NoisePattern> NoiseTexture<P>
+{
+    /// Construct with 1 octave of Perlin noise at scale 1. Show `low`
+    /// where the pattern is 0, and `high` where it is 1.
+    pub fn new(seed: u64, pattern: P, low: AnyTexture, high: AnyTexture) -> Self
+    {
+        Self { params: NoiseParams::new(seed), pattern, low, high }
+    }
+
+    /// Set the frequency of the noise. Larger is finer.
+    pub fn withScale(mut self, scale: Float) -> Self
+    {
+        self.params.scale = scale;
+        self
+    }
+
+    /// Set the number of fractal layers. Turbulence based patterns
+    /// usually look best with 5 to 8.
+    pub fn withOctaves(mut self, octaves: u32) -> Self
+    {
+        self.params.octaves = octaves;
+        self
+    }
+
+    /// Set the basis gradient noise.
+    pub fn withBasis(mut self, basis: NoiseBasis) -> Self
+    {
+        self.params.basis = basis;
+        self
+    }
+
+    fn factor(&self, p: &Vec3) -> Float
+    {
+        self.pattern.factor(&self.params, p).clamp(0.0, 1.0)
+    }
+}
+
+impl<P: NoisePattern> Texture for NoiseTexture<P>
+{
+    fn value(&self, u: Float, v: Float, p: &Vec3) -> Color
+    {
+        let t = self.factor(p);
+        (1.0 - t) * self.low.value(u, v, p) + t * self.high.value(u, v, p)
+    }
+
+    fn filtered(&self, hit: &Hit) -> Color
+    {
+        let t = self.factor(&hit.p);
+        (1.0 - t) * self.low.filtered(hit) + t * self.high.filtered(hit)
+    }
+}
+
+/// Plain fractal noise. With 1 octave this is just Perlin (or
+/// simplex) noise.
+pub struct Fbm;
+
+impl NoisePattern for Fbm
+{
+    fn factor(&self, noise: &NoiseParams, p: &Vec3) -> Float
+    {
+        0.5 * (noise.fbm(p) + 1.0)
+    }
+}
+
+/// Fractal turbulence.
+pub struct Turbulence;
+
+impl NoisePattern for Turbulence
+{
+    fn factor(&self, noise: &NoiseParams, p: &Vec3) -> Float
+    {
+        // Turbulence rarely goes above 0.5.
+        2.0 * noise.turbulence(p)
+    }
+}
+
+/// Marble: bands along an axis, distorted by turbulence.
+pub struct Marble
+{
+    /// The direction across the bands. Its length is the frequency
+    /// of the bands.
+    pub axis: Vec3,
+    /// How much the turbulence distorts the bands.
+    pub distortion: Float,
+}
+
+impl Marble
+{
+    pub fn new() -> Self
+    {
+        Self { axis: Vec3::new(0.0, 0.0, 4.0), distortion: 10.0 }
+    }
+}
+
+impl Default for Marble
+{
+    fn default() -> Self
+    {
+        Self::new()
+    }
+}
+
+impl NoisePattern for Marble
+{
+    fn factor(&self, noise: &NoiseParams, p: &Vec3) -> Float
+    {
+        let phase = crate::geometry::vec3::dot(p, &self.axis) +
+            self.distortion * noise.turbulence(p);
+        0.5 * (1.0 + phase.sin())
+    }
+}
+
+/// Wood: concentric rings around the y axis, distorted by noise.
+pub struct Wood
+{
+    /// Number of rings per unit distance from the axis.
+    pub ring_frequency: Float,
+    /// How much the noise distorts the rings, in number of rings.
+    pub distortion: Float,
+}
+
+impl Wood
+{
+    pub fn new() -> Self
+    {
+        Self { ring_frequency: 8.0, distortion: 1.0 }
+    }
+}
+
+impl Default for Wood
+{
+    fn default() -> Self
+    {
+        Self::new()
+    }
+}
+
+impl NoisePattern for Wood
+{
+    fn factor(&self, noise: &NoiseParams, p: &Vec3) -> Float
+    {
+        let radius = (p[0] * p[0] + p[2] * p[2]).sqrt();
+        let rings = radius * self.ring_frequency + self.distortion * noise.fbm(p);
+        // A ramp in every ring, sharper at the end: late wood.
+        (rings - rings.floor()).powi(3)
+    }
+}
+
+/// What to compute from the distances to the nearest feature points
+/// of cellular noise.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Cellular
+{
+    /// Distance to the nearest point: round cells, dark centers.
+    F1,
+    /// Distance to the second nearest point minus the nearest: dark
+    /// cell borders, like cracks or scales.
+    F2MinusF1,
+}
+
+/// A simple integer hash of a cell and a seed.
+fn hashCell(x: i64, y: i64, z: i64, seed: u64, channel: u32) -> u32
+{
+    let mut h = (seed as u32).wrapping_mul(0x9E37_79B9)
+        ^ ((seed >> 32) as u32).wrapping_mul(0x7FEB_352D)
+        ^ (x as u32).wrapping_mul(0x85EB_CA6B)
+        ^ (y as u32).wrapping_mul(0xC2B2_AE35)
+        ^ (z as u32).wrapping_mul(0x27D4_EB2F)
+        ^ channel.wrapping_mul(0x1656_67B1);
+    h ^= h >> 15;
+    h = h.wrapping_mul(0x2C1B_3C6D);
+    h ^= h >> 12;
+    h = h.wrapping_mul(0x297A_2D39);
+    h ^= h >> 15;
+    h
+}
+
+/// Return the distances to the nearest 2 Worley feature points from
+/// `p`. There is one random feature point in every unit cell.
+pub fn worley(p: &Vec3, seed: u64) -> (Float, Float)
+{
+    let cell = (p[0].floor() as i64, p[1].floor() as i64, p[2].floor() as i64);
+    let mut f1 = Float::INFINITY;
+    let mut f2 = Float::INFINITY;
+    for dx in -1..=1
+    {
+        for dy in -1..=1
+        {
+            for dz in -1..=1
+            {
+                let (x, y, z) = (cell.0 + dx, cell.1 + dy, cell.2 + dz);
+                let jitter = |channel| {
+                    hashCell(x, y, z, seed, channel) as Float / u32::MAX as Float
+                };
+                let feature = Vec3::new(x as Float + jitter(0),
+                                        y as Float + jitter(1),
+                                        z as Float + jitter(2));
+                let d = (feature - *p).norm();
+                if d < f1
+                {
+                    f2 = f1;
+                    f1 = d;
+                }
+                else if d < f2
+                {
+                    f2 = d;
+                }
+            }
+        }
+    }
+    (f1, f2)
+}
+
+/// Worley (cellular, Voronoi) noise. It does not use the gradient
+/// noise basis; octaves still add finer layers.
+impl NoisePattern for Cellular
+{
+    fn factor(&self, noise: &NoiseParams, p: &Vec3) -> Float
+    {
+        let mut sum = 0.0;
+        let mut amplitude = 1.0;
+        let mut total = 0.0;
+        let mut q = *p * noise.scale;
+        for octave in 0..noise.octaves
+        {
+            let (f1, f2) = worley(&q, noise.seed.wrapping_add(octave as u64));
+            sum += amplitude * match self
+            {
+                Cellular::F1 => f1,
+                Cellular::F2MinusF1 => f2 - f1,
+            };
+            total += amplitude;
+            amplitude *= 0.5;
+            q *= 2.0;
+        }
+        if total > 0.0 { sum / total } else { 0.0 }
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use std::sync::Arc;
+    use crate::texture::Constant;
+
+    /// Some points spread over a few lattice cells.
+    fn points() -> Vec<Vec3>
+    {
+        (0..200).map(|i| {
+            let t = i as Float;
+            Vec3::new((t * 0.37).sin() * 3.1, t * 0.051 - 4.0, (t * 0.13).cos() * 2.7)
+        }).collect()
+    }
+
+    #[test]
+    fn gradientNoiseIsZeroOnLatticeAndBounded()
+    {
+        let perlin = Perlin::new(7);
+        assert_eq!(perlin.noise(&Vec3::new(3.0, -2.0, 5.0)), 0.0);
+        for p in points()
+        {
+            assert!(perlin.noise(&p).abs() <= 1.0);
+            assert!(perlin.simplex(&p).abs() <= 1.0);
+            // One octave of fbm is the basis itself.
+            assert!((perlin.fbm(&p, 1, Perlin::noise) - perlin.noise(&p)).abs() < 1e-6);
+            assert!(perlin.turbulence(&p, 4, Perlin::simplex) >= 0.0);
+        }
+    }
+
+    #[test]
+    fn noiseDependsOnSeed()
+    {
+        let p = Vec3::new(0.3, 0.7, 0.1);
+        assert_eq!(Perlin::new(1).noise(&p), Perlin::new(1).noise(&p));
+        assert_ne!(Perlin::new(1).noise(&p), Perlin::new(2).noise(&p));
+        assert_eq!(worley(&p, 3), worley(&p, 3));
+        assert_ne!(worley(&p, 3), worley(&p, 4));
+    }
+
+    #[test]
+    fn worleyDistancesAreOrdered()
+    {
+        for p in points()
+        {
+            let (f1, f2) = worley(&p, 11);
+            assert!(f1 <= f2);
+            // There is a feature point in every cell.
+            assert!(f1 <= (3.0 as Float).sqrt());
+        }
+    }
+
+    #[test]
+    fn noiseTextureBlendsWithinInputs()
+    {
+        let low: AnyTexture = Arc::new(Constant::new(Color::new(0.0, 0.0, 1.0)));
+        let high: AnyTexture = Arc::new(Constant::new(Color::new(1.0, 0.0, 0.0)));
+        let tex = NoiseTexture::new(5, Turbulence, low, high).withScale(3.0).withOctaves(5);
+        for p in points()
+        {
+            let c = tex.value(0.0, 0.0, &p);
+            assert!(c[0] >= 0.0 && c[2] >= 0.0 && (c[0] + c[2] - 1.0).abs() < 1e-5);
+        }
+    }
+}
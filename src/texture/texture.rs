@@ -1,11 +1,24 @@
+#![allow(clippy::redundant_field_names)]
+
 use std::sync::Arc;
 
 use crate::config::Float;
-use crate::geometry::{Vec3, Color};
+use crate::geometry::{Vec3, Color, Hit};
 
 pub trait Texture
 {
+    /// Return the color at surface coordinate (`u`, `v`) and
+    /// position `p`.
     fn value(&self, u: Float, v: Float, p: &Vec3) -> Color;
+
+    /// Return the color at `hit`, averaged over the footprint of the
+    /// ray there if the texture can do that. Textures that refer to
+    /// other textures should pass the hit on, so that the footprint
+    /// is not lost.
+    fn filtered(&self, hit: &Hit) -> Color
+    {
+        self.value(hit.u, hit.v, &hit.p)
+    }
 }
 
 pub type AnyTexture = Arc<dyn Texture + Sync + Send>;
@@ -21,6 +34,12 @@ impl Constant
     {
         Self { color: c }
     }
+
+    /// Construct a gray texture, for scalar parameters.
+    pub fn scalar(x: Float) -> Self
+    {
+        Self { color: Vec3::new(x, x, x) }
+    }
 }
 
 impl Texture for Constant
@@ -43,21 +62,32 @@ impl Checker
     {
         Self { even: even, odd: odd }
     }
-}
 
-impl Texture for Checker
-{
-    fn value(&self, u: Float, v: Float, p: &Vec3) -> Color
+    /// Return the texture in effect at `p`.
+    fn pick(&self, p: &Vec3) -> &AnyTexture
     {
         let sines: Float = (10.0 * p[0]).sin() * (10.0 * p[1]).sin() *
             (10.0 * p[2]).sin();
         if sines < 0.0
         {
-            self.odd.value(u, v, p)
+            &self.odd
         }
         else
         {
-            self.even.value(u, v, p)
+            &self.even
         }
     }
 }
+
+impl Texture for Checker
+{
+    fn value(&self, u: Float, v: Float, p: &Vec3) -> Color
+    {
+        self.pick(p).value(u, v, p)
+    }
+
+    fn filtered(&self, hit: &Hit) -> Color
+    {
+        self.pick(&hit.p).filtered(hit)
+    }
+}